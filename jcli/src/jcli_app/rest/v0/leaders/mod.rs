@@ -39,6 +39,15 @@ pub enum Leaders {
 
     /// Leadership log operations
     Logs(GetLogs),
+    /// Get the slots of the current epoch every leader is elected for
+    Schedule {
+        #[structopt(flatten)]
+        addr: HostAddr,
+        #[structopt(flatten)]
+        debug: DebugFlag,
+        #[structopt(flatten)]
+        output_format: OutputFormat,
+    },
 }
 
 #[derive(StructOpt)]
@@ -70,6 +79,11 @@ impl Leaders {
                 debug,
                 output_format,
             }) => get_logs(addr, debug, output_format),
+            Leaders::Schedule {
+                addr,
+                debug,
+                output_format,
+            } => get_schedule(addr, debug, output_format),
         }
     }
 }
@@ -118,3 +132,20 @@ fn get_logs(addr: HostAddr, debug: DebugFlag, output_format: OutputFormat) -> Re
     println!("{}", formatted);
     Ok(())
 }
+
+fn get_schedule(
+    addr: HostAddr,
+    debug: DebugFlag,
+    output_format: OutputFormat,
+) -> Result<(), Error> {
+    let url = addr
+        .with_segments(&["v0", "leaders", "schedule"])?
+        .into_url();
+    let builder = reqwest::Client::new().get(url);
+    let response = RestApiSender::new(builder, &debug).send()?;
+    response.ok_response()?;
+    let schedule = response.body().json_value()?;
+    let formatted = output_format.format_json(schedule)?;
+    println!("{}", formatted);
+    Ok(())
+}
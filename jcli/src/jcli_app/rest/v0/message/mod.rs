@@ -8,17 +8,19 @@ use std::path::PathBuf;
 use structopt::StructOpt;
 extern crate bytes;
 use self::bytes::IntoBuf;
-use chain_core::property::Fragment as fragment_property;
 
 #[derive(StructOpt)]
 #[structopt(rename_all = "kebab-case")]
 pub enum Message {
-    /// Post message. Prints id for posted message
+    /// Post message. Prints id for posted message and whether it was
+    /// accepted, already known (duplicate) or rejected by the node
     Post {
         #[structopt(flatten)]
         addr: HostAddr,
         #[structopt(flatten)]
         debug: DebugFlag,
+        #[structopt(flatten)]
+        output_format: OutputFormat,
         /// File containing hex-encoded message.
         /// If not provided, message will be read from stdin.
         #[structopt(short, long)]
@@ -27,7 +29,8 @@ pub enum Message {
 
     /// get the node's logs on the message pool. This will provide information
     /// on pending transaction, rejected transaction and or when a transaction
-    /// has been added in a block
+    /// has been added in a block, and on where the transaction was received
+    /// from (REST or a peer node, with its node id and address)
     Logs {
         #[structopt(flatten)]
         addr: HostAddr,
@@ -36,17 +39,42 @@ pub enum Message {
         #[structopt(flatten)]
         output_format: OutputFormat,
     },
+
+    /// get the status of the given fragments in the node's message pool logs.
+    /// The fragments unknown to the node are not in the output
+    Status {
+        #[structopt(flatten)]
+        addr: HostAddr,
+        #[structopt(flatten)]
+        debug: DebugFlag,
+        #[structopt(flatten)]
+        output_format: OutputFormat,
+        /// hex-encoded IDs of the fragments
+        #[structopt(required = true)]
+        fragment_ids: Vec<String>,
+    },
 }
 
 impl Message {
     pub fn exec(self) -> Result<(), Error> {
         match self {
-            Message::Post { addr, debug, file } => post_message(file, addr, debug),
+            Message::Post {
+                addr,
+                debug,
+                output_format,
+                file,
+            } => post_message(file, addr, debug, output_format),
             Message::Logs {
                 addr,
                 debug,
                 output_format,
             } => get_logs(addr, debug, output_format),
+            Message::Status {
+                addr,
+                debug,
+                output_format,
+                fragment_ids,
+            } => get_statuses(addr, debug, output_format, fragment_ids),
         }
     }
 }
@@ -62,12 +90,37 @@ fn get_logs(addr: HostAddr, debug: DebugFlag, output_format: OutputFormat) -> Re
     Ok(())
 }
 
-fn post_message(file: Option<PathBuf>, addr: HostAddr, debug: DebugFlag) -> Result<(), Error> {
+fn get_statuses(
+    addr: HostAddr,
+    debug: DebugFlag,
+    output_format: OutputFormat,
+    fragment_ids: Vec<String>,
+) -> Result<(), Error> {
+    let url = addr
+        .with_segments(&["v0", "fragment", "statuses"])?
+        .into_url();
+    let builder = reqwest::Client::new().post(url);
+    let response = RestApiSender::new(builder, &debug)
+        .with_json_body(&fragment_ids)?
+        .send()?;
+    response.ok_response()?;
+    let statuses = response.body().json_value()?;
+    let formatted = output_format.format_json(statuses)?;
+    println!("{}", formatted);
+    Ok(())
+}
+
+fn post_message(
+    file: Option<PathBuf>,
+    addr: HostAddr,
+    debug: DebugFlag,
+    output_format: OutputFormat,
+) -> Result<(), Error> {
     let msg_hex = io::read_line(&file)?;
     let msg_bin = hex::decode(&msg_hex)?;
     let url = addr.with_segments(&["v0", "message"])?.into_url();
     let builder = reqwest::Client::new().post(url);
-    let fragment = Fragment::deserialize(msg_bin.as_slice().into_buf()).map_err(|e| {
+    Fragment::deserialize(msg_bin.as_slice().into_buf()).map_err(|e| {
         Error::InputFragmentMalformed {
             source: e,
             filler: CustomErrorFiller,
@@ -76,7 +129,16 @@ fn post_message(file: Option<PathBuf>, addr: HostAddr, debug: DebugFlag) -> Resu
     let response = RestApiSender::new(builder, &debug)
         .with_binary_body(msg_bin)
         .send()?;
+    if response.response().status() == reqwest::StatusCode::BAD_REQUEST {
+        // the body of a rejected fragment still tells the reason
+        if let Ok(admission) = response.body().json_value() {
+            let formatted = output_format.format_json(admission)?;
+            println!("{}", formatted);
+        }
+    }
     response.ok_response()?;
-    println!("{}", fragment.id());
+    let admission = response.body().json_value()?;
+    let formatted = output_format.format_json(admission)?;
+    println!("{}", formatted);
     Ok(())
 }
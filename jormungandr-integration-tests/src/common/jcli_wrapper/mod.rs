@@ -2,7 +2,8 @@
 
 use jormungandr_lib::crypto::hash::Hash;
 use jormungandr_lib::interfaces::{
-    AccountState, FragmentLog, FragmentStatus, SettingsDto, UTxOInfo,
+    AccountState, FragmentAdmission, FragmentAdmissionStatus, FragmentLog, FragmentStatus,
+    SettingsDto, UTxOInfo,
 };
 
 pub mod certificate;
@@ -137,9 +138,32 @@ pub fn assert_post_transaction(transactions_message: &str, host: &str) -> Hash {
     let output = process_utils::run_process_and_get_output(
         jcli_commands::get_post_transaction_command(&transactions_message, &host),
     );
-    let hash = output.as_hash();
+    let content = output.as_lossy_string();
     process_assert::assert_process_exited_successfully(output);
-    hash
+    let admission: FragmentAdmission =
+        serde_yaml::from_str(&content).expect("Cannot parse message post output");
+    admission.fragment_id().clone()
+}
+
+/// post a transaction expected to be rejected on admission: the node answers
+/// with an error status, so jcli fails after printing the admission
+pub fn assert_post_transaction_rejected(
+    transactions_message: &str,
+    host: &str,
+) -> FragmentAdmission {
+    let output = process_utils::run_process_and_get_output(
+        jcli_commands::get_post_transaction_command(&transactions_message, &host),
+    );
+    let content = output.as_lossy_string();
+    process_assert::assert_process_failed(output);
+    let admission: FragmentAdmission =
+        serde_yaml::from_str(&content).expect("Cannot parse message post output");
+    assert!(
+        !admission.is_accepted(),
+        "Transaction should be rejected, actual: {:?}",
+        &admission
+    );
+    admission
 }
 
 pub fn assert_transaction_post_accepted(transactions_message: &str, host: &str) -> () {
@@ -163,7 +187,7 @@ pub fn assert_transaction_post_failed(transactions_message: &str, host: &str) ->
     let node_stats = assert_rest_stats(&host);
     let before: i32 = node_stats.get("txRecvCnt").unwrap().parse().unwrap();
 
-    assert_post_transaction(&transactions_message, &host);
+    assert_post_transaction_rejected(&transactions_message, &host);
     let node_stats = assert_rest_stats(&host);
     let after: i32 = node_stats.get("txRecvCnt").unwrap().parse().unwrap();
     assert_eq!(
@@ -295,9 +319,16 @@ pub fn assert_transaction_in_block(transaction_message: &str, host: &str) -> Has
 }
 
 pub fn assert_transaction_rejected(transaction_message: &str, host: &str, expected_reason: &str) {
-    let fragment_id = assert_post_transaction(&transaction_message, &host);
-    wait_until_transaction_processed(fragment_id, &host);
-    assert_transaction_log_shows_rejected(fragment_id, &host, &expected_reason);
+    let admission = assert_post_transaction_rejected(&transaction_message, &host);
+    match admission.status() {
+        FragmentAdmissionStatus::Rejected { reason } => assert!(
+            reason.contains(&expected_reason),
+            "Unexpected rejection reason: {}",
+            reason
+        ),
+        status => panic!("Non expected admission status: {:?}", status),
+    }
+    assert_transaction_log_shows_rejected(admission.fragment_id().clone(), &host, &expected_reason);
 }
 
 pub fn wait_until_transaction_processed(fragment_id: Hash, host: &str) {
@@ -0,0 +1,33 @@
+use crate::time::SystemTime;
+use serde::{Deserialize, Serialize};
+
+/// a peer node banned for having sent invalid data
+/// (blocks or fragments) to the node
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BannedPeer {
+    node_id: String,
+    banned_until: SystemTime,
+}
+
+impl BannedPeer {
+    /// create a new BannedPeer with the given values
+    #[inline]
+    pub fn new(node_id: String, banned_until: SystemTime) -> Self {
+        BannedPeer {
+            node_id,
+            banned_until,
+        }
+    }
+
+    /// the identifier of the peer node, as displayed in the node's logs
+    #[inline]
+    pub fn node_id(&self) -> &str {
+        &self.node_id
+    }
+
+    /// the time at which the ban expires
+    #[inline]
+    pub fn banned_until(&self) -> &SystemTime {
+        &self.banned_until
+    }
+}
@@ -0,0 +1,70 @@
+use crate::crypto::hash::Hash;
+use chain_impl_mockchain::key;
+use serde::{Deserialize, Serialize};
+
+/// outcome of the submission of a fragment to the node's mempool
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum FragmentAdmissionStatus {
+    /// the fragment entered the mempool and will be propagated to the network
+    Accepted,
+    /// the fragment is already known to the node, it is not processed again
+    Duplicate,
+    /// the fragment has been rejected and won't be added in a block
+    Rejected { reason: String },
+}
+
+/// the answer of the node to the submission of a fragment
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FragmentAdmission {
+    fragment_id: Hash,
+    status: FragmentAdmissionStatus,
+}
+
+impl FragmentAdmission {
+    /// create a new FragmentAdmission with the given values
+    #[inline]
+    pub fn new(fragment_id: key::Hash, status: FragmentAdmissionStatus) -> Self {
+        FragmentAdmission {
+            fragment_id: fragment_id.into(),
+            status,
+        }
+    }
+
+    #[inline]
+    pub fn fragment_id(&self) -> &Hash {
+        &self.fragment_id
+    }
+
+    #[inline]
+    pub fn status(&self) -> &FragmentAdmissionStatus {
+        &self.status
+    }
+
+    #[inline]
+    pub fn is_accepted(&self) -> bool {
+        self.status == FragmentAdmissionStatus::Accepted
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn serde_rejected_encode_decode() {
+        let admission = FragmentAdmission {
+            fragment_id: Hash::from_hex(
+                "a50a80e0ce6cb8e19d4381dc2a521c1d3ab8a532029131e440548625b2a4d3e8",
+            )
+            .unwrap(),
+            status: FragmentAdmissionStatus::Rejected {
+                reason: "transaction has 1 inputs but 0 witnesses".to_owned(),
+            },
+        };
+
+        let encoded = serde_yaml::to_string(&admission).unwrap();
+        let decoded: FragmentAdmission = serde_yaml::from_str(&encoded).unwrap();
+
+        assert_eq!(decoded, admission)
+    }
+}
@@ -1,18 +1,23 @@
 use crate::{crypto::hash::Hash, interfaces::BlockDate, time::SystemTime};
 use chain_impl_mockchain::key;
 use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
 
 /// identify the source of a fragment
-#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum FragmentOrigin {
     /// trace back the origin of a fragment to a given
     /// network node. This will allow tracking back the
     /// origins of the fragment and eventually blacklisting
     /// the senders from sending us more fragment (in case
     /// they are invalids or so)
-    ///
-    /// TODO: add the network identifier/IP Address
-    Network,
+    Network {
+        /// the identifier of the peer node the fragment was received from,
+        /// as displayed in the node's logs
+        node_id: String,
+        /// the socket address of the peer, if it is known
+        address: Option<SocketAddr>,
+    },
     /// This marks the fragment is coming from the REST interface
     /// (a client wallet or another service).
     Rest,
@@ -107,3 +112,21 @@ impl FragmentLog {
         &self.status
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn serde_network_origin_encode_decode() {
+        let origin = FragmentOrigin::Network {
+            node_id: "61876417513299148541939924962613870822".to_owned(),
+            address: Some("127.0.0.1:8299".parse().unwrap()),
+        };
+
+        let encoded = serde_yaml::to_string(&origin).unwrap();
+        let decoded: FragmentOrigin = serde_yaml::from_str(&encoded).unwrap();
+
+        assert_eq!(decoded, origin)
+    }
+}
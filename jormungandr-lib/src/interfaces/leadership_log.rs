@@ -1,4 +1,4 @@
-use crate::{interfaces::BlockDate, time::SystemTime};
+use crate::{crypto::hash::Hash, interfaces::BlockDate, time::SystemTime};
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
@@ -11,6 +11,20 @@ pub struct EnclaveLeaderId(u32);
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct LeadershipLogId(EnclaveLeaderId, BlockDate);
 
+/// outcome of the event in the leadership schedule
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LeadershipLogStatus {
+    /// the block is yet to be created or processed by the blockchain
+    Pending,
+    /// the block has been created and added to the blockchain
+    Block { block: Hash, fragments: usize },
+    /// the block could not be created or was rejected by the blockchain
+    Rejected { reason: String },
+    /// the block has been added to the blockchain but is no longer part
+    /// of the main branch, a competing fork has been selected instead
+    Orphaned { block: Hash },
+}
+
 /// provides information regarding events in the leadership schedule
 ///
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -21,6 +35,7 @@ pub struct LeadershipLog {
     wake_at_time: Option<SystemTime>,
     finished_at_time: Option<SystemTime>,
     enclave_leader_id: EnclaveLeaderId,
+    status: LeadershipLogStatus,
 }
 
 impl EnclaveLeaderId {
@@ -46,6 +61,7 @@ impl LeadershipLog {
             wake_at_time: None,
             finished_at_time: None,
             enclave_leader_id,
+            status: LeadershipLogStatus::Pending,
         }
     }
 
@@ -72,6 +88,14 @@ impl LeadershipLog {
     pub fn enclave_leader_id(&self) -> &EnclaveLeaderId {
         &self.enclave_leader_id
     }
+    pub fn status(&self) -> &LeadershipLogStatus {
+        &self.status
+    }
+
+    /// set the outcome of the leadership event
+    pub fn set_status(&mut self, status: LeadershipLogStatus) {
+        self.status = status
+    }
 
     /// make a leadership event as triggered.
     ///
@@ -0,0 +1,52 @@
+use crate::{
+    interfaces::{BlockDate, EnclaveLeaderId},
+    time::SystemTime,
+};
+use serde::{Deserialize, Serialize};
+
+/// the slots an enclave leader has been elected for in an epoch
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LeadershipSchedule {
+    enclave_leader_id: EnclaveLeaderId,
+    slots: Vec<LeadershipScheduleSlot>,
+}
+
+/// a slot the leader has been elected for
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LeadershipScheduleSlot {
+    scheduled_at_date: BlockDate,
+    scheduled_at_time: SystemTime,
+}
+
+impl LeadershipSchedule {
+    pub fn new(enclave_leader_id: EnclaveLeaderId) -> Self {
+        LeadershipSchedule {
+            enclave_leader_id,
+            slots: Vec::new(),
+        }
+    }
+
+    /// add a slot the leader has been elected for
+    pub fn push(&mut self, scheduled_at_date: BlockDate, scheduled_at_time: SystemTime) {
+        self.slots.push(LeadershipScheduleSlot {
+            scheduled_at_date,
+            scheduled_at_time,
+        })
+    }
+
+    pub fn enclave_leader_id(&self) -> &EnclaveLeaderId {
+        &self.enclave_leader_id
+    }
+    pub fn slots(&self) -> &[LeadershipScheduleSlot] {
+        &self.slots
+    }
+}
+
+impl LeadershipScheduleSlot {
+    pub fn scheduled_at_date(&self) -> &BlockDate {
+        &self.scheduled_at_date
+    }
+    pub fn scheduled_at_time(&self) -> &SystemTime {
+        &self.scheduled_at_time
+    }
+}
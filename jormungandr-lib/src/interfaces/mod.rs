@@ -1,10 +1,13 @@
 mod account_state;
 mod address;
+mod banned_peer;
 mod block0_configuration;
 mod blockdate;
 mod certificate;
+mod fragment_admission;
 mod fragment_log;
 mod leadership_log;
+mod leadership_schedule;
 mod linear_fee;
 mod old_address;
 mod settings;
@@ -15,13 +18,18 @@ mod value;
 
 pub use self::account_state::AccountState;
 pub use self::address::Address;
+pub use self::banned_peer::BannedPeer;
 pub use self::block0_configuration::*;
 pub use self::blockdate::BlockDate;
 pub use self::certificate::{
     Certificate, CertificateFromBech32Error, CertificateFromStrError, CertificateToBech32Error,
 };
+pub use self::fragment_admission::{FragmentAdmission, FragmentAdmissionStatus};
 pub use self::fragment_log::{FragmentLog, FragmentOrigin, FragmentStatus};
-pub use self::leadership_log::{EnclaveLeaderId, LeadershipLog, LeadershipLogId};
+pub use self::leadership_log::{
+    EnclaveLeaderId, LeadershipLog, LeadershipLogId, LeadershipLogStatus,
+};
+pub use self::leadership_schedule::{LeadershipSchedule, LeadershipScheduleSlot};
 pub use self::linear_fee::LinearFeeDef;
 pub use self::old_address::OldAddress;
 pub use self::settings::*;
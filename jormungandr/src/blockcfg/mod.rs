@@ -15,6 +15,11 @@ pub use chain_impl_mockchain::{
 };
 use std::time::{Duration, SystemTime};
 
+/// upper bound of the size of a serialised block, checked before
+/// allocating the buffer of a block read from a file. It leaves room
+/// for a block0 carrying a large initial state.
+pub const BLOCK_MAX_SIZE: usize = 64 * 1024 * 1024;
+
 custom_error! {pub Block0Error
     CannotParseEntity{source: config::Error} = "Block0 Initial settings: {source}",
     Malformed{source: Block0Malformed} = "Block0 is invalid or malformed: {source}"
@@ -0,0 +1,314 @@
+use super::{chain::pre_check_header_against_parent, Error, ErrorKind};
+use crate::blockcfg::{Block, Header, HeaderHash};
+use chain_core::property::Block as _;
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    time::{Duration, Instant},
+};
+use tokio::{prelude::*, sync::lock::Lock};
+
+// How long a header stays a candidate, waiting for its block. The
+// candidates of a chain that could not be fetched (e.g. the peers sending
+// it disconnected) are dropped afterward, they can be received again.
+//
+// This may need to be made into a configuration parameter.
+const CANDIDATE_TTL: Duration = Duration::from_secs(1800);
+
+/// chains of headers received from the network, pre-validated and
+/// waiting for their blocks to be fetched and applied.
+///
+/// The headers are stored without their blocks so the chain can be
+/// pre-validated first and the blocks fetched afterward in arbitrary
+/// order. The blocks received before their parent has been applied are
+/// held here until the parent is applied.
+///
+/// The candidates expire after a while, see [`purge`].
+///
+/// [`purge`]: #method.purge
+#[derive(Clone)]
+pub struct CandidateForest {
+    inner: Lock<CandidateForestData>,
+}
+
+struct CandidateForestData {
+    /// the pre-validated headers of which the block is not applied yet,
+    /// with the time they expire
+    headers: HashMap<HeaderHash, (Header, Instant)>,
+    /// the blocks received before their parent has been applied
+    blocks: HashMap<HeaderHash, Block>,
+    /// the hashes of the candidate headers, by the hash of their parent
+    children: HashMap<HeaderHash, Vec<HeaderHash>>,
+}
+
+impl CandidateForest {
+    pub fn new() -> Self {
+        CandidateForest {
+            inner: Lock::new(CandidateForestData::new()),
+        }
+    }
+
+    /// add a header of which the parent has already been applied and checked
+    /// against.
+    ///
+    /// The future returns `None` if the header is already a candidate.
+    pub fn add_connected_header(
+        &self,
+        header: Header,
+    ) -> impl Future<Item = Option<HeaderHash>, Error = Infallible> {
+        let mut inner = self.inner.clone();
+        future::poll_fn(move || Ok(inner.poll_lock())).map(move |mut guard| guard.add(header))
+    }
+
+    /// add a header of which the parent is expected to be a candidate header,
+    /// after checking it against the parent.
+    ///
+    /// The future returns `None` if the header is already a candidate.
+    ///
+    /// # Errors
+    ///
+    /// The resulted future may fail if
+    ///
+    /// * the parent is not a candidate header: `ErrorKind::MissingParentBlockFromStorage`;
+    /// * the header is not valid against its parent header.
+    ///
+    pub fn add_unconnected_header(
+        &self,
+        header: Header,
+    ) -> impl Future<Item = Option<HeaderHash>, Error = Error> {
+        let mut inner = self.inner.clone();
+        future::poll_fn(move || Ok(inner.poll_lock()))
+            .map_err(|_: Infallible| unreachable!())
+            .and_then(move |mut guard| {
+                match guard.headers.get(header.block_parent_hash()) {
+                    None => return Err(ErrorKind::MissingParentBlockFromStorage(header).into()),
+                    Some((parent, _)) => pre_check_header_against_parent(&header, parent)?,
+                }
+                Ok(guard.add(header))
+            })
+    }
+
+    /// hold the block until its parent is applied.
+    ///
+    /// The future returns `false` if the block is not a candidate, in which
+    /// case the block is not held.
+    pub fn hold_block(&self, block: Block) -> impl Future<Item = bool, Error = Infallible> {
+        let mut inner = self.inner.clone();
+        future::poll_fn(move || Ok(inner.poll_lock())).map(move |mut guard| {
+            let block_id = block.id();
+            if guard.headers.contains_key(&block_id) {
+                guard.blocks.insert(block_id, block);
+                true
+            } else {
+                false
+            }
+        })
+    }
+
+    /// notify the block has been applied: it is no longer a candidate.
+    ///
+    /// The future returns the blocks of the candidate children that were held
+    /// waiting for this block, they can now be applied.
+    pub fn apply_block(
+        &self,
+        block_id: HeaderHash,
+    ) -> impl Future<Item = Vec<Block>, Error = Infallible> {
+        let mut inner = self.inner.clone();
+        future::poll_fn(move || Ok(inner.poll_lock()))
+            .map(move |mut guard| guard.apply_block(block_id))
+    }
+
+    /// drop the expired candidates, with the blocks held for them.
+    pub fn purge(&self) -> impl Future<Item = (), Error = Infallible> {
+        let mut inner = self.inner.clone();
+        future::poll_fn(move || Ok(inner.poll_lock()))
+            .map(move |mut guard| guard.purge(Instant::now()))
+    }
+}
+
+impl CandidateForestData {
+    fn new() -> Self {
+        CandidateForestData {
+            headers: HashMap::new(),
+            blocks: HashMap::new(),
+            children: HashMap::new(),
+        }
+    }
+
+    fn add(&mut self, header: Header) -> Option<HeaderHash> {
+        self.add_at(header, Instant::now())
+    }
+
+    fn add_at(&mut self, header: Header, now: Instant) -> Option<HeaderHash> {
+        let block_id = header.hash();
+        if self.headers.contains_key(&block_id) {
+            return None;
+        }
+        self.children
+            .entry(header.block_parent_hash().clone())
+            .or_insert_with(Vec::new)
+            .push(block_id);
+        self.headers.insert(block_id, (header, now + CANDIDATE_TTL));
+        Some(block_id)
+    }
+
+    fn purge(&mut self, now: Instant) {
+        let expired: Vec<HeaderHash> = self
+            .headers
+            .iter()
+            .filter(|(_, (_, expires_at))| *expires_at <= now)
+            .map(|(block_id, _)| *block_id)
+            .collect();
+        for block_id in expired.iter() {
+            self.headers.remove(block_id);
+            self.blocks.remove(block_id);
+        }
+
+        let headers = &self.headers;
+        self.children.retain(|_, children| {
+            children.retain(|child_id| headers.contains_key(child_id));
+            !children.is_empty()
+        });
+    }
+
+    fn apply_block(&mut self, block_id: HeaderHash) -> Vec<Block> {
+        self.headers.remove(&block_id);
+        self.blocks.remove(&block_id);
+
+        let children = self.children.remove(&block_id).unwrap_or_default();
+        children
+            .into_iter()
+            .filter_map(|child_id| {
+                let block = self.blocks.remove(&child_id)?;
+                self.headers.remove(&child_id);
+                Some(block)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockcfg::{BlockBuilder, BlockDate};
+    use chain_core::property::ChainLength as _;
+
+    fn genesis() -> Block {
+        BlockBuilder::new().make_genesis_block()
+    }
+
+    fn child(parent: &Block, slot_id: u32) -> Block {
+        let mut builder = BlockBuilder::new();
+        builder
+            .date(BlockDate { epoch: 0, slot_id })
+            .parent(parent.id())
+            .chain_length(parent.header.chain_length().next());
+        builder.make_genesis_block()
+    }
+
+    fn ids(blocks: Vec<Block>) -> Vec<HeaderHash> {
+        blocks.iter().map(|block| block.id()).collect()
+    }
+
+    #[test]
+    fn held_blocks_are_released_in_chain_order() {
+        let forest = CandidateForest::new();
+        let block0 = genesis();
+        let block1 = child(&block0, 1);
+        let block2 = child(&block1, 2);
+        let block3 = child(&block2, 3);
+
+        forest
+            .add_connected_header(block1.header.clone())
+            .wait()
+            .unwrap();
+        forest
+            .add_unconnected_header(block2.header.clone())
+            .wait()
+            .unwrap();
+        forest
+            .add_unconnected_header(block3.header.clone())
+            .wait()
+            .unwrap();
+
+        // the blocks are received out of order
+        assert!(forest.hold_block(block3.clone()).wait().unwrap());
+        assert!(forest.hold_block(block2.clone()).wait().unwrap());
+
+        // block1 is not received yet, it is applied from the network later
+        assert_eq!(ids(forest.apply_block(block0.id()).wait().unwrap()), vec![]);
+        assert_eq!(
+            ids(forest.apply_block(block1.id()).wait().unwrap()),
+            vec![block2.id()]
+        );
+        assert_eq!(
+            ids(forest.apply_block(block2.id()).wait().unwrap()),
+            vec![block3.id()]
+        );
+        assert_eq!(ids(forest.apply_block(block3.id()).wait().unwrap()), vec![]);
+    }
+
+    #[test]
+    fn block_of_unknown_header_is_not_held() {
+        let forest = CandidateForest::new();
+        let block1 = child(&genesis(), 1);
+
+        assert!(!forest.hold_block(block1).wait().unwrap());
+    }
+
+    #[test]
+    fn header_is_added_once() {
+        let forest = CandidateForest::new();
+        let block1 = child(&genesis(), 1);
+
+        assert_eq!(
+            forest
+                .add_connected_header(block1.header.clone())
+                .wait()
+                .unwrap(),
+            Some(block1.id())
+        );
+        assert_eq!(
+            forest
+                .add_connected_header(block1.header.clone())
+                .wait()
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn unconnected_header_needs_a_candidate_parent() {
+        let forest = CandidateForest::new();
+        let block1 = child(&genesis(), 1);
+        let block2 = child(&block1, 2);
+
+        match forest.add_unconnected_header(block2.header.clone()).wait() {
+            Err(Error(ErrorKind::MissingParentBlockFromStorage(_), _)) => {}
+            _ => panic!("expected the parent to be missing"),
+        }
+    }
+
+    #[test]
+    fn expired_candidates_are_purged() {
+        let mut data = CandidateForestData::new();
+        let block0 = genesis();
+        let block1 = child(&block0, 1);
+        let block2 = child(&block1, 2);
+        let now = Instant::now();
+
+        data.add_at(block1.header.clone(), now);
+        data.add_at(block2.header.clone(), now + Duration::from_secs(60));
+        data.blocks.insert(block1.id(), block1.clone());
+
+        data.purge(now + CANDIDATE_TTL);
+        assert!(!data.headers.contains_key(&block1.id()));
+        assert!(!data.blocks.contains_key(&block1.id()));
+        assert!(!data.children.contains_key(&block0.id()));
+        assert!(data.headers.contains_key(&block2.id()));
+
+        data.purge(now + CANDIDATE_TTL + Duration::from_secs(60));
+        assert!(data.headers.is_empty());
+        assert!(data.children.is_empty());
+    }
+}
@@ -53,12 +53,20 @@ use crate::{
     blockcfg::{
         Block, Block0Error, Epoch, Header, HeaderHash, Leadership, Ledger, LedgerParameters,
     },
-    blockchain::{Branch, Branches, Multiverse, Ref, RefCache, Storage},
+    blockchain::{
+        chain_selection::{compare_against, ComparisonResult},
+        BlockStream, Branch, Branches, CandidateForest, Multiverse, Ref, RefCache, Storage,
+        StoragePruning,
+    },
     start_up::NodeStorage,
+    stats_counter::CacheStats,
 };
 use chain_impl_mockchain::{leadership::Verification, ledger};
 use chain_storage::error::Error as StorageError;
-use chain_time::TimeFrame;
+use chain_time::{
+    era::{EpochPosition, EpochSlotOffset},
+    SlotDuration, TimeEra, TimeFrame, Timeline,
+};
 use std::{convert::Infallible, sync::Arc, time::Duration};
 use tokio::prelude::*;
 
@@ -84,7 +92,17 @@ error_chain! {
 
         MissingParentBlockFromStorage(header: Header) {
             description("missing a parent block from the storage"),
-            display("Missing a block from the storage. The process was recovering the blockchain and the block parent block '{}' was not already in the cache", header.block_parent_hash()),
+            display("Missing a block from the storage. The process was recovering the blockchain and the block parent block '{}' was not in the storage", header.block_parent_hash()),
+        }
+
+        BlockAlreadyPresent (hash: HeaderHash) {
+            description("Block already present in the blockchain"),
+            display("Block '{}' is already present in the blockchain", hash),
+        }
+
+        CannotReloadRef (hash: HeaderHash) {
+            description("Cannot reload the state of a block from the storage"),
+            display("Cannot reload the state of block '{}': none of its ancestors is in the cache", hash),
         }
 
         NoTag (tag: String) {
@@ -100,11 +118,20 @@ error_chain! {
         CannotApplyBlock {
             description("Block cannot be applied on top of the previous block's ledger state"),
         }
+
+        EpochNotInTimeFrame (epoch: Epoch) {
+            description("Epoch not in the time frame of the previous epochs"),
+            display("The first slots of epoch {} are not in the time frame of the previous epochs", epoch),
+        }
     }
 }
 
 const MAIN_BRANCH_TAG: &str = "HEAD";
 
+/// tag of the last block put in the storage. It allows to find back
+/// the blocks stored after the `HEAD` tag was last updated.
+const LAST_BLOCK_TAG: &str = "LAST_BLOCK";
+
 /// blockchain object, can be safely shared across multiple threads. However it is better not
 /// to as some operations may require a mutex.
 ///
@@ -114,17 +141,32 @@ const MAIN_BRANCH_TAG: &str = "HEAD";
 /// * `RefCache`: a cache of blocks headers and associated states;
 /// * `Multiverse`: of ledger. It is a cache of different ledger states.
 ///
+/// It also holds the `CandidateForest`: the chains of headers being
+/// synchronised from the network, waiting for their blocks.
+///
 #[derive(Clone)]
 pub struct Blockchain {
     branches: Branches,
 
+    candidates: CandidateForest,
+
     ref_cache: RefCache,
 
-    ledgers: Multiverse<Arc<Ledger>>,
+    ledgers: Multiverse<Ledger>,
 
     storage: Storage,
 }
 
+/// the maximum number of entries in the in-memory caches of the
+/// `Blockchain`, `None` for no limit
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CacheLimits {
+    /// maximum number of `Ref` in the `RefCache`
+    pub max_refs: Option<usize>,
+    /// maximum number of ledger states in the `Multiverse`
+    pub max_ledgers: Option<usize>,
+}
+
 pub enum PreCheckedHeader {
     /// result when the given header is already present in the
     /// local storage. The embedded `cached_reference` gives us
@@ -167,15 +209,87 @@ pub struct PostCheckedHeader {
     epoch_ledger_parameters: Arc<LedgerParameters>,
     parent_ledger_state: Arc<Ledger>,
     time_frame: Arc<TimeFrame>,
+    era: Arc<TimeEra>,
     previous_epoch_state: Option<Arc<Ref>>,
 }
 
+/// perform the simple verifications of the header against its parent's:
+///
+/// * check the block_date is increasing
+/// * check the chain_length is monotonically increasing
+pub(super) fn pre_check_header_against_parent(header: &Header, parent: &Header) -> Result<()> {
+    use chain_core::property::ChainLength as _;
+
+    if header.block_date() <= parent.block_date() {
+        return Err(ErrorKind::BlockHeaderVerificationFailed(
+            "block date is set before the parent's".to_owned(),
+        )
+        .into());
+    }
+    if header.chain_length() != parent.chain_length().next() {
+        return Err(ErrorKind::BlockHeaderVerificationFailed(
+            "chain length is not monotonically increasing".to_owned(),
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+/// compute the time frame and the era of the given epoch if the slot
+/// duration, in seconds, or the number of slots per epoch set in the
+/// ledger differ from the ones of the previous epochs.
+///
+/// The new time frame starts at the time the previous time frame and era
+/// give to the first slot of the epoch, so the slots of the previous
+/// epochs keep their time.
+///
+/// Returns `None` if the time frame and the era are unchanged.
+fn new_epoch_time_frame(
+    time_frame: &TimeFrame,
+    era: &TimeEra,
+    epoch: Epoch,
+    slot_duration: u32,
+    slots_per_epoch: u32,
+) -> Result<Option<(TimeFrame, TimeEra)>> {
+    let slot_time = |slot_offset| {
+        let slot = era.from_era_to_slot(EpochPosition {
+            epoch: chain_time::Epoch(epoch),
+            slot: EpochSlotOffset(slot_offset),
+        });
+        time_frame
+            .slot_to_systemtime(slot)
+            .ok_or_else(|| Error::from(ErrorKind::EpochNotInTimeFrame(epoch)))
+    };
+    let epoch_start = slot_time(0)?;
+    let previous_slot_duration = slot_time(1)?
+        .duration_since(epoch_start)
+        .map_err(|_| ErrorKind::EpochNotInTimeFrame(epoch))?;
+
+    if Duration::from_secs(slot_duration as u64) == previous_slot_duration
+        && slots_per_epoch == era.slots_per_epoch()
+    {
+        return Ok(None);
+    }
+
+    let time_frame = TimeFrame::new(
+        Timeline::new(epoch_start),
+        SlotDuration::from_secs(slot_duration),
+    );
+    let first_slot = time_frame
+        .slot_at(&epoch_start)
+        .ok_or_else(|| Error::from(ErrorKind::EpochNotInTimeFrame(epoch)))?;
+    let era = TimeEra::new(first_slot, chain_time::Epoch(epoch), slots_per_epoch);
+    Ok(Some((time_frame, era)))
+}
+
 impl Blockchain {
-    pub fn new(storage: NodeStorage, ref_cache_ttl: Duration) -> Self {
+    pub fn new(storage: NodeStorage, ref_cache_ttl: Duration, cache_limits: CacheLimits) -> Self {
         Blockchain {
             branches: Branches::new(),
-            ref_cache: RefCache::new(ref_cache_ttl),
-            ledgers: Multiverse::new(),
+            candidates: CandidateForest::new(),
+            ref_cache: RefCache::new(ref_cache_ttl, cache_limits.max_refs, CacheStats::default()),
+            ledgers: Multiverse::new(cache_limits.max_ledgers, CacheStats::default()),
             storage: Storage::new(storage),
         }
     }
@@ -184,6 +298,26 @@ impl Blockchain {
         &self.storage
     }
 
+    /// prune the storage with `prune_storage`. To be set before the
+    /// blockchain is shared.
+    pub fn set_storage_pruning(&mut self, pruning: StoragePruning) {
+        self.storage.set_pruning(pruning)
+    }
+
+    /// the counters of the cache of the `Ref`
+    pub fn ref_cache_stats(&self) -> &CacheStats {
+        self.ref_cache.stats()
+    }
+
+    /// the counters of the multiverse of the ledger states
+    pub fn multiverse_stats(&self) -> &CacheStats {
+        self.ledgers.stats()
+    }
+
+    pub fn candidates(&self) -> &CandidateForest {
+        &self.candidates
+    }
+
     /// create and store a reference of this leader to the new
     fn create_and_store_reference(
         &mut self,
@@ -191,6 +325,7 @@ impl Blockchain {
         header: Header,
         ledger: Arc<Ledger>,
         time_frame: Arc<TimeFrame>,
+        era: Arc<TimeEra>,
         leadership: Arc<Leadership>,
         ledger_parameters: Arc<LedgerParameters>,
         previous_epoch_state: Option<Arc<Ref>>,
@@ -201,12 +336,14 @@ impl Blockchain {
         let ref_cache = self.ref_cache.clone();
 
         multiverse
-            .insert(chain_length, header_hash, ledger.clone())
+            .insert(chain_length, header_hash, ledger.as_ref().clone())
+            .and_then(move |ledger_gcroot| multiverse.purge_if_full().map(move |()| ledger_gcroot))
             .and_then(move |ledger_gcroot| {
                 let reference = Ref::new(
                     ledger_gcroot,
                     ledger,
                     time_frame,
+                    era,
                     leadership,
                     ledger_parameters,
                     header,
@@ -227,14 +364,15 @@ impl Blockchain {
     /// and it might be necessary to contacts the network to retrieve a missing
     /// branch
     ///
-    /// TODO: the case where the block is in storage but not yet in the cache
-    ///       is not implemented
+    /// If the block is in the storage but not in the cache anymore, its `Ref`
+    /// is reloaded with `load_ref_from_storage`.
     pub fn get_ref(
         &mut self,
         header_hash: HeaderHash,
     ) -> impl Future<Item = Option<Ref>, Error = Error> {
         let get_ref_cache_future = self.ref_cache.get(header_hash.clone());
         let block_exists_future = self.storage.block_exists(header_hash);
+        let self1 = self.clone();
 
         get_ref_cache_future
             .map_err(|_: Infallible| unreachable!())
@@ -245,13 +383,13 @@ impl Blockchain {
                             .map_err(|e| {
                                 Error::with_chain(e, "cannot check if the block is in the storage")
                             })
-                            .and_then(|block_exists| {
+                            .and_then(move |block_exists| {
                                 if block_exists {
-                                    unimplemented!(
-                                        "method to load a Ref from the storage is not yet there"
+                                    future::Either::A(
+                                        self1.load_ref_from_storage(header_hash).map(Some),
                                     )
                                 } else {
-                                    future::ok(None)
+                                    future::Either::B(future::ok(None))
                                 }
                             }),
                     )
@@ -261,6 +399,78 @@ impl Blockchain {
             })
     }
 
+    /// reload the `Ref` of a block which is in the storage but not in the
+    /// cache anymore.
+    ///
+    /// The blocks are read back from the storage down to the nearest
+    /// ancestor still in the cache, then applied again on top of it. The
+    /// ledger states still in the `Multiverse` are reused instead of
+    /// applying their block again. The `Ref`s of the replayed blocks are
+    /// put back in the cache.
+    fn load_ref_from_storage(
+        &self,
+        header_hash: HeaderHash,
+    ) -> impl Future<Item = Ref, Error = Error> {
+        let ref_cache = self.ref_cache.clone();
+        let storage = self.storage.clone();
+        let self1 = self.clone();
+
+        future::loop_fn((header_hash, Vec::new()), move |(hash, mut blocks)| {
+            let storage = storage.clone();
+            ref_cache
+                .get(hash)
+                .map_err(|_: Infallible| unreachable!())
+                .and_then(move |maybe_ref| match maybe_ref {
+                    Some(ancestor) => {
+                        future::Either::A(future::ok(future::Loop::Break((ancestor, blocks))))
+                    }
+                    None => future::Either::B(
+                        storage
+                            .get(hash)
+                            .map_err(|e| {
+                                Error::with_chain(e, "cannot load a block from the storage")
+                            })
+                            .and_then(move |maybe_block| match maybe_block {
+                                Some(block) => {
+                                    let parent_hash = block.header.block_parent_hash().clone();
+                                    blocks.push(block);
+                                    Ok(future::Loop::Continue((parent_hash, blocks)))
+                                }
+                                None => Err(ErrorKind::CannotReloadRef(header_hash).into()),
+                            }),
+                    ),
+                })
+        })
+        .and_then(move |(ancestor, blocks)| {
+            stream::iter_ok::<_, Error>(blocks.into_iter().rev()).fold(
+                ancestor,
+                move |parent, block: Block| {
+                    let mut self2 = self1.clone();
+                    let mut self3 = self1.clone();
+                    let ledgers = self1.ledgers.clone();
+                    let block_id = block.header.hash();
+                    self2
+                        .post_check_header(block.header.clone(), parent)
+                        .and_then(move |post_checked_header| {
+                            ledgers
+                                .get(block_id)
+                                .map_err(|_: Infallible| unreachable!())
+                                .map(move |ledger| (post_checked_header, ledger))
+                        })
+                        .and_then(move |(post_checked_header, ledger)| match ledger {
+                            // the ledger state was not collected yet
+                            Some(ledger) => future::Either::A(
+                                self3.create_reference_from(post_checked_header, ledger),
+                            ),
+                            None => {
+                                future::Either::B(self3.apply_block(post_checked_header, &block))
+                            }
+                        })
+                },
+            )
+        })
+    }
+
     /// load the header's parent `Ref`.
     fn load_header_parent(
         &mut self,
@@ -316,23 +526,10 @@ impl Blockchain {
                 PreCheckedHeader::HeaderWithCache {
                     ref header,
                     ref parent_ref,
-                } => {
-                    use chain_core::property::ChainLength as _;
-
-                    if header.block_date() <= parent_ref.block_date() {
-                        return future::err(
-                            "block is not valid, date is set before parent's".into(),
-                        );
-                    }
-                    if header.chain_length() != parent_ref.chain_length().next() {
-                        return future::err(
-                            "block is not valid, chain length is not monotonically increasing"
-                                .into(),
-                        );
-                    }
-
-                    future::ok(pre_check)
-                }
+                } => future::result(
+                    pre_check_header_against_parent(header, parent_ref.header())
+                        .map(|()| pre_check),
+                ),
                 _ => future::ok(pre_check),
             })
     }
@@ -353,8 +550,16 @@ impl Blockchain {
 
         let current_date = header.block_date();
 
-        let (epoch_leadership_schedule, epoch_ledger_parameters, time_frame, previous_epoch_state) =
-            self.new_epoch_leadership_from(current_date.epoch, parent);
+        let (
+            epoch_leadership_schedule,
+            epoch_ledger_parameters,
+            time_frame,
+            era,
+            previous_epoch_state,
+        ) = match self.new_epoch_leadership_from(current_date.epoch, parent) {
+            Ok(epoch_leadership) => epoch_leadership,
+            Err(error) => return future::err(error),
+        };
 
         match epoch_leadership_schedule.verify(&header) {
             Verification::Success => future::ok(PostCheckedHeader {
@@ -363,6 +568,7 @@ impl Blockchain {
                 epoch_ledger_parameters,
                 parent_ledger_state,
                 time_frame,
+                era,
                 previous_epoch_state,
             }),
             Verification::Failure(error) => {
@@ -371,29 +577,52 @@ impl Blockchain {
         }
     }
 
+    /// get the leadership, the ledger parameters, the time frame and the era
+    /// applicable to the blocks of the given epoch following the `parent`.
+    ///
+    /// On an epoch transition they are computed again from the ledger: the
+    /// updates of the slot duration, of the number of slots per epoch or of
+    /// the active slot coefficient adopted by the parent's ledger take effect
+    /// from the first slot of the new epoch.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `ErrorKind::EpochNotInTimeFrame` if the first slots of
+    /// the epoch cannot be placed in the time frame of the `parent`.
     pub fn new_epoch_leadership_from(
         &mut self,
         epoch: Epoch,
         parent: Ref,
-    ) -> (
+    ) -> Result<(
         Arc<Leadership>,
         Arc<LedgerParameters>,
         Arc<TimeFrame>,
+        Arc<TimeEra>,
         Option<Arc<Ref>>,
-    ) {
+    )> {
         let parent_ledger_state = parent.ledger().clone();
         let parent_epoch_leadership_schedule = parent.epoch_leadership_schedule().clone();
         let parent_epoch_ledger_parameters = parent.epoch_ledger_parameters().clone();
         let parent_time_frame = parent.time_frame().clone();
+        let parent_era = parent.era().clone();
 
         let parent_date = parent.block_date();
 
         if parent_date.epoch < epoch {
-            // TODO: the time frame may change in the future, we will need to handle this
-            //       special case but it is not actually clear how to modify the time frame
-            //       for the blockchain
             use chain_impl_mockchain::block::ConsensusVersion;
 
+            let settings = parent_ledger_state.settings();
+            let (time_frame, era) = match new_epoch_time_frame(
+                &parent_time_frame,
+                &parent_era,
+                epoch,
+                settings.slot_duration as u32,
+                settings.slots_per_epoch,
+            )? {
+                Some((time_frame, era)) => (Arc::new(time_frame), Arc::new(era)),
+                None => (parent_time_frame, parent_era),
+            };
+
             let epoch_state =
                 if parent_ledger_state.consensus_version() == ConsensusVersion::GenesisPraos {
                     // if there is no parent state available this might be because it is not
@@ -409,19 +638,21 @@ impl Blockchain {
             let leadership = Arc::new(Leadership::new(epoch, &epoch_state));
             let ledger_parameters = Arc::new(leadership.ledger_parameters().clone());
             let previous_epoch_state = Some(Arc::new(parent));
-            (
+            Ok((
                 leadership,
                 ledger_parameters,
-                parent_time_frame,
+                time_frame,
+                era,
                 previous_epoch_state,
-            )
+            ))
         } else {
-            (
+            Ok((
                 parent_epoch_leadership_schedule,
                 parent_epoch_ledger_parameters,
                 parent_time_frame,
+                parent_era,
                 parent.last_ref_previous_epoch().map(Arc::clone),
-            )
+            ))
         }
     }
 
@@ -430,38 +661,46 @@ impl Blockchain {
         post_checked_header: PostCheckedHeader,
         block: &Block,
     ) -> impl Future<Item = Ref, Error = Error> {
-        let header = post_checked_header.header;
-        let block_id = header.hash();
-        let epoch_leadership_schedule = post_checked_header.epoch_leadership_schedule;
-        let epoch_ledger_parameters = post_checked_header.epoch_ledger_parameters;
-        let ledger = post_checked_header.parent_ledger_state;
-        let time_frame = post_checked_header.time_frame;
-        let previous_epoch_state = post_checked_header.previous_epoch_state;
-
-        debug_assert!(block.header.hash() == block_id);
+        debug_assert!(block.header.hash() == post_checked_header.header.hash());
 
-        let metadata = header.to_content_eval_context();
+        let metadata = post_checked_header.header.to_content_eval_context();
 
         let mut self1 = self.clone();
 
         future::result(
-            ledger
-                .apply_block(&epoch_ledger_parameters, block.contents.iter(), &metadata)
+            post_checked_header
+                .parent_ledger_state
+                .apply_block(
+                    &post_checked_header.epoch_ledger_parameters,
+                    block.contents.iter(),
+                    &metadata,
+                )
                 .chain_err(|| ErrorKind::CannotApplyBlock),
         )
-        .and_then(move |new_ledger| {
-            self1
-                .create_and_store_reference(
-                    block_id,
-                    header,
-                    Arc::new(new_ledger),
-                    time_frame,
-                    epoch_leadership_schedule,
-                    epoch_ledger_parameters,
-                    previous_epoch_state,
-                )
-                .map_err(|_: Infallible| unreachable!())
-        })
+        .and_then(move |new_ledger| self1.create_reference_from(post_checked_header, new_ledger))
+    }
+
+    /// create and store the `Ref` of the post checked header, given the
+    /// ledger state after its block
+    fn create_reference_from(
+        &mut self,
+        post_checked_header: PostCheckedHeader,
+        ledger: Ledger,
+    ) -> impl Future<Item = Ref, Error = Error> {
+        let header = post_checked_header.header;
+        let block_id = header.hash();
+
+        self.create_and_store_reference(
+            block_id,
+            header,
+            Arc::new(ledger),
+            post_checked_header.time_frame,
+            post_checked_header.era,
+            post_checked_header.epoch_leadership_schedule,
+            post_checked_header.epoch_ledger_parameters,
+            post_checked_header.previous_epoch_state,
+        )
+        .map_err(|_: Infallible| unreachable!())
     }
 
     /// Apply the block on the blockchain from a post checked header
@@ -472,6 +711,7 @@ impl Blockchain {
         block: Block,
     ) -> impl Future<Item = Ref, Error = Error> {
         let mut storage = self.storage.clone();
+        let mut storage_2 = self.storage.clone();
         self.apply_block(post_checked_header, &block)
             .and_then(move |block_ref| {
                 storage
@@ -479,6 +719,12 @@ impl Blockchain {
                     .map_err(|e| e.into())
                     .and_then(move |()| Ok(block_ref))
             })
+            .and_then(move |block_ref| {
+                storage_2
+                    .put_tag(LAST_BLOCK_TAG.to_owned(), block_ref.hash())
+                    .map_err(|e| e.into())
+                    .map(move |()| block_ref)
+            })
     }
 
     /// Apply the given block0 in the blockchain (updating the RefCache and the other objects)
@@ -493,7 +739,7 @@ impl Blockchain {
     ///
     /// * the block0 does build an invalid `Ledger`: `ErrorKind::Block0InitialLedgerError`;
     ///
-    fn apply_block0(&mut self, block0: Block) -> impl Future<Item = Branch, Error = Error> {
+    pub fn apply_block0(&mut self, block0: Block) -> impl Future<Item = Branch, Error = Error> {
         let block0_header = block0.header.clone();
         let block0_id = block0_header.hash();
         let block0_id_1 = block0_header.hash();
@@ -538,6 +784,7 @@ impl Blockchain {
             })
             .and_then(move |(block0_ledger, block0_leadership, time_frame)| {
                 let ledger_parameters = block0_leadership.ledger_parameters().clone();
+                let era = block0_leadership.era().clone();
 
                 self1
                     .create_and_store_reference(
@@ -545,6 +792,7 @@ impl Blockchain {
                         block0_header,
                         Arc::new(block0_ledger),
                         Arc::new(time_frame),
+                        Arc::new(era),
                         Arc::new(block0_leadership),
                         Arc::new(ledger_parameters),
                         None,
@@ -615,6 +863,10 @@ impl Blockchain {
     ///
     /// The Future will returns a branch pointing to the `Head`.
     ///
+    /// TODO: every block is applied again from the block0, resuming from
+    ///       a ledger snapshot requires a serialisation format for the
+    ///       `Ledger` in `chain-impl-mockchain` first.
+    ///
     /// # Errors
     ///
     /// The resulted future may fail if
@@ -634,6 +886,7 @@ impl Blockchain {
         let mut self2 = self.clone();
         let self3 = self.clone();
         let self4 = self.clone();
+        let mut self7 = self.clone();
 
         self.storage
             .block_exists(block0_id.clone())
@@ -681,41 +934,257 @@ impl Blockchain {
                                 Error::with_chain(e, "Error while iterating between bloc0 and HEAD")
                             })
                             .fold((branch, self4), move |(branch, mut self4), block: Block| {
-                                let header = block.header.clone();
-
-                                let mut self5 = self4.clone();
-                                let mut self6 = self4.clone();
                                 let returned = self4.clone();
 
-                                self4
-                                    .pre_check_header(header)
-                                    .and_then(move |pre_checked_header: PreCheckedHeader| {
-                                        match pre_checked_header {
-                                            PreCheckedHeader::HeaderWithCache {
-                                                header,
-                                                parent_ref,
-                                            } => future::Either::A(self5.post_check_header(header, parent_ref)),
-                                            PreCheckedHeader::AlreadyPresent { header, cached_reference: _cached_reference } => {
-                                                unreachable!("block already present, this should not happen. {:#?}", header)
-                                            },
-                                            PreCheckedHeader::MissingParent { header } =>
-                                                future::Either::B(future::err(ErrorKind::MissingParentBlockFromStorage(header).into())),
-                                        }
-                                    })
-                                    .and_then(move |post_checked_header: PostCheckedHeader| {
-                                        self6.apply_block(post_checked_header, &block)
-                                    })
-                                    .and_then(move |new_ref| {
-                                        branch
-                                            .clone()
-                                            .update_ref(new_ref)
-                                            .map(move |_old_ref| (branch, returned))
-                                            .map_err(|_: Infallible| unreachable!())
-                                    })
+                                self4.apply_stored_block(block).and_then(move |new_ref| {
+                                    branch
+                                        .clone()
+                                        .update_ref(new_ref)
+                                        .map(move |_old_ref| (branch, returned))
+                                        .map_err(|_: Infallible| unreachable!())
+                                })
                             })
                             .map(|(branch, _)| branch)
                     })
             })
+            .and_then(move |branch| self7.recover_blocks_after_head(branch))
+    }
+
+    /// apply a block already present in the storage on top of its parent's
+    /// `Ref`, without storing it again.
+    ///
+    /// The header and the ledger transition of the block are checked again,
+    /// as the storage does not keep the result of the previous validation.
+    pub fn apply_stored_block(&mut self, block: Block) -> impl Future<Item = Ref, Error = Error> {
+        self.check_and_apply_block(block, false)
+    }
+
+    /// check the block against its parent's `Ref` and apply it. The
+    /// parent's `Ref` is taken from the cache, or reloaded from the
+    /// storage if it is not cached anymore.
+    ///
+    /// If `store` is set, the block is added to the storage once applied.
+    fn check_and_apply_block(
+        &mut self,
+        block: Block,
+        store: bool,
+    ) -> impl Future<Item = Ref, Error = Error> {
+        let header = block.header.clone();
+
+        let mut self1 = self.clone();
+        let mut self2 = self.clone();
+
+        self.pre_check_header(header)
+            .and_then(move |pre_checked_header| match pre_checked_header {
+                PreCheckedHeader::HeaderWithCache { header, parent_ref } => {
+                    future::Either::A(self1.post_check_header(header, parent_ref))
+                }
+                PreCheckedHeader::AlreadyPresent { header, .. } => future::Either::B(future::err(
+                    ErrorKind::BlockAlreadyPresent(header.hash()).into(),
+                )),
+                PreCheckedHeader::MissingParent { header } => future::Either::B(future::err(
+                    ErrorKind::MissingParentBlockFromStorage(header).into(),
+                )),
+            })
+            .and_then(move |post_checked_header| {
+                if store {
+                    future::Either::A(self2.apply_and_store_block(post_checked_header, block))
+                } else {
+                    future::Either::B(self2.apply_block(post_checked_header, &block))
+                }
+            })
+    }
+
+    /// recover the blocks stored after the last update of the `HEAD` tag.
+    ///
+    /// If the node stopped between storing blocks and updating the `HEAD`
+    /// tag, the blocks stored since are applied again, up to the first
+    /// invalid one, and selected as the tip if they make a better chain
+    /// than the `HEAD`'s.
+    ///
+    /// The future returns the given branch, updated to the selected tip.
+    fn recover_blocks_after_head(
+        &mut self,
+        branch: Branch,
+    ) -> impl Future<Item = Branch, Error = Error> {
+        let storage = self.storage.clone();
+        let storage_2 = self.storage.clone();
+        let storage_3 = self.storage.clone();
+        let self1 = self.clone();
+        let mut self2 = self.clone();
+        let mut branch_2 = branch.clone();
+
+        branch
+            .get_ref()
+            .map_err(|_: Infallible| unreachable!())
+            .and_then(move |head| {
+                storage
+                    .get_tag(LAST_BLOCK_TAG.to_owned())
+                    .map_err(|e| Error::with_chain(e, "Cannot get hash of the LAST_BLOCK tag"))
+                    .map(move |last_block| (head, last_block))
+            })
+            .and_then(move |(head, last_block)| match last_block {
+                Some(last_block) if last_block != head.hash() => future::Either::A(
+                    storage_2
+                        .common_ancestor(head.hash(), last_block)
+                        .and_then(move |ancestor| storage_2.stream_from_to(ancestor, last_block))
+                        .map_err(|e| {
+                            Error::with_chain(e, "Cannot iterate blocks stored after HEAD")
+                        })
+                        .and_then(move |block_stream| {
+                            let block_stream = match block_stream {
+                                Some(block_stream) => block_stream,
+                                None => {
+                                    return future::Either::A(future::ok((head, None)));
+                                }
+                            };
+                            future::Either::B(
+                                block_stream
+                                    .map_err(|e| {
+                                        Error::with_chain(
+                                            e,
+                                            "Error while iterating blocks stored after HEAD",
+                                        )
+                                    })
+                                    .fold(
+                                        (self1, None, false),
+                                        |(mut self1, last_valid, failed), block: Block| {
+                                            if failed {
+                                                return future::Either::A(future::ok((
+                                                    self1, last_valid, failed,
+                                                )));
+                                            }
+                                            let returned = self1.clone();
+                                            future::Either::B(self1.apply_stored_block(block).then(
+                                                move |res| match res {
+                                                    Ok(new_ref) => Ok::<_, Error>((
+                                                        returned,
+                                                        Some(new_ref),
+                                                        false,
+                                                    )),
+                                                    Err(_) => Ok((returned, last_valid, true)),
+                                                },
+                                            ))
+                                        },
+                                    )
+                                    .map(move |(_, last_valid, _)| (head, last_valid)),
+                            )
+                        }),
+                ),
+                _ => future::Either::B(future::ok((head, None))),
+            })
+            .and_then(move |(head, last_valid)| match last_valid {
+                None => future::Either::A(future::ok(None)),
+                Some(candidate) => {
+                    future::Either::B(compare_against(&storage_3, &head, &candidate).map(
+                        move |comparison| match comparison {
+                            ComparisonResult::PreferCurrent => None,
+                            ComparisonResult::PreferCandidate { .. } => Some(candidate),
+                        },
+                    ))
+                }
+            })
+            .and_then(move |selected| match selected {
+                None => future::Either::A(future::ok(branch_2)),
+                Some(new_tip) => future::Either::B(self2.put_tip(&new_tip).and_then(move |()| {
+                    branch_2
+                        .update_ref(new_tip)
+                        .map_err(|_: Infallible| unreachable!())
+                        .map(move |_old_ref| branch_2)
+                })),
+            })
+    }
+
+    /// get the stream of the blocks of the main branch, from the block
+    /// following the block0 to the `HEAD`
+    pub fn stream_from_block0_to_head(
+        &self,
+        block0_id: HeaderHash,
+    ) -> impl Future<Item = BlockStream, Error = Error> {
+        let storage = self.storage.clone();
+
+        self.storage
+            .get_tag(MAIN_BRANCH_TAG.to_owned())
+            .map_err(|e| Error::with_chain(e, "Cannot get hash of the HEAD tag"))
+            .and_then(|opt| {
+                if let Some(id) = opt {
+                    future::ok(id)
+                } else {
+                    future::err(ErrorKind::NoTag(MAIN_BRANCH_TAG.to_owned()).into())
+                }
+            })
+            .and_then(move |head_hash| {
+                storage
+                    .stream_from_to(block0_id, head_hash)
+                    .map_err(|e| Error::with_chain(e, "Cannot iterate blocks from block0 to HEAD"))
+            })
+            .and_then(|block_stream| {
+                if let Some(block_stream) = block_stream {
+                    future::ok(block_stream)
+                } else {
+                    future::err("Cannot iterate between block0 and HEAD".into())
+                }
+            })
+    }
+
+    /// fully validate a block coming from outside of the node and add it
+    /// to the storage. Its parent must already be in the blockchain.
+    pub fn import_block(&mut self, block: Block) -> impl Future<Item = Ref, Error = Error> {
+        self.check_and_apply_block(block, true)
+    }
+
+    /// store the hash of the given tip in the `HEAD` tag, so the node
+    /// resumes from it on the next start
+    pub fn put_tip(&mut self, tip: &Ref) -> impl Future<Item = (), Error = Error> {
+        self.storage
+            .put_tag(MAIN_BRANCH_TAG.to_owned(), tip.hash())
+            .map_err(|e| Error::with_chain(e, "Cannot put the tip's hash in the HEAD tag"))
+    }
+
+    /// remove from the storage the blocks of the forks too deep below the
+    /// tip to be switched to, if the storage pruning is set.
+    ///
+    /// Returns the number of bytes reclaimed, or `None` if the storage is
+    /// not pruned.
+    pub fn prune_storage(&self) -> impl Future<Item = Option<u64>, Error = Error> {
+        self.storage
+            .prune_forks(vec![MAIN_BRANCH_TAG.to_owned(), LAST_BLOCK_TAG.to_owned()])
+            .map_err(|e| Error::with_chain(e, "Cannot prune the storage"))
+    }
+
+    /// get the hash of the `HEAD` tag, or of the last block put in the
+    /// storage if the `HEAD` tag is missing.
+    ///
+    /// The future returns `None` if none of these tags are in the storage.
+    pub fn get_head_or_last_block(&self) -> impl Future<Item = Option<HeaderHash>, Error = Error> {
+        let storage = self.storage.clone();
+
+        self.storage
+            .get_tag(MAIN_BRANCH_TAG.to_owned())
+            .map_err(|e| Error::with_chain(e, "Cannot get hash of the HEAD tag"))
+            .and_then(move |head| match head {
+                Some(head_hash) => future::Either::A(future::ok(Some(head_hash))),
+                None => future::Either::B(
+                    storage
+                        .get_tag(LAST_BLOCK_TAG.to_owned())
+                        .map_err(|e| Error::with_chain(e, "Cannot get hash of the LAST_BLOCK tag")),
+                ),
+            })
+    }
+
+    /// rewrite both the `HEAD` tag and the last block tag to the given
+    /// tip, so the blocks stored after it are ignored on the next start.
+    pub fn reset_head(&mut self, tip: &Ref) -> impl Future<Item = (), Error = Error> {
+        let mut storage = self.storage.clone();
+        let tip_hash = tip.hash();
+
+        self.put_tip(tip).and_then(move |()| {
+            storage
+                .put_tag(LAST_BLOCK_TAG.to_owned(), tip_hash)
+                .map_err(|e| {
+                    Error::with_chain(e, "Cannot put the tip's hash in the LAST_BLOCK tag")
+                })
+        })
     }
 
     pub fn get_checkpoints(
@@ -729,3 +1198,106 @@ impl Blockchain {
             .and_then(move |tip| storage.get_checkpoints(tip.hash()).map_err(|e| e.into()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+
+    const SLOT_DURATION: u32 = 10;
+    const SLOTS_PER_EPOCH: u32 = 100;
+
+    fn start() -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000)
+    }
+
+    fn epoch0() -> (TimeFrame, TimeEra) {
+        let time_frame = TimeFrame::new(
+            Timeline::new(start()),
+            SlotDuration::from_secs(SLOT_DURATION),
+        );
+        let first_slot = time_frame.slot_at(&start()).unwrap();
+        let era = TimeEra::new(first_slot, chain_time::Epoch(0), SLOTS_PER_EPOCH);
+        (time_frame, era)
+    }
+
+    fn slot_time(time_frame: &TimeFrame, era: &TimeEra, epoch: Epoch, slot: u32) -> SystemTime {
+        let slot = era.from_era_to_slot(EpochPosition {
+            epoch: chain_time::Epoch(epoch),
+            slot: EpochSlotOffset(slot),
+        });
+        time_frame.slot_to_systemtime(slot).unwrap()
+    }
+
+    fn epoch_position(time_frame: &TimeFrame, era: &TimeEra, time: SystemTime) -> (u32, u32) {
+        let slot = time_frame.slot_at(&time).unwrap();
+        let position = era.from_slot_to_era(slot).unwrap();
+        (position.epoch.0, position.slot.0)
+    }
+
+    #[test]
+    fn unchanged_settings_keep_the_time_frame() {
+        let (time_frame, era) = epoch0();
+
+        let changed =
+            new_epoch_time_frame(&time_frame, &era, 2, SLOT_DURATION, SLOTS_PER_EPOCH).unwrap();
+
+        assert!(changed.is_none());
+    }
+
+    #[test]
+    fn new_slot_duration_starts_at_the_epoch_boundary() {
+        let (time_frame, era) = epoch0();
+        let epoch2_start = start() + Duration::from_secs(2 * 100 * 10);
+
+        let (new_time_frame, new_era) =
+            new_epoch_time_frame(&time_frame, &era, 2, 20, SLOTS_PER_EPOCH)
+                .unwrap()
+                .unwrap();
+
+        assert_eq!(slot_time(&new_time_frame, &new_era, 2, 0), epoch2_start);
+        assert_eq!(
+            slot_time(&new_time_frame, &new_era, 2, 1),
+            epoch2_start + Duration::from_secs(20)
+        );
+        assert_eq!(
+            epoch_position(
+                &new_time_frame,
+                &new_era,
+                epoch2_start + Duration::from_secs(45)
+            ),
+            (2, 2)
+        );
+        // the last slot of the previous epoch keeps its time
+        assert_eq!(
+            slot_time(&time_frame, &era, 1, 99),
+            epoch2_start - Duration::from_secs(10)
+        );
+    }
+
+    #[test]
+    fn new_slots_per_epoch_starts_at_the_epoch_boundary() {
+        let (time_frame, era) = epoch0();
+        let epoch1_start = start() + Duration::from_secs(100 * 10);
+
+        let (new_time_frame, new_era) =
+            new_epoch_time_frame(&time_frame, &era, 1, SLOT_DURATION, 50)
+                .unwrap()
+                .unwrap();
+
+        assert_eq!(new_era.slots_per_epoch(), 50);
+        assert_eq!(slot_time(&new_time_frame, &new_era, 1, 0), epoch1_start);
+        assert_eq!(
+            slot_time(&new_time_frame, &new_era, 2, 0),
+            epoch1_start + Duration::from_secs(50 * 10)
+        );
+        assert_eq!(
+            epoch_position(
+                &new_time_frame,
+                &new_era,
+                epoch1_start + Duration::from_secs(510)
+            ),
+            (2, 1)
+        );
+    }
+}
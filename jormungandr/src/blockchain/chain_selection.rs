@@ -0,0 +1,146 @@
+//! chain selection rule: decide which of the current tip or of a
+//! candidate block should be the tip of the blockchain.
+//!
+//! The longest chain (in number of blocks) is preferred, as long as
+//! switching to it does not require to roll back more than the
+//! `epoch_stability_depth` blocks of the current tip.
+
+use super::{Error, Ref, Storage};
+use tokio::prelude::*;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ComparisonResult {
+    /// keep the current tip
+    PreferCurrent,
+    /// switch to the candidate
+    PreferCandidate {
+        /// the number of blocks of the current tip's branch that are
+        /// rolled back by switching to the candidate. `0` means the
+        /// candidate extends the current tip.
+        rollback_depth: u64,
+    },
+}
+
+/// compare the `candidate` against the `current` tip
+///
+/// the candidate must already be in the storage.
+pub fn compare_against(
+    storage: &Storage,
+    current: &Ref,
+    candidate: &Ref,
+) -> impl Future<Item = ComparisonResult, Error = Error> {
+    let current_length = current.chain_length();
+    let candidate_length = candidate.chain_length();
+    let epoch_stability_depth = current.ledger().settings().epoch_stability_depth as u64;
+
+    if candidate_length <= current_length {
+        return future::Either::A(future::ok(ComparisonResult::PreferCurrent));
+    }
+
+    if candidate.block_parent_hash() == &current.hash() {
+        return future::Either::A(future::ok(select(
+            current_length,
+            candidate_length,
+            Some(0),
+            epoch_stability_depth,
+        )));
+    }
+
+    future::Either::B(
+        storage
+            .rollback_distance(current.hash(), candidate.hash(), epoch_stability_depth)
+            .map_err(|e| Error::with_chain(e, "cannot find the fork point of the candidate"))
+            .map(move |rollback_depth| {
+                select(
+                    current_length,
+                    candidate_length,
+                    rollback_depth,
+                    epoch_stability_depth,
+                )
+            }),
+    )
+}
+
+/// decide between the current tip and the candidate from the length of
+/// their chains and the number of blocks of the current tip's branch to roll
+/// back to switch to the candidate (`None` if it is too far to be known).
+fn select<L: Ord>(
+    current_length: L,
+    candidate_length: L,
+    rollback_depth: Option<u64>,
+    epoch_stability_depth: u64,
+) -> ComparisonResult {
+    if candidate_length <= current_length {
+        return ComparisonResult::PreferCurrent;
+    }
+
+    match rollback_depth {
+        Some(rollback_depth) if rollback_depth <= epoch_stability_depth => {
+            ComparisonResult::PreferCandidate { rollback_depth }
+        }
+        _ => ComparisonResult::PreferCurrent,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPOCH_STABILITY_DEPTH: u64 = 10;
+
+    #[test]
+    fn prefer_longer_candidate() {
+        assert_eq!(
+            select(5u32, 6, Some(0), EPOCH_STABILITY_DEPTH),
+            ComparisonResult::PreferCandidate { rollback_depth: 0 }
+        );
+        assert_eq!(
+            select(5u32, 7, Some(3), EPOCH_STABILITY_DEPTH),
+            ComparisonResult::PreferCandidate { rollback_depth: 3 }
+        );
+    }
+
+    #[test]
+    fn keep_tip_if_candidate_is_not_longer() {
+        assert_eq!(
+            select(5u32, 5, Some(1), EPOCH_STABILITY_DEPTH),
+            ComparisonResult::PreferCurrent
+        );
+        assert_eq!(
+            select(5u32, 4, Some(2), EPOCH_STABILITY_DEPTH),
+            ComparisonResult::PreferCurrent
+        );
+    }
+
+    #[test]
+    fn rollback_up_to_the_stability_depth() {
+        assert_eq!(
+            select(
+                20u32,
+                30,
+                Some(EPOCH_STABILITY_DEPTH),
+                EPOCH_STABILITY_DEPTH
+            ),
+            ComparisonResult::PreferCandidate {
+                rollback_depth: EPOCH_STABILITY_DEPTH
+            }
+        );
+    }
+
+    #[test]
+    fn reject_rollback_beyond_the_stability_depth() {
+        assert_eq!(
+            select(
+                20u32,
+                30,
+                Some(EPOCH_STABILITY_DEPTH + 1),
+                EPOCH_STABILITY_DEPTH
+            ),
+            ComparisonResult::PreferCurrent
+        );
+        assert_eq!(
+            select(20u32, 30, None, EPOCH_STABILITY_DEPTH),
+            ComparisonResult::PreferCurrent
+        );
+    }
+}
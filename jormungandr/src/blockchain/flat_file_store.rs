@@ -0,0 +1,666 @@
+//! append-only block storage in a single flat file
+//!
+//! The blocks and the tags are appended to the file as records, in the
+//! order they are put in the storage. Nothing is ever overwritten: a tag
+//! updated again is appended again and the last record wins. The index of
+//! the blocks and the tags is kept in memory and rebuilt by reading the
+//! whole file when the storage is opened.
+//!
+//! The records are not synced to the disk one by one, a crash may lose
+//! the last records. An incomplete record at the end of the file is
+//! dropped when the storage is opened.
+//!
+//! The blocks of the abandoned forks can be removed with `prune_forks`,
+//! which writes the file again without them.
+//!
+//! # Format
+//!
+//! All the integers are big endian.
+//!
+//! * block record: `0u8`, the block's length as `u32`, the serialised
+//!   block, the block's hash, its depth as `u64`, the number of back
+//!   links as `u32` then for each back link its distance as `u64` and
+//!   the hash of the block it links to;
+//! * tag record: `1u8`, the tag name's length as `u32`, the UTF-8 tag
+//!   name, the hash of the tagged block.
+
+use crate::blockcfg::{Block, HeaderHash, BLOCK_MAX_SIZE};
+use chain_core::property::{Deserialize as _, Serialize as _};
+use chain_storage::{
+    error::Error as StorageError,
+    store::{BackLink, BlockInfo, BlockStore},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+const RECORD_BLOCK: u8 = 0;
+const RECORD_TAG: u8 = 1;
+const HASH_SIZE: usize = 32;
+/// the lengths read from the file are checked before allocating anything
+const TAG_NAME_MAX_SIZE: usize = 1024;
+
+pub struct FlatFileBlockStore {
+    path: PathBuf,
+    file: Mutex<File>,
+    /// offset of the end of the last complete record
+    end: u64,
+    blocks: HashMap<HeaderHash, BlockEntry>,
+    tags: HashMap<String, HeaderHash>,
+}
+
+struct BlockEntry {
+    /// offset of the serialised block in the file
+    offset: u64,
+    len: u32,
+    info: BlockInfo<HeaderHash>,
+}
+
+enum Record {
+    Block(BlockEntry),
+    Tag(String, HeaderHash),
+}
+
+impl FlatFileBlockStore {
+    /// open the storage file at the given path, creating it if it does not
+    /// exist yet, and rebuild the index of its blocks and tags.
+    pub fn new<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(&path)?;
+
+        let mut blocks = HashMap::new();
+        let mut tags = HashMap::new();
+        let mut end = 0;
+        {
+            let mut reader = BufReader::new(&mut file);
+            loop {
+                match read_record(&mut reader, end) {
+                    Ok(None) => break,
+                    Ok(Some((record, record_len))) => {
+                        match record {
+                            Record::Block(entry) => {
+                                blocks.insert(entry.info.block_hash.clone(), entry);
+                            }
+                            Record::Tag(name, block_hash) => {
+                                tags.insert(name, block_hash);
+                            }
+                        }
+                        end += record_len;
+                    }
+                    // the node stopped while appending the last record
+                    Err(ref error) if error.kind() == io::ErrorKind::UnexpectedEof => break,
+                    Err(error) => return Err(error),
+                }
+            }
+        }
+        file.set_len(end)?;
+
+        Ok(FlatFileBlockStore {
+            path,
+            file: Mutex::new(file),
+            end,
+            blocks,
+            tags,
+        })
+    }
+
+    fn append(&mut self, record: &[u8]) -> io::Result<()> {
+        let mut file = self.file.lock().unwrap();
+        file.seek(SeekFrom::Start(self.end))?;
+        file.write_all(record)?;
+        self.end += record.len() as u64;
+        Ok(())
+    }
+
+    fn read_block_bytes(&self, entry: &BlockEntry) -> io::Result<Vec<u8>> {
+        let mut bytes = vec![0u8; entry.len as usize];
+        let mut file = self.file.lock().unwrap();
+        file.seek(SeekFrom::Start(entry.offset))?;
+        file.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn read_block(&self, entry: &BlockEntry) -> io::Result<Block> {
+        let bytes = self.read_block_bytes(entry)?;
+        Block::deserialize(&bytes[..])
+    }
+
+    /// remove the blocks of the forks which branched off the chains of
+    /// the tagged blocks more than `depth` blocks below the deepest
+    /// tagged block. Such forks are beyond the rollback horizon: they can
+    /// not become the tip anymore.
+    ///
+    /// The storage file is written again, only with the remaining blocks
+    /// and the current value of the tags, then replaces the previous one.
+    ///
+    /// Returns the number of bytes reclaimed.
+    pub fn prune_forks(&mut self, depth: u64) -> io::Result<u64> {
+        let pruned = self.abandoned_forks(depth);
+        if pruned.is_empty() {
+            return Ok(0);
+        }
+
+        let mut kept: Vec<&BlockEntry> = self
+            .blocks
+            .values()
+            .filter(|entry| !pruned.contains(&entry.info.block_hash))
+            .collect();
+        kept.sort_by_key(|entry| entry.offset);
+
+        let mut pruned_path = self.path.clone().into_os_string();
+        pruned_path.push(".prune");
+        let pruned_path = PathBuf::from(pruned_path);
+        {
+            let mut writer = BufWriter::new(File::create(&pruned_path)?);
+            for entry in kept {
+                let block_bytes = self.read_block_bytes(entry)?;
+                writer.write_all(&block_record(&block_bytes, &entry.info)?)?;
+            }
+            for (tag_name, block_hash) in self.tags.iter() {
+                writer.write_all(&tag_record(tag_name, block_hash)?)?;
+            }
+            writer.into_inner()?.sync_all()?;
+        }
+        fs::rename(&pruned_path, &self.path)?;
+
+        let previous_end = self.end;
+        *self = FlatFileBlockStore::new(&self.path)?;
+        Ok(previous_end.saturating_sub(self.end))
+    }
+
+    /// find the blocks to remove with `prune_forks`
+    fn abandoned_forks(&self, depth: u64) -> HashSet<HeaderHash> {
+        // the tagged blocks and their ancestors
+        let mut chains = HashSet::new();
+        for tagged in self.tags.values() {
+            let mut current = Some(tagged.clone());
+            while let Some(block_hash) = current.take() {
+                if !chains.insert(block_hash.clone()) {
+                    break;
+                }
+                current = self
+                    .blocks
+                    .get(&block_hash)
+                    .filter(|entry| entry.info.depth > 0)
+                    .map(|entry| entry.info.parent_id());
+            }
+        }
+
+        let tip_depth = match self
+            .tags
+            .values()
+            .filter_map(|block_hash| self.blocks.get(block_hash))
+            .map(|entry| entry.info.depth)
+            .max()
+        {
+            Some(tip_depth) => tip_depth,
+            None => return HashSet::new(),
+        };
+        let horizon = tip_depth.saturating_sub(depth);
+
+        // the depth of the block where each visited fork branched off
+        let mut fork_points: HashMap<HeaderHash, Option<u64>> = HashMap::new();
+        let mut pruned = HashSet::new();
+        for entry in self.blocks.values() {
+            let block_hash = &entry.info.block_hash;
+            if chains.contains(block_hash) {
+                continue;
+            }
+
+            let mut path = Vec::new();
+            let mut current = entry;
+            let fork_point = loop {
+                if let Some(fork_point) = fork_points.get(&current.info.block_hash) {
+                    break *fork_point;
+                }
+                path.push(current.info.block_hash.clone());
+                if current.info.depth == 0 {
+                    break None;
+                }
+                let parent_hash = current.info.parent_id();
+                if chains.contains(&parent_hash) {
+                    break Some(current.info.depth - 1);
+                }
+                match self.blocks.get(&parent_hash) {
+                    Some(parent) => current = parent,
+                    // keep the forks we can not follow to the main chains
+                    None => break None,
+                }
+            };
+            for visited in path {
+                fork_points.insert(visited, fork_point);
+            }
+
+            match fork_point {
+                Some(fork_point) if fork_point < horizon => {
+                    pruned.insert(block_hash.clone());
+                }
+                _ => (),
+            }
+        }
+        pruned
+    }
+}
+
+impl BlockStore for FlatFileBlockStore {
+    type Block = Block;
+
+    fn put_block_internal(
+        &mut self,
+        block: &Block,
+        block_info: BlockInfo<HeaderHash>,
+    ) -> Result<(), StorageError> {
+        if self.blocks.contains_key(&block_info.block_hash) {
+            return Ok(());
+        }
+
+        let block_bytes = block.serialize_as_vec().map_err(backend_error)?;
+        let record = block_record(&block_bytes, &block_info).map_err(backend_error)?;
+
+        let offset = self.end + 5;
+        self.append(&record).map_err(backend_error)?;
+        self.blocks.insert(
+            block_info.block_hash.clone(),
+            BlockEntry {
+                offset,
+                len: block_bytes.len() as u32,
+                info: block_info,
+            },
+        );
+        Ok(())
+    }
+
+    fn get_block(
+        &self,
+        block_hash: &HeaderHash,
+    ) -> Result<(Block, BlockInfo<HeaderHash>), StorageError> {
+        let entry = self
+            .blocks
+            .get(block_hash)
+            .ok_or(StorageError::BlockNotFound)?;
+        let block = self.read_block(entry).map_err(backend_error)?;
+        Ok((block, entry.info.clone()))
+    }
+
+    fn get_block_info(
+        &self,
+        block_hash: &HeaderHash,
+    ) -> Result<BlockInfo<HeaderHash>, StorageError> {
+        self.blocks
+            .get(block_hash)
+            .map(|entry| entry.info.clone())
+            .ok_or(StorageError::BlockNotFound)
+    }
+
+    fn put_tag(&mut self, tag_name: &str, block_hash: &HeaderHash) -> Result<(), StorageError> {
+        if !self.blocks.contains_key(block_hash) {
+            return Err(StorageError::BlockNotFound);
+        }
+
+        let record = tag_record(tag_name, block_hash).map_err(backend_error)?;
+        self.append(&record).map_err(backend_error)?;
+        self.tags.insert(tag_name.to_owned(), block_hash.clone());
+        Ok(())
+    }
+
+    fn get_tag(&self, tag_name: &str) -> Result<Option<HeaderHash>, StorageError> {
+        Ok(self.tags.get(tag_name).cloned())
+    }
+}
+
+fn backend_error(error: io::Error) -> StorageError {
+    StorageError::BackendError(Box::new(error))
+}
+
+fn block_record(block_bytes: &[u8], info: &BlockInfo<HeaderHash>) -> io::Result<Vec<u8>> {
+    let mut record = Vec::with_capacity(block_bytes.len() + 128);
+    record.push(RECORD_BLOCK);
+    record.extend_from_slice(&(block_bytes.len() as u32).to_be_bytes());
+    record.extend_from_slice(block_bytes);
+    write_block_info(&mut record, info)?;
+    Ok(record)
+}
+
+fn tag_record(tag_name: &str, block_hash: &HeaderHash) -> io::Result<Vec<u8>> {
+    let mut record = Vec::with_capacity(tag_name.len() + 64);
+    record.push(RECORD_TAG);
+    record.extend_from_slice(&(tag_name.len() as u32).to_be_bytes());
+    record.extend_from_slice(tag_name.as_bytes());
+    block_hash.serialize(&mut record)?;
+    Ok(record)
+}
+
+fn write_block_info(record: &mut Vec<u8>, info: &BlockInfo<HeaderHash>) -> io::Result<()> {
+    info.block_hash.serialize(&mut *record)?;
+    record.extend_from_slice(&info.depth.to_be_bytes());
+    record.extend_from_slice(&(info.back_links.len() as u32).to_be_bytes());
+    for back_link in info.back_links.iter() {
+        record.extend_from_slice(&back_link.distance.to_be_bytes());
+        back_link.block_hash.serialize(&mut *record)?;
+    }
+    Ok(())
+}
+
+/// read the record starting at the given offset, returns the record and
+/// its length in bytes or `None` at the end of the file.
+fn read_record<R: BufRead>(reader: &mut R, offset: u64) -> io::Result<Option<(Record, u64)>> {
+    if reader.fill_buf()?.is_empty() {
+        return Ok(None);
+    }
+    let mut reader = CountingReader {
+        inner: reader,
+        count: 0,
+    };
+
+    let record = match read_u8(&mut reader)? {
+        RECORD_BLOCK => {
+            let len = read_u32(&mut reader)?;
+            check_length(len, BLOCK_MAX_SIZE, offset)?;
+            let block_offset = offset + reader.count;
+            io::copy(&mut (&mut reader).take(len as u64), &mut io::sink())?;
+            if reader.count != 5 + len as u64 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            let info = read_block_info(&mut reader)?;
+            Record::Block(BlockEntry {
+                offset: block_offset,
+                len,
+                info,
+            })
+        }
+        RECORD_TAG => {
+            let len = read_u32(&mut reader)?;
+            check_length(len, TAG_NAME_MAX_SIZE, offset)?;
+            let mut name = vec![0u8; len as usize];
+            reader.read_exact(&mut name)?;
+            let name = String::from_utf8(name)
+                .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+            let block_hash = read_hash(&mut reader)?;
+            Record::Tag(name, block_hash)
+        }
+        kind => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unknown record kind {} at offset {}", kind, offset),
+            ))
+        }
+    };
+
+    Ok(Some((record, reader.count)))
+}
+
+fn check_length(len: u32, max: usize, offset: u64) -> io::Result<()> {
+    if len as usize > max {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "length {} of the record at offset {} is larger than the maximum of {}",
+                len, offset, max
+            ),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+fn read_block_info<R: Read>(reader: &mut R) -> io::Result<BlockInfo<HeaderHash>> {
+    let block_hash = read_hash(reader)?;
+    let depth = read_u64(reader)?;
+    let back_links_len = read_u32(reader)?;
+    let mut back_links = Vec::new();
+    for _ in 0..back_links_len {
+        let distance = read_u64(reader)?;
+        let block_hash = read_hash(reader)?;
+        back_links.push(BackLink {
+            distance,
+            block_hash,
+        });
+    }
+    Ok(BlockInfo {
+        block_hash,
+        depth,
+        back_links,
+    })
+}
+
+fn read_hash<R: Read>(reader: &mut R) -> io::Result<HeaderHash> {
+    let mut bytes = [0u8; HASH_SIZE];
+    reader.read_exact(&mut bytes)?;
+    HeaderHash::deserialize(&bytes[..])
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> io::Result<u8> {
+    let mut bytes = [0u8; 1];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes[0])
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_be_bytes(bytes))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+/// count the bytes read, to find the offsets of the records
+struct CountingReader<R> {
+    inner: R,
+    count: u64,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.count += read as u64;
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockcfg::{BlockBuilder, BlockDate};
+    use chain_core::property::{Block as _, ChainLength as _};
+
+    fn genesis() -> Block {
+        BlockBuilder::new().make_genesis_block()
+    }
+
+    fn child(parent: &Block, slot_id: u32) -> Block {
+        let mut builder = BlockBuilder::new();
+        builder
+            .date(BlockDate { epoch: 0, slot_id })
+            .parent(parent.id())
+            .chain_length(parent.header.chain_length().next());
+        builder.make_genesis_block()
+    }
+
+    fn chain(parent: &Block, first_slot_id: u32, len: u32) -> Vec<Block> {
+        let mut blocks: Vec<Block> = Vec::new();
+        for slot_id in first_slot_id..first_slot_id + len {
+            let block = child(blocks.last().unwrap_or(parent), slot_id);
+            blocks.push(block);
+        }
+        blocks
+    }
+
+    #[test]
+    fn blocks_and_tags_are_read_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("blocks.dat");
+        let block0 = genesis();
+        let blocks = chain(&block0, 1, 3);
+        {
+            let mut store = FlatFileBlockStore::new(&path).unwrap();
+            store.put_block(&block0).unwrap();
+            for block in blocks.iter() {
+                store.put_block(block).unwrap();
+            }
+            store.put_tag("HEAD", &blocks[2].id()).unwrap();
+
+            let (block, info) = store.get_block(&blocks[1].id()).unwrap();
+            assert_eq!(block.id(), blocks[1].id());
+            assert_eq!(info.depth, 2);
+            assert_eq!(info.parent_id(), blocks[0].id());
+        }
+
+        let store = FlatFileBlockStore::new(&path).unwrap();
+        for (depth, block) in Some(&block0).into_iter().chain(blocks.iter()).enumerate() {
+            let (stored, info) = store.get_block(&block.id()).unwrap();
+            assert_eq!(stored.id(), block.id());
+            assert_eq!(info.depth, depth as u64);
+        }
+        assert_eq!(store.get_tag("HEAD").unwrap(), Some(blocks[2].id()));
+        assert_eq!(store.get_tag("LAST_BLOCK").unwrap(), None);
+    }
+
+    #[test]
+    fn last_tag_record_wins() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("blocks.dat");
+        let block0 = genesis();
+        let blocks = chain(&block0, 1, 2);
+        {
+            let mut store = FlatFileBlockStore::new(&path).unwrap();
+            store.put_block(&block0).unwrap();
+            for block in blocks.iter() {
+                store.put_block(block).unwrap();
+            }
+            store.put_tag("HEAD", &blocks[1].id()).unwrap();
+            store.put_tag("HEAD", &blocks[0].id()).unwrap();
+            assert_eq!(store.get_tag("HEAD").unwrap(), Some(blocks[0].id()));
+        }
+
+        let store = FlatFileBlockStore::new(&path).unwrap();
+        assert_eq!(store.get_tag("HEAD").unwrap(), Some(blocks[0].id()));
+    }
+
+    #[test]
+    fn truncated_last_record_is_dropped_on_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("blocks.dat");
+        let block0 = genesis();
+        let blocks = chain(&block0, 1, 2);
+        {
+            let mut store = FlatFileBlockStore::new(&path).unwrap();
+            store.put_block(&block0).unwrap();
+            store.put_block(&blocks[0]).unwrap();
+            store.put_tag("HEAD", &blocks[0].id()).unwrap();
+            store.put_block(&blocks[1]).unwrap();
+        }
+        let len = fs::metadata(&path).unwrap().len();
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len - 3)
+            .unwrap();
+
+        {
+            let mut store = FlatFileBlockStore::new(&path).unwrap();
+            assert!(store.block_exists(&blocks[0].id()).unwrap());
+            assert!(!store.block_exists(&blocks[1].id()).unwrap());
+            assert_eq!(store.get_tag("HEAD").unwrap(), Some(blocks[0].id()));
+
+            // the records are appended after the last complete one
+            store.put_block(&blocks[1]).unwrap();
+            store.put_tag("HEAD", &blocks[1].id()).unwrap();
+        }
+
+        let store = FlatFileBlockStore::new(&path).unwrap();
+        let (block, _) = store.get_block(&blocks[1].id()).unwrap();
+        assert_eq!(block.id(), blocks[1].id());
+        assert_eq!(store.get_tag("HEAD").unwrap(), Some(blocks[1].id()));
+    }
+
+    #[test]
+    fn oversized_record_length_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("blocks.dat");
+        let mut record = vec![RECORD_TAG];
+        record.extend_from_slice(&u32::max_value().to_be_bytes());
+        record.extend_from_slice(&[0u8; 64]);
+        fs::write(&path, &record).unwrap();
+
+        match FlatFileBlockStore::new(&path) {
+            Err(error) => assert_eq!(error.kind(), io::ErrorKind::InvalidData),
+            Ok(_) => panic!("expected the storage to be rejected"),
+        }
+    }
+
+    #[test]
+    fn prune_forks_removes_the_forks_beyond_the_horizon() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("blocks.dat");
+        let mut store = FlatFileBlockStore::new(&path).unwrap();
+
+        let block0 = genesis();
+        let main = chain(&block0, 1, 5);
+        // branched off the block0, 5 blocks below the tip
+        let old_fork = chain(&block0, 10, 2);
+        // branched off the 4th block, 1 block below the tip
+        let recent_fork = chain(&main[3], 20, 1);
+
+        store.put_block(&block0).unwrap();
+        for block in main.iter().chain(old_fork.iter()).chain(recent_fork.iter()) {
+            store.put_block(block).unwrap();
+        }
+        store.put_tag("HEAD", &main[4].id()).unwrap();
+
+        let reclaimed = store.prune_forks(2).unwrap();
+        assert!(reclaimed > 0);
+
+        for block in old_fork.iter() {
+            assert!(!store.block_exists(&block.id()).unwrap());
+        }
+        for block in main.iter().chain(recent_fork.iter()) {
+            assert!(store.block_exists(&block.id()).unwrap());
+        }
+        assert!(store.block_exists(&block0.id()).unwrap());
+        assert_eq!(store.get_tag("HEAD").unwrap(), Some(main[4].id()));
+
+        // the pruned file is the one opened again
+        let store = FlatFileBlockStore::new(&path).unwrap();
+        assert!(!store.block_exists(&old_fork[0].id()).unwrap());
+        let (block, info) = store.get_block(&main[4].id()).unwrap();
+        assert_eq!(block.id(), main[4].id());
+        assert_eq!(info.depth, 5);
+        assert_eq!(store.get_tag("HEAD").unwrap(), Some(main[4].id()));
+    }
+
+    #[test]
+    fn prune_forks_keeps_the_ancestors_of_every_tag() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("blocks.dat");
+        let mut store = FlatFileBlockStore::new(&path).unwrap();
+
+        let block0 = genesis();
+        let main = chain(&block0, 1, 5);
+        let tagged_fork = chain(&block0, 10, 2);
+
+        store.put_block(&block0).unwrap();
+        for block in main.iter().chain(tagged_fork.iter()) {
+            store.put_block(block).unwrap();
+        }
+        store.put_tag("HEAD", &main[4].id()).unwrap();
+        store.put_tag("LAST_BLOCK", &tagged_fork[1].id()).unwrap();
+
+        assert_eq!(store.prune_forks(2).unwrap(), 0);
+        for block in main.iter().chain(tagged_fork.iter()) {
+            assert!(store.block_exists(&block.id()).unwrap());
+        }
+    }
+}
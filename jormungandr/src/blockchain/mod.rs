@@ -1,5 +1,8 @@
 mod branch;
+mod candidate;
 mod chain;
+mod chain_selection;
+mod flat_file_store;
 mod multiverse;
 mod process;
 mod reference;
@@ -8,10 +11,12 @@ mod storage;
 
 pub use self::{
     branch::{Branch, Branches},
-    chain::{Blockchain, Error, ErrorKind, PreCheckedHeader},
+    candidate::CandidateForest,
+    chain::{Blockchain, CacheLimits, Error, ErrorKind, PreCheckedHeader},
+    flat_file_store::FlatFileBlockStore,
     multiverse::Multiverse,
-    process::handle_input,
+    process::{handle_input, save_tip},
     reference::Ref,
     reference_cache::RefCache,
-    storage::Storage,
+    storage::{BlockStream, RebuildStorage, Storage, StoragePruning},
 };
@@ -1,19 +1,33 @@
-use crate::blockcfg::{ChainLength, HeaderHash, Ledger, Multiverse as MultiverseData};
+use crate::{
+    blockcfg::{ChainLength, HeaderHash, Ledger, Multiverse as MultiverseData},
+    stats_counter::CacheStats,
+};
 use chain_impl_mockchain::multiverse::GCRoot;
 use std::convert::Infallible;
 use tokio::{prelude::*, sync::lock::Lock};
 
 pub struct Multiverse<T> {
     inner: Lock<MultiverseData<T>>,
+    max_states: Option<usize>,
+    stats: CacheStats,
 }
 
 impl<T> Multiverse<T> {
-    pub fn new() -> Self {
+    /// create a new `Multiverse` with the given maximum number of states,
+    /// if any. The size and evictions of the multiverse are counted in
+    /// the given `CacheStats`.
+    pub fn new(max_states: Option<usize>, stats: CacheStats) -> Self {
         Multiverse {
             inner: Lock::new(MultiverseData::new()),
+            max_states,
+            stats,
         }
     }
 
+    pub fn stats(&self) -> &CacheStats {
+        &self.stats
+    }
+
     pub fn insert(
         &self,
         chain_length: ChainLength,
@@ -21,17 +35,41 @@ impl<T> Multiverse<T> {
         value: T,
     ) -> impl Future<Item = GCRoot, Error = Infallible> {
         let mut inner = self.inner.clone();
+        let stats = self.stats.clone();
 
-        future::poll_fn(move || Ok(inner.poll_lock()))
-            .map(move |mut guard| guard.insert(chain_length, hash, value))
+        future::poll_fn(move || Ok(inner.poll_lock())).map(move |mut guard| {
+            let gc_root = guard.insert(chain_length, hash, value);
+            stats.set_size(guard.nr_states());
+            gc_root
+        })
+    }
+
+    /// tell if the multiverse holds more states than its maximum
+    pub fn is_full(&self) -> impl Future<Item = bool, Error = Infallible> {
+        let mut inner = self.inner.clone();
+        let max_states = self.max_states;
+
+        future::poll_fn(move || Ok(inner.poll_lock())).map(move |guard| match max_states {
+            Some(max_states) => guard.nr_states() > max_states,
+            None => false,
+        })
     }
 }
 
 impl<T: Clone> Multiverse<T> {
     pub fn get(&self, hash: HeaderHash) -> impl Future<Item = Option<T>, Error = Infallible> {
         let mut inner = self.inner.clone();
+        let stats = self.stats.clone();
 
-        future::poll_fn(move || Ok(inner.poll_lock())).map(move |guard| guard.get(&hash).cloned())
+        future::poll_fn(move || Ok(inner.poll_lock())).map(move |guard| {
+            let value = guard.get(&hash).cloned();
+            if value.is_some() {
+                stats.add_hit();
+            } else {
+                stats.add_miss();
+            }
+            value
+        })
     }
 }
 
@@ -43,8 +81,33 @@ impl Multiverse<Ledger> {
     ///       the garbage collection for any `T`
     pub fn purge(&self) -> impl Future<Item = (), Error = Infallible> {
         let mut inner = self.inner.clone();
+        let stats = self.stats.clone();
+
+        future::poll_fn(move || Ok(inner.poll_lock())).map(move |mut guard| {
+            let before = guard.nr_states();
+            guard.gc();
+            let after = guard.nr_states();
+            stats.add_evictions(before.saturating_sub(after));
+            stats.set_size(after);
+        })
+    }
+
+    /// run the garbage collection of the multiverse if it holds more
+    /// states than its maximum.
+    ///
+    /// Only the states no longer referenced by a `GCRoot` are collected:
+    /// the states of the `Ref` evicted from the `RefCache`, least recently
+    /// used first.
+    pub fn purge_if_full(&self) -> impl Future<Item = (), Error = Infallible> {
+        let purge = self.purge();
 
-        future::poll_fn(move || Ok(inner.poll_lock())).map(|mut guard| guard.gc())
+        self.is_full().and_then(move |is_full| {
+            if is_full {
+                future::Either::A(purge)
+            } else {
+                future::Either::B(future::ok(()))
+            }
+        })
     }
 }
 
@@ -52,6 +115,71 @@ impl<T> Clone for Multiverse<T> {
     fn clone(&self) -> Self {
         Multiverse {
             inner: self.inner.clone(),
+            max_states: self.max_states,
+            stats: self.stats.clone(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockcfg::BlockBuilder;
+    use chain_core::property::ChainLength as _;
+
+    fn insert(multiverse: &Multiverse<u8>, n: u8) -> GCRoot {
+        let mut chain_length = BlockBuilder::new()
+            .make_genesis_block()
+            .header
+            .chain_length();
+        for _ in 0..n {
+            chain_length = chain_length.next();
+        }
+        multiverse
+            .insert(chain_length, HeaderHash::from([n; 32]), n)
+            .wait()
+            .unwrap()
+    }
+
+    #[test]
+    fn is_full_once_over_the_maximum_of_states() {
+        let stats = CacheStats::default();
+        let multiverse = Multiverse::new(Some(2), stats.clone());
+
+        let _root1 = insert(&multiverse, 1);
+        let _root2 = insert(&multiverse, 2);
+        assert!(!multiverse.is_full().wait().unwrap());
+
+        let _root3 = insert(&multiverse, 3);
+        assert!(multiverse.is_full().wait().unwrap());
+        assert_eq!(stats.size(), 3);
+    }
+
+    #[test]
+    fn unbounded_multiverse_is_never_full() {
+        let multiverse = Multiverse::new(None, CacheStats::default());
+
+        let _roots: Vec<_> = (1..10).map(|n| insert(&multiverse, n)).collect();
+
+        assert!(!multiverse.is_full().wait().unwrap());
+    }
+
+    #[test]
+    fn get_counts_hits_and_misses() {
+        let stats = CacheStats::default();
+        let multiverse = Multiverse::new(None, stats.clone());
+        let _root = insert(&multiverse, 1);
+
+        assert_eq!(
+            multiverse.get(HeaderHash::from([1; 32])).wait().unwrap(),
+            Some(1)
+        );
+        assert_eq!(
+            multiverse.get(HeaderHash::from([2; 32])).wait().unwrap(),
+            None
+        );
+
+        assert_eq!(stats.hits(), 1);
+        assert_eq!(stats.misses(), 1);
+    }
+}
@@ -1,36 +1,51 @@
-use super::{Blockchain, Branch, Error, ErrorKind, PreCheckedHeader, Ref};
+use super::{
+    chain_selection::{self, ComparisonResult},
+    Blockchain, Branch, Error, ErrorKind, PreCheckedHeader, Ref,
+};
 use crate::{
-    blockcfg::{Block, Epoch, Header, HeaderHash},
-    intercom::{self, BlockMsg, NetworkMsg, PropagateMsg},
-    leadership::NewEpochToSchedule,
-    network::p2p::topology::NodeId,
+    blockcfg::{Block, Epoch, Fragment, FragmentId, Header, HeaderHash},
+    intercom::{self, BlockMsg, NetworkMsg, PropagateMsg, TransactionMsg},
+    leadership::{LeadershipLogHandle, Logs as LeadershipLogs, NewEpochToSchedule},
+    network::{
+        chain_pull,
+        p2p::{comm::Misbehavior, topology::NodeId},
+    },
     stats_counter::StatsCounter,
     utils::{
         async_msg::MessageBox,
         task::{Input, TokioServiceInfo},
     },
 };
-use chain_core::property::{Block as _, HasHeader as _};
+use chain_core::property::{Block as _, Fragment as _, HasFragments as _, HasHeader as _};
 
 use futures::future::Either;
+use jormungandr_lib::interfaces::LeadershipLogStatus;
 use slog::Logger;
 use tokio::{prelude::*, sync::mpsc::Sender};
 
-use std::convert::identity;
+use std::convert::{identity, Infallible};
 
 pub fn handle_input(
     info: &TokioServiceInfo,
     blockchain: &mut Blockchain,
     blockchain_tip: &mut Branch,
-    _stats_counter: &StatsCounter,
+    stats_counter: &StatsCounter,
     new_epoch_announcements: &mut Sender<NewEpochToSchedule>,
     network_msg_box: &mut MessageBox<NetworkMsg>,
+    fragment_msg_box: &mut MessageBox<TransactionMsg>,
+    leadership_logs: &LeadershipLogs,
     input: Input<BlockMsg>,
 ) -> Result<(), ()> {
     let bquery = match input {
         Input::Shutdown => {
-            // TODO: is there some work to do here to clean up the
-            //       the state and make sure all state is saved properly
+            // all the previous inputs have been processed, make sure the
+            // current tip is saved before stopping. The blocks held for
+            // chain candidates are dropped, they will be fetched again.
+            save_tip(info.logger(), blockchain, blockchain_tip)
+                .wait()
+                .unwrap_or_else(|err| {
+                    error!(info.logger(), "cannot save the blockchain tip: {}", err)
+                });
             return Ok(());
         }
         Input::Input(msg) => msg,
@@ -54,16 +69,50 @@ pub fn handle_input(
                 )
             });
         }
-        BlockMsg::LeadershipBlock(block) => {
+        BlockMsg::LeadershipBlock(block, log) => {
+            let fragment_ids = block_fragment_ids(&block);
+            let block_id = block.header.hash();
             let future = process_leadership_block(info.logger(), blockchain.clone(), block);
-            let new_block_ref = future.wait().unwrap();
-            let header = new_block_ref.header().clone();
-            blockchain_tip.update_ref(new_block_ref).wait().unwrap();
-            network_msg_box
-                .try_send(NetworkMsg::Propagate(PropagateMsg::Block(header)))
-                .unwrap_or_else(|err| {
-                    error!(info.logger(), "cannot propagate block to network: {}", err)
-                });
+            let new_block_ref = match future.wait() {
+                Ok(new_block_ref) => new_block_ref,
+                Err(err) => {
+                    error!(
+                        info.logger(),
+                        "cannot process the block from leader event: {}", err
+                    );
+                    stats_counter.add_leadership_failure_cnt(1);
+                    log.set_status(LeadershipLogStatus::Rejected {
+                        reason: err.to_string(),
+                    })
+                    .wait()
+                    .unwrap();
+                    return Ok(());
+                }
+            };
+            let processed = process_applied_block(
+                info,
+                blockchain,
+                blockchain_tip,
+                network_msg_box,
+                fragment_msg_box,
+                leadership_logs,
+                new_block_ref,
+                fragment_ids,
+                Some(log.clone()),
+            );
+            if let Err(err) = processed {
+                error!(
+                    info.logger(),
+                    "cannot select the block from leader event: {}", err;
+                    "hash" => block_id.to_string()
+                );
+                stats_counter.add_leadership_failure_cnt(1);
+                log.set_status(LeadershipLogStatus::Rejected {
+                    reason: err.to_string(),
+                })
+                .wait()
+                .unwrap();
+            }
         }
         BlockMsg::AnnouncedBlock(header, node_id) => {
             let future = process_block_announcement(
@@ -74,34 +123,355 @@ pub fn handle_input(
                 network_msg_box.clone(),
                 info.logger().clone(),
             );
-            future.wait().unwrap();
+            future.wait().unwrap_or_else(|err| {
+                warn!(
+                    info.logger(),
+                    "invalid block announcement from {}: {}", node_id, err
+                );
+                if let Some(misbehavior) = block_error_misbehavior(&err) {
+                    network_msg_box
+                        .try_send(NetworkMsg::PeerMisbehaved(node_id, misbehavior))
+                        .unwrap_or_else(|err| {
+                            error!(info.logger(), "cannot report peer to network: {}", err)
+                        });
+                }
+            });
         }
         BlockMsg::NetworkBlock(block, reply) => {
+            let fragment_ids = block_fragment_ids(&block);
             let future = process_network_block(blockchain.clone(), block, info.logger().clone());
+            let res = future.wait().and_then(|maybe_applied| match maybe_applied {
+                Some(new_block_ref) => process_applied_block(
+                    info,
+                    blockchain,
+                    blockchain_tip,
+                    network_msg_box,
+                    fragment_msg_box,
+                    leadership_logs,
+                    new_block_ref,
+                    fragment_ids,
+                    None,
+                )
+                .map(|_selected| ()),
+                None => Ok(()),
+            });
+            match res {
+                Err(e) => reply.reply_error(network_block_error_into_reply(e)),
+                Ok(()) => reply.reply_ok(()),
+            }
+        }
+        BlockMsg::ChainHeaders(headers, reply) => {
+            let future = process_chain_headers_into_block_request(
+                blockchain.clone(),
+                stream::iter_ok(headers),
+                info.logger().clone(),
+            );
             match future.wait() {
-                Err(e) => {
-                    reply.reply_error(network_block_error_into_reply(e));
-                }
-                Ok(maybe_updated) => {
-                    if let Some(new_block_ref) = maybe_updated {
-                        let header = new_block_ref.header().clone();
-                        blockchain_tip.update_ref(new_block_ref).wait().unwrap();
+                Err(e) => reply.reply_error(network_block_error_into_reply(e)),
+                Ok(block_ids) => {
+                    // spread the requests of the blocks between the peers
+                    for chunk in block_ids.chunks(chain_pull::CHUNK_SIZE) {
                         network_msg_box
-                            .try_send(NetworkMsg::Propagate(PropagateMsg::Block(header)))
+                            .try_send(NetworkMsg::GetBlocks(chunk.to_vec()))
                             .unwrap_or_else(|err| {
-                                error!(info.logger(), "cannot propagate block to network: {}", err)
+                                error!(info.logger(), "cannot request blocks from network: {}", err)
                             });
                     }
                     reply.reply_ok(());
                 }
             }
         }
-        BlockMsg::ChainHeaders(headers, reply) => unimplemented!(),
     };
 
     Ok(())
 }
 
+/// select the applied block as the new tip if preferred by the chain
+/// selection rule, then apply in order the blocks of the chain candidates
+/// that were held waiting for it.
+///
+/// If the applied block comes from a leader event, the outcome of the chain
+/// selection is recorded in its `leadership_log` before the held blocks are
+/// applied, as they may orphan it again.
+///
+/// Returns `true` if the applied block has been selected as the new tip.
+/// Fails only if the chain selection of the applied block fails, the
+/// failures of the held blocks are logged.
+fn process_applied_block(
+    info: &TokioServiceInfo,
+    blockchain: &Blockchain,
+    blockchain_tip: &Branch,
+    network_msg_box: &mut MessageBox<NetworkMsg>,
+    fragment_msg_box: &mut MessageBox<TransactionMsg>,
+    leadership_logs: &LeadershipLogs,
+    new_block_ref: Ref,
+    fragment_ids: Vec<FragmentId>,
+    mut leadership_log: Option<LeadershipLogHandle>,
+) -> Result<bool, Error> {
+    let mut applied = vec![(new_block_ref, fragment_ids)];
+    let mut selected = None;
+
+    while let Some((block_ref, fragment_ids)) = applied.pop() {
+        let block_id = block_ref.hash();
+        let header = block_ref.header().clone();
+        let tip_updated = process_new_ref(
+            info.logger().clone(),
+            blockchain.clone(),
+            blockchain_tip.clone(),
+            leadership_logs.clone(),
+            block_ref.clone(),
+        )
+        .wait();
+        let tip_updated = match (tip_updated, selected) {
+            (Ok(tip_updated), _) => tip_updated,
+            (Err(err), None) => return Err(err),
+            (Err(err), Some(_)) => {
+                warn!(
+                    info.logger(),
+                    "cannot select the block of a chain candidate: {}", err
+                );
+                continue;
+            }
+        };
+        selected.get_or_insert(tip_updated.is_some());
+        if let Some(log) = leadership_log.take() {
+            let status = if tip_updated.is_some() {
+                LeadershipLogStatus::Block {
+                    block: block_id.into(),
+                    fragments: fragment_ids.len(),
+                }
+            } else {
+                // the tip has moved to a competing branch in the meantime
+                LeadershipLogStatus::Orphaned {
+                    block: block_id.into(),
+                }
+            };
+            log.set_status(status).wait().unwrap();
+        }
+        if let Some(switch) = tip_updated {
+            let mut fragment_ids = fragment_ids;
+            for block in switch.applied.iter() {
+                fragment_ids.extend(block_fragment_ids(block));
+            }
+            let rolled_back = switch
+                .rolled_back
+                .into_iter()
+                .flat_map(|block| block.fragments().cloned().collect::<Vec<_>>())
+                .collect();
+            notify_new_tip(
+                info.logger(),
+                fragment_msg_box,
+                block_ref,
+                fragment_ids,
+                rolled_back,
+            );
+            network_msg_box
+                .try_send(NetworkMsg::Propagate(PropagateMsg::Block(header)))
+                .unwrap_or_else(|err| {
+                    error!(info.logger(), "cannot propagate block to network: {}", err)
+                });
+        }
+
+        let held_blocks = blockchain
+            .candidates()
+            .apply_block(block_id)
+            .wait()
+            .unwrap();
+        for block in held_blocks {
+            let fragment_ids = block_fragment_ids(&block);
+            let future = process_network_block(blockchain.clone(), block, info.logger().clone());
+            match future.wait() {
+                Ok(Some(block_ref)) => applied.push((block_ref, fragment_ids)),
+                Ok(None) => {}
+                Err(err) => warn!(
+                    info.logger(),
+                    "cannot apply the block of a chain candidate: {}", err
+                ),
+            }
+        }
+    }
+
+    Ok(selected.unwrap_or(false))
+}
+
+/// the blocks on each side of the fork when the tip switches to a
+/// competing branch, from the newest to the oldest
+#[derive(Default)]
+struct Switch {
+    /// the blocks of the previous tip's branch rolled back by the switch
+    rolled_back: Vec<Block>,
+    /// the blocks of the new tip's branch, excluding the new tip, which were
+    /// not in the previous tip's branch
+    applied: Vec<Block>,
+}
+
+/// apply the chain selection rule to the newly applied block and update
+/// the tip of the blockchain if the block is selected.
+///
+/// The future returns the blocks switched if the tip has been updated. The
+/// blocks of the previous tip's branch rolled back by the switch are marked
+/// as orphaned in the leadership logs, and the blocks of the new tip's
+/// branch previously orphaned are marked as part of the branch again.
+fn process_new_ref(
+    logger: Logger,
+    blockchain: Blockchain,
+    mut blockchain_tip: Branch,
+    leadership_logs: LeadershipLogs,
+    candidate: Ref,
+) -> impl Future<Item = Option<Switch>, Error = Error> {
+    let mut tip_storage = blockchain.clone();
+    let storage = blockchain.storage().clone();
+    blockchain_tip
+        .get_ref()
+        .map_err(|_: std::convert::Infallible| unreachable!())
+        .and_then(move |current| {
+            chain_selection::compare_against(blockchain.storage(), &current, &candidate)
+                .map(move |comparison| (current, candidate, comparison))
+        })
+        .and_then(move |(current, candidate, comparison)| match comparison {
+            ComparisonResult::PreferCurrent => {
+                debug!(
+                    logger,
+                    "block is not selected as the new tip";
+                    "hash" => candidate.hash().to_string(),
+                    "chain_length" => ?candidate.chain_length(),
+                    "tip" => current.hash().to_string(),
+                    "tip_chain_length" => ?current.chain_length(),
+                );
+                Either::A(future::ok(None))
+            }
+            ComparisonResult::PreferCandidate { rollback_depth } => {
+                if rollback_depth > 0 {
+                    info!(
+                        logger,
+                        "switching to a competing branch";
+                        "rollback_depth" => rollback_depth,
+                        "hash" => candidate.hash().to_string(),
+                        "chain_length" => ?candidate.chain_length(),
+                        "previous_tip" => current.hash().to_string(),
+                        "previous_tip_chain_length" => ?current.chain_length(),
+                    );
+                }
+                // the block is already in the storage, so the HEAD tag can
+                // safely point to it
+                let previous_tip = current.hash();
+                let candidate_hash = candidate.hash();
+                // the abandoned forks are pruned once per epoch of the tip
+                let new_epoch = candidate.block_date().epoch > current.block_date().epoch;
+                let pruned_blockchain = tip_storage.clone();
+                let prune_logger = logger.clone();
+                Either::B(
+                    tip_storage
+                        .put_tip(&candidate)
+                        .and_then(move |()| {
+                            blockchain_tip
+                                .update_ref(candidate)
+                                .map_err(|_: std::convert::Infallible| unreachable!())
+                        })
+                        .and_then(move |_| {
+                            if rollback_depth == 0 {
+                                return Either::A(future::ok(Switch::default()));
+                            }
+                            Either::B(
+                                storage
+                                    .fork_branches(previous_tip, candidate_hash)
+                                    .map_err(|e| {
+                                        Error::with_chain(e, "cannot find the rolled back blocks")
+                                    })
+                                    .map(|(rolled_back, applied)| Switch {
+                                        rolled_back,
+                                        applied,
+                                    }),
+                            )
+                        })
+                        .and_then(move |switch| {
+                            if !new_epoch {
+                                return Either::A(future::ok(switch));
+                            }
+                            Either::B(pruned_blockchain.prune_storage().then(move |res| {
+                                match res {
+                                    Ok(Some(reclaimed)) => info!(
+                                        prune_logger,
+                                        "pruned the abandoned forks of the storage";
+                                        "reclaimed_bytes" => reclaimed
+                                    ),
+                                    Ok(None) => (),
+                                    Err(err) => {
+                                        warn!(prune_logger, "cannot prune the storage: {}", err)
+                                    }
+                                }
+                                Ok(switch)
+                            }))
+                        })
+                        .and_then(move |switch| {
+                            let orphaned = switch
+                                .rolled_back
+                                .iter()
+                                .map(|block| block.header.hash().into())
+                                .collect();
+                            let adopted = switch
+                                .applied
+                                .iter()
+                                .map(|block| {
+                                    (block.header.hash().into(), block.fragments().count())
+                                })
+                                .collect();
+                            leadership_logs
+                                .mark_orphaned(orphaned)
+                                .and_then(move |()| leadership_logs.mark_adopted(adopted))
+                                .map_err(|()| unreachable!())
+                                .map(move |()| Some(switch))
+                        }),
+                )
+            }
+        })
+}
+
+/// store the current tip of the blockchain in the `HEAD` tag
+pub fn save_tip(
+    logger: &Logger,
+    blockchain: &Blockchain,
+    blockchain_tip: &Branch,
+) -> impl Future<Item = (), Error = Error> {
+    let mut blockchain = blockchain.clone();
+    let logger = logger.clone();
+    blockchain_tip
+        .get_ref()
+        .map_err(|_: std::convert::Infallible| unreachable!())
+        .and_then(move |tip| {
+            blockchain.put_tip(&tip).map(move |()| {
+                info!(logger, "blockchain tip saved"; "hash" => tip.hash().to_string());
+            })
+        })
+}
+
+fn block_fragment_ids(block: &Block) -> Vec<FragmentId> {
+    block.fragments().map(|fragment| fragment.id()).collect()
+}
+
+/// let the fragment task clean up its pool against the new tip and take
+/// back the fragments of the rolled back blocks
+fn notify_new_tip(
+    logger: &Logger,
+    fragment_msg_box: &mut MessageBox<TransactionMsg>,
+    new_tip: Ref,
+    fragment_ids: Vec<FragmentId>,
+    rolled_back: Vec<Fragment>,
+) {
+    fragment_msg_box
+        .try_send(TransactionMsg::UpdateTip(
+            new_tip,
+            fragment_ids,
+            rolled_back,
+        ))
+        .unwrap_or_else(|err| {
+            error!(
+                logger,
+                "cannot notify the fragment task of the new tip: {}", err
+            )
+        });
+}
+
 pub fn handle_end_of_epoch(
     logger: Logger,
     new_epoch_announcements: Sender<NewEpochToSchedule>,
@@ -113,15 +483,14 @@ pub fn handle_end_of_epoch(
     blockchain_tip
         .get_ref()
         .map_err(|_: std::convert::Infallible| unreachable!())
-        .and_then(move |ref_tip| {
-            let (new_schedule, new_parameters, time_frame, _) =
-                blockchain.new_epoch_leadership_from(epoch, ref_tip);
-
+        .and_then(move |ref_tip| blockchain.new_epoch_leadership_from(epoch, ref_tip))
+        .and_then(move |(new_schedule, new_parameters, time_frame, era, _)| {
             new_epoch_announcements
                 .send(NewEpochToSchedule {
                     new_schedule,
                     new_parameters,
                     time_frame: (*time_frame).clone(),
+                    era: (*era).clone(),
                 })
                 .map_err(move |_err| {
                     crit!(
@@ -226,19 +595,32 @@ pub fn process_network_block(
     logger: Logger,
 ) -> impl Future<Item = Option<Ref>, Error = Error> {
     let mut end_blockchain = blockchain.clone();
+    let candidates = blockchain.candidates().clone();
     let header = block.header();
     blockchain
         .pre_check_header(header)
         .and_then(move |pre_checked| match pre_checked {
             PreCheckedHeader::AlreadyPresent { .. } => {
                 debug!(logger, "block is already present");
-                Either::A(future::ok(None))
+                Either::A(Either::A(future::ok(None)))
             }
             PreCheckedHeader::MissingParent { header, .. } => {
-                debug!(logger, "block is missing a locally stored parent");
-                Either::A(future::err(
-                    ErrorKind::MissingParentBlockFromStorage(header).into(),
-                ))
+                // the block may belong to a chain of headers being
+                // synchronised, in which case it is held until the
+                // parent block is applied
+                let hold_block = candidates
+                    .hold_block(block)
+                    .map_err(|_: Infallible| unreachable!())
+                    .and_then(move |held| {
+                        if held {
+                            debug!(logger, "block is held until its parent is applied");
+                            Ok(None)
+                        } else {
+                            debug!(logger, "block is missing a locally stored parent");
+                            Err(ErrorKind::MissingParentBlockFromStorage(header).into())
+                        }
+                    });
+                Either::A(Either::B(hold_block))
             }
             PreCheckedHeader::HeaderWithCache { header, parent_ref } => {
                 let post_check_and_apply = blockchain
@@ -255,33 +637,54 @@ pub fn process_network_block(
         })
 }
 
+/// the misbehaviour of the peer that sent a block failing to be processed,
+/// if the failure is caused by the block itself.
+fn block_error_misbehavior(err: &Error) -> Option<Misbehavior> {
+    use super::ErrorKind::*;
+
+    match err.0 {
+        Ledger(_) | BlockHeaderVerificationFailed(_) | CannotApplyBlock => {
+            Some(Misbehavior::InvalidBlock)
+        }
+        MissingParentBlockFromStorage(_) => Some(Misbehavior::UnconnectedBlock),
+        _ => None,
+    }
+}
+
 fn network_block_error_into_reply(err: Error) -> intercom::Error {
     use super::ErrorKind::*;
 
     match err.0 {
         Storage(e) => intercom::Error::failed(e),
-        Ledger(e) => intercom::Error::failed_precondition(e),
+        Ledger(e) => intercom::Error::invalid_argument(e),
         Block0(e) => intercom::Error::failed(e),
         MissingParentBlockFromStorage(_) => intercom::Error::failed_precondition(err.to_string()),
-        BlockHeaderVerificationFailed(_) => intercom::Error::invalid_argument(err.to_string()),
+        BlockHeaderVerificationFailed(_) | CannotApplyBlock => {
+            intercom::Error::invalid_argument(err.to_string())
+        }
         _ => intercom::Error::failed(err.to_string()),
     }
 }
 
+/// pre-validate the chain of headers and add them to the chain candidates,
+/// after dropping the expired candidates.
+///
+/// The future returns the hashes of the blocks to fetch from the network.
 pub fn process_chain_headers_into_block_request<S>(
     mut blockchain: Blockchain,
     headers: S,
     logger: Logger,
 ) -> impl Future<Item = Vec<HeaderHash>, Error = Error>
 where
-    S: Stream<Item = Header>,
+    S: Stream<Item = Header, Error = Error>,
 {
-    headers
-        .map_err(|e| {
-            // TODO: map the incoming stream error to the result error
-            unimplemented!()
-        })
+    let purge = blockchain
+        .candidates()
+        .purge()
+        .map_err(|_: Infallible| unreachable!());
+    let new_headers = headers
         .and_then(move |header| {
+            let candidates = blockchain.candidates().clone();
             blockchain
                 .pre_check_header(header)
                 .and_then(move |pre_checked| match pre_checked {
@@ -289,23 +692,28 @@ where
                         // The block is already present. This may happen
                         // if the peer has started from an earlier checkpoint
                         // than our tip, so ignore this and proceed.
-                        Ok(None)
+                        Either::A(future::ok(None))
                     }
                     PreCheckedHeader::MissingParent { header, .. } => {
-                        // TODO: this fails on the first header after the
-                        // immediate descendant of the local tip. Need branch storage
-                        // that would store the whole header chain without blocks,
-                        // so that the chain can be pre-validated first and blocks
-                        // fetched afterwards in arbitrary order.
-                        Err(ErrorKind::MissingParentBlockFromStorage(header).into())
+                        // The parent is expected to be one of the headers
+                        // received earlier in the chain.
+                        Either::B(Either::A(candidates.add_unconnected_header(header)))
                     }
-                    PreCheckedHeader::HeaderWithCache { header, parent_ref } => {
+                    PreCheckedHeader::HeaderWithCache { header, .. } => {
                         // TODO: limit the headers to the single epoch
                         // before pausing to retrieve blocks.
-                        Ok(Some(header.hash()))
+                        Either::B(Either::B(
+                            candidates
+                                .add_connected_header(header)
+                                .map_err(|_: Infallible| unreachable!()),
+                        ))
                     }
                 })
         })
         .filter_map(identity)
-        .collect()
+        .collect();
+    purge.and_then(move |()| new_headers).map(move |block_ids| {
+        debug!(logger, "chain headers pre-validated"; "new_headers" => block_ids.len());
+        block_ids
+    })
 }
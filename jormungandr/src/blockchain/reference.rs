@@ -2,7 +2,7 @@ use crate::blockcfg::{
     BlockDate, ChainLength, Header, HeaderHash, Leadership, Ledger, LedgerParameters,
 };
 use chain_impl_mockchain::multiverse::GCRoot;
-use chain_time::TimeFrame;
+use chain_time::{TimeEra, TimeFrame};
 use std::sync::Arc;
 
 /// a reference to a block in the blockchain
@@ -16,6 +16,10 @@ pub struct Ref {
     /// the time frame applicable in the current branch of the blockchain
     time_frame: Arc<TimeFrame>,
 
+    /// the era of the current epoch, to convert the slots of the time frame
+    /// to and from block dates
+    era: Arc<TimeEra>,
+
     /// the leadership used to validate the current header's leader
     ///
     /// this object will be shared between different Ref of the same epoch
@@ -43,6 +47,7 @@ impl Ref {
         ledger_pointer: GCRoot,
         ledger: Arc<Ledger>,
         time_frame: Arc<TimeFrame>,
+        era: Arc<TimeEra>,
         epoch_leadership_schedule: Arc<Leadership>,
         epoch_ledger_parameters: Arc<LedgerParameters>,
         header: Header,
@@ -57,6 +62,7 @@ impl Ref {
             ledger_gc: Arc::new(ledger_pointer),
             ledger,
             time_frame,
+            era,
             epoch_leadership_schedule,
             epoch_ledger_parameters,
             header,
@@ -100,6 +106,12 @@ impl Ref {
         &self.time_frame
     }
 
+    /// get the era of the current epoch, in which the block dates relate
+    /// to the slots of the `time_frame`
+    pub fn era(&self) -> &Arc<TimeEra> {
+        &self.era
+    }
+
     pub fn epoch_leadership_schedule(&self) -> &Arc<Leadership> {
         &self.epoch_leadership_schedule
     }
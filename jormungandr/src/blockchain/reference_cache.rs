@@ -1,5 +1,9 @@
-use crate::{blockcfg::HeaderHash, blockchain::Ref};
-use std::{collections::HashMap, convert::Infallible, time::Duration};
+use crate::{blockcfg::HeaderHash, blockchain::Ref, stats_counter::CacheStats};
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::Infallible,
+    time::Duration,
+};
 use tokio::{
     prelude::*,
     sync::lock::Lock,
@@ -13,30 +17,53 @@ use tokio::{
 /// The cache expired [`Ref`] will be removed only if the [`Ref`]'s
 /// TTL has expired and [`purge`] has been called and has completed.
 ///
+/// If the cache has a maximum number of entries, the least recently
+/// accessed [`Ref`] is removed when a new one is inserted in the full cache.
+///
 /// [`Ref`]: ./struct.Ref.html
 /// [`purge`]: ./struct.Ref.html#method.purge
 #[derive(Clone)]
 pub struct RefCache {
-    inner: Lock<RefCacheData>,
+    inner: Lock<RefCacheData<Ref>>,
+    stats: CacheStats,
 }
 
 /// cache of already loaded in-memory block `Ref`
-struct RefCacheData {
-    entries: HashMap<HeaderHash, (Ref, delay_queue::Key)>,
+struct RefCacheData<V> {
+    entries: HashMap<HeaderHash, RefCacheEntry<V>>,
     expirations: DelayQueue<HeaderHash>,
+    /// the keys of the entries, by the order of their last access
+    accesses: BTreeMap<u64, HeaderHash>,
+    next_access: u64,
 
     ttl: Duration,
+    max_entries: Option<usize>,
+    stats: CacheStats,
+}
+
+struct RefCacheEntry<V> {
+    value: V,
+    expiration: delay_queue::Key,
+    last_access: u64,
 }
 
 impl RefCache {
-    /// create a new `RefCache` with the given expiration `Duration`.
+    /// create a new `RefCache` with the given expiration `Duration`
+    /// and maximum number of entries, if any.
     ///
-    pub fn new(ttl: Duration) -> Self {
+    /// The hits, misses, evictions and size of the cache are counted
+    /// in the given `CacheStats`.
+    pub fn new(ttl: Duration, max_entries: Option<usize>, stats: CacheStats) -> Self {
         RefCache {
-            inner: Lock::new(RefCacheData::new(ttl)),
+            inner: Lock::new(RefCacheData::new(ttl, max_entries, stats.clone())),
+            stats,
         }
     }
 
+    pub fn stats(&self) -> &CacheStats {
+        &self.stats
+    }
+
     /// return a future that will attempt to insert the given [`Ref`]
     /// in the cache.
     ///
@@ -90,42 +117,199 @@ impl RefCache {
     }
 }
 
-impl RefCacheData {
-    fn new(ttl: Duration) -> Self {
+impl<V> RefCacheData<V> {
+    fn new(ttl: Duration, max_entries: Option<usize>, stats: CacheStats) -> Self {
         RefCacheData {
             entries: HashMap::new(),
             expirations: DelayQueue::new(),
+            accesses: BTreeMap::new(),
+            next_access: 0,
             ttl,
+            max_entries,
+            stats,
         }
     }
 
-    fn insert(&mut self, key: HeaderHash, value: Ref) {
-        let delay = self.expirations.insert(key.clone(), self.ttl);
+    fn insert(&mut self, key: HeaderHash, value: V) {
+        self.remove(&key);
+
+        let expiration = self.expirations.insert(key.clone(), self.ttl);
+        let last_access = self.new_access(key.clone());
+        self.entries.insert(
+            key,
+            RefCacheEntry {
+                value,
+                expiration,
+                last_access,
+            },
+        );
 
-        self.entries.insert(key, (value, delay));
+        if let Some(max_entries) = self.max_entries {
+            let mut evicted = 0;
+            while self.entries.len() > max_entries {
+                let least_recent = match self.accesses.values().next() {
+                    Some(least_recent) => least_recent.clone(),
+                    None => break,
+                };
+                self.remove(&least_recent);
+                evicted += 1;
+            }
+            self.stats.add_evictions(evicted);
+        }
+        self.stats.set_size(self.entries.len());
     }
 
-    fn get(&mut self, key: &HeaderHash) -> Option<&Ref> {
-        if let Some((v, k)) = self.entries.get(key) {
-            self.expirations.reset(k, self.ttl);
+    fn get(&mut self, key: &HeaderHash) -> Option<&V> {
+        let last_access = match self.entries.get(key) {
+            None => {
+                self.stats.add_miss();
+                return None;
+            }
+            Some(entry) => entry.last_access,
+        };
+        self.stats.add_hit();
 
-            Some(v)
-        } else {
-            None
-        }
+        self.accesses.remove(&last_access);
+        let last_access = self.new_access(key.clone());
+
+        let entry = self.entries.get_mut(key).unwrap();
+        entry.last_access = last_access;
+        self.expirations.reset(&entry.expiration, self.ttl);
+
+        Some(&entry.value)
     }
 
     fn remove(&mut self, key: &HeaderHash) {
-        if let Some((_, cache_key)) = self.entries.remove(key) {
-            self.expirations.remove(&cache_key);
+        if let Some(entry) = self.entries.remove(key) {
+            self.expirations.remove(&entry.expiration);
+            self.accesses.remove(&entry.last_access);
         }
+        self.stats.set_size(self.entries.len());
+    }
+
+    fn new_access(&mut self, key: HeaderHash) -> u64 {
+        let access = self.next_access;
+        self.next_access += 1;
+        self.accesses.insert(access, key);
+        access
     }
 
     fn poll_purge(&mut self) -> Poll<(), timer::Error> {
         while let Some(entry) = try_ready!(self.expirations.poll()) {
-            self.entries.remove(entry.get_ref());
+            if let Some(removed) = self.entries.remove(entry.get_ref()) {
+                self.accesses.remove(&removed.last_access);
+            }
+            self.stats.set_size(self.entries.len());
         }
 
         Ok(Async::Ready(()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::runtime::current_thread::Runtime;
+
+    fn key(n: u8) -> HeaderHash {
+        HeaderHash::from([n; 32])
+    }
+
+    fn keys(data: &RefCacheData<u8>) -> Vec<HeaderHash> {
+        data.accesses.values().cloned().collect()
+    }
+
+    /// run the test in a runtime, the expirations need a timer
+    fn with_runtime<F: FnOnce()>(test: F) {
+        Runtime::new()
+            .unwrap()
+            .block_on(future::lazy(|| {
+                test();
+                Ok::<(), ()>(())
+            }))
+            .unwrap();
+    }
+
+    #[test]
+    fn least_recently_accessed_entry_is_evicted_first() {
+        with_runtime(|| {
+            let stats = CacheStats::default();
+            let mut data = RefCacheData::new(Duration::from_secs(60), Some(2), stats.clone());
+
+            data.insert(key(1), 1);
+            data.insert(key(2), 2);
+            // the access moves the first entry after the second one
+            assert_eq!(data.get(&key(1)), Some(&1));
+            data.insert(key(3), 3);
+
+            assert_eq!(data.get(&key(2)), None);
+            assert_eq!(keys(&data), vec![key(1), key(3)]);
+            assert_eq!(stats.evictions(), 1);
+            assert_eq!(stats.size(), 2);
+            assert_eq!(stats.hits(), 1);
+            assert_eq!(stats.misses(), 1);
+        });
+    }
+
+    #[test]
+    fn inserting_again_refreshes_the_entry() {
+        with_runtime(|| {
+            let stats = CacheStats::default();
+            let mut data = RefCacheData::new(Duration::from_secs(60), Some(2), stats.clone());
+
+            data.insert(key(1), 1);
+            data.insert(key(2), 2);
+            data.insert(key(1), 10);
+            data.insert(key(3), 3);
+
+            assert_eq!(data.get(&key(1)), Some(&10));
+            assert_eq!(data.get(&key(2)), None);
+            assert_eq!(data.entries.len(), 2);
+            assert_eq!(stats.evictions(), 1);
+        });
+    }
+
+    #[test]
+    fn unbounded_cache_evicts_nothing() {
+        with_runtime(|| {
+            let stats = CacheStats::default();
+            let mut data = RefCacheData::new(Duration::from_secs(60), None, stats.clone());
+
+            for n in 0..10 {
+                data.insert(key(n), n);
+            }
+
+            assert_eq!(data.entries.len(), 10);
+            assert_eq!(stats.evictions(), 0);
+            assert_eq!(stats.size(), 10);
+        });
+    }
+
+    #[test]
+    fn purge_removes_the_expired_entries() {
+        let stats = CacheStats::default();
+        let mut runtime = Runtime::new().unwrap();
+        let data = runtime
+            .block_on(future::lazy(|| {
+                let mut data = RefCacheData::new(Duration::from_millis(10), None, stats.clone());
+                data.insert(key(1), 1);
+                data.insert(key(2), 2);
+                Ok::<_, ()>(data)
+            }))
+            .unwrap();
+
+        let mut data = runtime
+            .block_on(
+                timer::Delay::new(std::time::Instant::now() + Duration::from_millis(50))
+                    .map(move |()| data),
+            )
+            .unwrap();
+        runtime
+            .block_on(future::poll_fn(|| data.poll_purge()))
+            .unwrap();
+
+        assert!(data.entries.is_empty());
+        assert!(data.accesses.is_empty());
+        assert_eq!(stats.size(), 0);
+    }
+}
@@ -4,15 +4,60 @@ use crate::{
 };
 use chain_storage::{
     error::Error as StorageError,
+    memory::MemoryBlockStore,
     store::{for_path_to_nth_ancestor, BlockInfo},
 };
-use std::ops::Deref as _;
+use std::{collections::HashSet, ops::Deref as _};
 use tokio::prelude::*;
 use tokio::sync::lock::{Lock, LockGuard};
 
+/// shared access to the node's block storage
+///
+/// The `chain-storage` `BlockStore` cannot remove blocks: the blocks of the
+/// abandoned forks are pruned by writing a new storage with the remaining
+/// blocks only, see `prune_forks`. With the memory backend they are kept
+/// until the node stops.
 #[derive(Clone)]
 pub struct Storage {
     inner: Lock<NodeStorage>,
+    pruning: Option<Lock<StoragePruning>>,
+}
+
+/// a storage backend which can be written again from scratch, to prune it
+pub trait RebuildStorage: Send {
+    /// open a new and empty storage, next to the current one
+    fn create(&self) -> Result<NodeStorage, StorageError>;
+
+    /// replace the current storage with the one opened by `create`, both
+    /// being closed, and open it.
+    ///
+    /// Returns the storage and the number of bytes reclaimed.
+    fn replace(&self) -> Result<(NodeStorage, u64), StorageError>;
+
+    /// open the current storage again, if it could not be replaced
+    fn reopen(&self) -> Result<NodeStorage, StorageError>;
+}
+
+/// the pruning of the blocks of the abandoned forks
+pub struct StoragePruning {
+    /// the forks which branched off more than this number of blocks
+    /// below the tip are removed
+    depth: u64,
+    rebuild: Box<dyn RebuildStorage>,
+    /// the blocks put in the storage since the last pruning, with their
+    /// depth: those still above the pruning horizon are kept, whether
+    /// they are on the main chain or not
+    recent_blocks: Vec<(HeaderHash, u64)>,
+}
+
+impl StoragePruning {
+    pub fn new(depth: u64, rebuild: Box<dyn RebuildStorage>) -> Self {
+        StoragePruning {
+            depth,
+            rebuild,
+            recent_blocks: Vec::new(),
+        }
+    }
 }
 
 pub struct BlockStream {
@@ -26,9 +71,16 @@ impl Storage {
     pub fn new(storage: NodeStorage) -> Self {
         Storage {
             inner: Lock::new(storage),
+            pruning: None,
         }
     }
 
+    /// prune the storage with `prune_forks`. To be set before the storage
+    /// is shared.
+    pub fn set_pruning(&mut self, pruning: StoragePruning) {
+        self.pruning = Some(Lock::new(pruning));
+    }
+
     #[deprecated(since = "new blockchain API", note = "use the stream iterator instead")]
     pub fn get_inner(&self) -> impl Future<Item = LockGuard<NodeStorage>, Error = StorageError> {
         let mut inner = self.inner.clone();
@@ -111,15 +163,71 @@ impl Storage {
     }
 
     pub fn put_block(&mut self, block: Block) -> impl Future<Item = (), Error = StorageError> {
+        use chain_core::property::Block as _;
+
         let mut inner = self.inner.clone();
+        let pruning = self.pruning.clone();
+        let record_depth = pruning.is_some();
+        let block_hash = block.id();
+        let recent_hash = block_hash.clone();
 
-        future::poll_fn(move || Ok(inner.poll_lock())).and_then(move |mut guard| {
-            match guard.put_block(&block) {
-                Err(StorageError::BlockNotFound) => unreachable!(),
-                Err(error) => future::err(error),
-                Ok(()) => future::ok(()),
-            }
-        })
+        future::poll_fn(move || Ok(inner.poll_lock()))
+            .and_then(move |mut guard| {
+                match guard.put_block(&block) {
+                    Err(StorageError::BlockNotFound) => unreachable!(),
+                    Err(error) => return Err(error),
+                    Ok(()) => (),
+                }
+                // the depth is only needed to prune the storage
+                if record_depth {
+                    guard
+                        .get_block_info(&block_hash)
+                        .map(|block_info| Some(block_info.depth))
+                } else {
+                    Ok(None)
+                }
+            })
+            .and_then(move |depth| match (pruning, depth) {
+                (Some(mut pruning), Some(depth)) => future::Either::A(
+                    future::poll_fn(move || Ok(pruning.poll_lock())).map(move |mut guard| {
+                        guard.recent_blocks.push((recent_hash, depth));
+                    }),
+                ),
+                _ => future::Either::B(future::ok(())),
+            })
+    }
+
+    /// remove the blocks of the forks which branched off more than the
+    /// pruning depth below the deepest of the given tags. The blocks of
+    /// the chains of the tags are kept, as well as the blocks recently put
+    /// in the storage which are above the pruning horizon, and their
+    /// ancestors.
+    ///
+    /// The remaining blocks and the tags are copied in a new storage which
+    /// then replaces the current one.
+    ///
+    /// Returns the number of bytes reclaimed, or `None` if the storage is
+    /// not pruned.
+    pub fn prune_forks(
+        &self,
+        tags: Vec<String>,
+    ) -> impl Future<Item = Option<u64>, Error = StorageError> {
+        let mut inner = self.inner.clone();
+        let mut pruning = match self.pruning.clone() {
+            None => return future::Either::A(future::ok(None)),
+            Some(pruning) => pruning,
+        };
+
+        future::Either::B(
+            future::poll_fn(move || Ok(pruning.poll_lock()))
+                .and_then(move |pruning| {
+                    future::poll_fn(move || Ok(inner.poll_lock()))
+                        .map(move |store| (pruning, store))
+                })
+                .and_then(move |(mut pruning, mut store)| {
+                    rebuild_without_forks(&mut store, &mut pruning, &tags).map(Some)
+                }),
+        )
     }
 
     pub fn stream_from_to(
@@ -162,6 +270,173 @@ impl Storage {
             Ok(checkpoints)
         })
     }
+
+    /// get the number of blocks to roll back from `tip` to reach the closest
+    /// ancestor it shares with `candidate`.
+    ///
+    /// Returns `None` if the common ancestor is further than `max_distance`
+    /// blocks away from `tip`.
+    pub fn rollback_distance(
+        &self,
+        tip: HeaderHash,
+        candidate: HeaderHash,
+        max_distance: u64,
+    ) -> impl Future<Item = Option<u64>, Error = StorageError> {
+        let mut inner = self.inner.clone();
+        future::poll_fn(move || Ok(inner.poll_lock())).and_then(move |store| {
+            find_common_ancestor(&*store, &tip, &candidate, Some(max_distance))
+                .map(|ancestor| ancestor.map(|(_, distance)| distance))
+        })
+    }
+
+    /// get the blocks of the two branches of a fork, from the newest to
+    /// the oldest and excluding their closest common ancestor: the blocks
+    /// leading to `tip` and the blocks leading to `candidate`, excluding
+    /// `candidate` itself.
+    pub fn fork_branches(
+        &self,
+        tip: HeaderHash,
+        candidate: HeaderHash,
+    ) -> impl Future<Item = (Vec<Block>, Vec<Block>), Error = StorageError> {
+        let mut inner = self.inner.clone();
+        future::poll_fn(move || Ok(inner.poll_lock())).and_then(move |store| {
+            let (ancestor, _) = find_common_ancestor(&*store, &tip, &candidate, None)?
+                .ok_or(StorageError::BlockNotFound)?;
+            let candidate_parent = store.get_block_info(&candidate)?.parent_id();
+            let tip_branch = branch_blocks(&*store, tip, &ancestor)?;
+            let candidate_branch = if candidate == ancestor {
+                Vec::new()
+            } else {
+                branch_blocks(&*store, candidate_parent, &ancestor)?
+            };
+            Ok((tip_branch, candidate_branch))
+        })
+    }
+
+    /// get the closest ancestor the two given blocks have in common
+    pub fn common_ancestor(
+        &self,
+        tip: HeaderHash,
+        candidate: HeaderHash,
+    ) -> impl Future<Item = HeaderHash, Error = StorageError> {
+        let mut inner = self.inner.clone();
+        future::poll_fn(move || Ok(inner.poll_lock())).and_then(move |store| {
+            find_common_ancestor(&*store, &tip, &candidate, None)?
+                .map(|(hash, _)| hash)
+                .ok_or(StorageError::BlockNotFound)
+        })
+    }
+}
+
+fn rebuild_without_forks(
+    store: &mut NodeStorage,
+    pruning: &mut StoragePruning,
+    tags: &[String],
+) -> Result<u64, StorageError> {
+    let mut tagged = Vec::new();
+    let mut tip_depth = None;
+    for tag in tags {
+        if let Some(block_hash) = store.get_tag(tag)? {
+            let depth = store.get_block_info(&block_hash)?.depth;
+            tip_depth = tip_depth.max(Some(depth));
+            tagged.push((tag, block_hash));
+        }
+    }
+    let horizon = match tip_depth {
+        None => return Ok(0),
+        Some(tip_depth) => tip_depth.saturating_sub(pruning.depth),
+    };
+    pruning.recent_blocks.retain(|(_, depth)| *depth >= horizon);
+
+    let mut rebuilt = pruning.rebuild.create()?;
+    let heads = tagged.iter().map(|(_, block_hash)| block_hash).chain(
+        pruning
+            .recent_blocks
+            .iter()
+            .map(|(block_hash, _)| block_hash),
+    );
+    let mut copied = HashSet::new();
+    for head in heads {
+        let mut current = Some(head.clone());
+        while let Some(block_hash) = current.take() {
+            if !copied.insert(block_hash.clone()) {
+                break;
+            }
+            let (block, block_info) = store.get_block(&block_hash)?;
+            if block_info.depth > 0 {
+                current = Some(block_info.parent_id());
+            }
+            rebuilt.put_block_internal(&block, block_info)?;
+        }
+    }
+    for (tag, block_hash) in tagged.iter() {
+        rebuilt.put_tag(tag, block_hash)?;
+    }
+    drop(rebuilt);
+
+    // close the current storage before replacing it
+    *store = Box::new(MemoryBlockStore::new());
+    match pruning.rebuild.replace() {
+        Ok((replaced, reclaimed)) => {
+            *store = replaced;
+            Ok(reclaimed)
+        }
+        Err(error) => {
+            *store = pruning.rebuild.reopen()?;
+            Err(error)
+        }
+    }
+}
+
+/// get the blocks from `from` down to its ancestor `to` (excluded)
+fn branch_blocks(
+    store: &NodeStorage,
+    from: HeaderHash,
+    to: &HeaderHash,
+) -> Result<Vec<Block>, StorageError> {
+    let mut blocks = Vec::new();
+    let mut block_hash = from;
+    while &block_hash != to {
+        let (block, block_info) = store.get_block(&block_hash)?;
+        block_hash = block_info.parent_id();
+        blocks.push(block);
+    }
+    Ok(blocks)
+}
+
+/// find the closest common ancestor of `tip` and `candidate` and its distance
+/// from `tip`, giving up if the distance is greater than `max_distance`.
+///
+/// Fails with `BlockNotFound` if an ancestor of either block is missing from
+/// the storage.
+fn find_common_ancestor(
+    store: &NodeStorage,
+    tip: &HeaderHash,
+    candidate: &HeaderHash,
+    max_distance: Option<u64>,
+) -> Result<Option<(HeaderHash, u64)>, StorageError> {
+    let mut tip_info = store.get_block_info(tip)?;
+    let mut candidate_info = store.get_block_info(candidate)?;
+    let tip_depth = tip_info.depth;
+
+    if candidate_info.depth > tip_depth {
+        candidate_info =
+            for_path_to_nth_ancestor(store, candidate, candidate_info.depth - tip_depth, |_| {})?;
+    }
+
+    while tip_info.block_hash != candidate_info.block_hash {
+        if let Some(max_distance) = max_distance {
+            if tip_depth - tip_info.depth >= max_distance {
+                return Ok(None);
+            }
+        }
+        if tip_info.depth == candidate_info.depth {
+            candidate_info = store.get_block_info(&candidate_info.parent_id())?;
+        }
+        tip_info = store.get_block_info(&tip_info.parent_id())?;
+    }
+
+    Ok(Some((tip_info.block_hash, tip_depth - tip_info.depth)))
 }
 
 impl Stream for BlockStream {
@@ -33,11 +33,20 @@ impl PoolEntry {
         let raw = fragment.to_raw();
         let fragment_size = raw.size_bytes_plus_size();
         let fragment_ref = raw.id();
-        // TODO: the fragment fee is not yet computed. Yet we should
-        // have an explicit fee in the message. So we need to be able
-        // to extract this information without the need to compute the
-        // fee from the ledger's fee settings.
-        let fragment_fee = Value::zero();
+        // the fee is the explicit difference between the inputs and the
+        // outputs: this does not need the ledger's fee settings. Whether
+        // the fee is enough is only checked when applying the fragment.
+        let fragment_fee = match fragment {
+            Fragment::Transaction(tx) => explicit_fee(
+                tx.transaction.inputs.iter().map(|input| input.value),
+                tx.transaction.outputs.iter().map(|output| output.value),
+            ),
+            Fragment::Certificate(tx) => explicit_fee(
+                tx.transaction.inputs.iter().map(|input| input.value),
+                tx.transaction.outputs.iter().map(|output| output.value),
+            ),
+            _ => Value::zero(),
+        };
 
         PoolEntry {
             fragment_ref: fragment_ref,
@@ -57,6 +66,24 @@ impl PoolEntry {
         }
     }
 
+    #[cfg(test)]
+    pub fn new_for_test(
+        fragment_ref: FragmentId,
+        fragment_fee: Value,
+        fragment_size: usize,
+    ) -> Self {
+        PoolEntry {
+            fragment_ref,
+            fragment_fee,
+            fragment_size,
+            received_at: SystemTime::now(),
+            descendants_fee: Value::zero(),
+            descendants_size: 0usize,
+            ancestors_fee: Value::zero(),
+            ancestors_size: 0usize,
+        }
+    }
+
     #[inline]
     pub fn fragment_ref(&self) -> &FragmentId {
         &self.fragment_ref
@@ -90,3 +117,14 @@ impl PoolEntry {
         self.ancestors_size + self.fragment_size
     }
 }
+
+fn explicit_fee<I, O>(inputs: I, outputs: O) -> Value
+where
+    I: Iterator<Item = Value>,
+    O: Iterator<Item = Value>,
+{
+    match (Value::sum(inputs), Value::sum(outputs)) {
+        (Ok(inputs), Ok(outputs)) => (inputs - outputs).unwrap_or(Value::zero()),
+        _ => Value::zero(),
+    }
+}
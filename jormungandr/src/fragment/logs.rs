@@ -33,6 +33,26 @@ impl Logs {
         })
     }
 
+    /// get the status of the given fragments. The fragments unknown
+    /// to the logs are not in the returned list.
+    pub fn statuses(
+        &self,
+        fragment_ids: Vec<FragmentId>,
+    ) -> impl Future<Item = Vec<(FragmentId, FragmentStatus)>, Error = ()> {
+        let mut lock = self.0.clone();
+        future::poll_fn(move || Ok(lock.poll_lock())).and_then(move |guard| {
+            let statuses = fragment_ids
+                .into_iter()
+                .filter_map(|id| {
+                    guard
+                        .get(&id.clone().into())
+                        .map(|log| (id, log.status().clone()))
+                })
+                .collect();
+            future::ok(statuses)
+        })
+    }
+
     pub fn modify(
         &mut self,
         fragment_id: FragmentId,
@@ -109,6 +129,10 @@ pub(super) mod internal {
                 .collect()
         }
 
+        pub fn get(&self, fragment_id: &Hash) -> Option<&FragmentLog> {
+            self.entries.get(fragment_id).map(|(log, _)| log)
+        }
+
         pub fn insert(&mut self, log: FragmentLog) {
             let fragment_id = log.fragment_id().clone();
             let delay = self.expirations.insert(fragment_id.clone(), self.ttl);
@@ -116,13 +140,16 @@ pub(super) mod internal {
             self.entries.insert(fragment_id, (log, delay));
         }
 
+        /// update the status of the fragment's log and extend its lifetime.
+        ///
+        /// The log may have expired while the fragment was still in the pool
+        /// (e.g. when the logs TTL is shorter than the pool's), in which case
+        /// there is nothing left to update.
         pub fn modify(&mut self, fragment_id: &Hash, status: FragmentStatus) {
             if let Some((ref mut log, ref key)) = self.entries.get_mut(fragment_id) {
                 log.modify(status);
 
                 self.expirations.reset_at(key, Instant::now() + self.ttl);
-            } else {
-                unimplemented!()
             }
         }
 
@@ -149,3 +176,40 @@ pub(super) mod internal {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::internal::Logs;
+    use crate::fragment::FragmentId;
+    use jormungandr_lib::interfaces::{FragmentLog, FragmentOrigin, FragmentStatus};
+    use std::time::Duration;
+
+    #[test]
+    fn modify_known_fragment() {
+        let mut logs = Logs::new(Duration::from_secs(60));
+        let fragment_id = FragmentId::from([1; 32]);
+        logs.insert(FragmentLog::new(fragment_id.clone(), FragmentOrigin::Rest));
+        let id = fragment_id.into();
+
+        let status = FragmentStatus::Rejected {
+            reason: "test".to_owned(),
+        };
+        logs.modify(&id, status.clone());
+
+        assert_eq!(logs.get(&id).map(|log| log.status()), Some(&status));
+    }
+
+    #[test]
+    fn modify_expired_fragment_is_a_no_op() {
+        let mut logs = Logs::new(Duration::from_secs(60));
+        let id = FragmentId::from([1; 32]).into();
+        logs.modify(
+            &id,
+            FragmentStatus::Rejected {
+                reason: "test".to_owned(),
+            },
+        );
+
+        assert!(logs.get(&id).is_none());
+    }
+}
@@ -3,10 +3,11 @@ mod logs;
 mod pool;
 mod process;
 pub mod selection;
+pub mod validation;
 
 pub use self::entry::PoolEntry;
 pub use self::logs::Logs;
-pub use self::pool::Pool;
+pub use self::pool::{Limits as PoolLimits, Pool};
 pub use self::process::Process;
 
 pub use crate::blockcfg::{Fragment, FragmentId};
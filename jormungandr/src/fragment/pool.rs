@@ -1,11 +1,28 @@
 use crate::{
     blockcfg::{HeaderContentEvalContext, Ledger, LedgerParameters},
-    fragment::{selection::FragmentSelectionAlgorithm, Fragment, Logs},
+    fragment::{selection::FragmentSelectionAlgorithm, validation, Fragment, FragmentId, Logs},
+};
+use jormungandr_lib::interfaces::{
+    FragmentAdmissionStatus, FragmentLog, FragmentOrigin, FragmentStatus,
 };
-use jormungandr_lib::interfaces::{FragmentLog, FragmentOrigin};
 use std::time::Duration;
 use tokio::{prelude::*, sync::lock::Lock, timer};
 
+custom_error! {pub Error
+    Full = "the mempool is full and the fragment does not pay a higher fee than the pending fragments",
+    Evicted = "evicted from the full mempool by a fragment paying a higher fee",
+    Invalid { source: validation::Error } = "{source}",
+}
+
+/// the capacity of the pool
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    /// maximum number of fragments in the pool
+    pub max_entries: usize,
+    /// maximum accumulated size, in bytes, of the fragments in the pool
+    pub max_bytes: usize,
+}
+
 #[derive(Clone)]
 pub struct Pool {
     logs: Logs,
@@ -13,10 +30,10 @@ pub struct Pool {
 }
 
 impl Pool {
-    pub fn new(ttl: Duration, logs: Logs) -> Self {
+    pub fn new(ttl: Duration, limits: Limits, logs: Logs) -> Self {
         Pool {
             logs,
-            pool: Lock::new(internal::Pool::new(ttl)),
+            pool: Lock::new(internal::Pool::new(ttl, limits)),
         }
     }
 
@@ -24,41 +41,167 @@ impl Pool {
         &self.logs
     }
 
+    /// insert the fragment in the pool, returns whether the fragment has been
+    /// accepted, was already known or has been rejected because the pool is full.
+    ///
+    /// If the pool is full, the pending fragments paying the lowest fee
+    /// (the oldest first) are evicted to make room for a fragment paying
+    /// a higher fee. The evicted fragments and the pending fragments that
+    /// cannot be applied anymore without them are marked as rejected.
     pub fn insert(
         &mut self,
         origin: FragmentOrigin,
         fragment: Fragment,
-    ) -> impl Future<Item = bool, Error = ()> {
+    ) -> impl Future<Item = FragmentAdmissionStatus, Error = ()> {
         use chain_core::property::Fragment as _;
 
         let id = fragment.id();
         let mut lock = self.pool.clone();
-        let mut logs = self.logs.clone();
+        let logs = self.logs.clone();
 
         self.logs()
             .exists(vec![id.clone()])
             .and_then(move |exists| {
                 if exists[0] {
-                    future::Either::A(future::ok(false))
+                    future::Either::A(future::ok(FragmentAdmissionStatus::Duplicate))
                 } else {
-                    future::Either::B(future::poll_fn(move || Ok(lock.poll_lock())).and_then(
-                        move |mut guard| {
-                            guard.insert(fragment);
-
-                            let log = FragmentLog::new(id.into(), origin);
-                            logs.insert(log).map(|()| true)
-                        },
-                    ))
+                    future::Either::B(
+                        future::poll_fn(move || Ok(lock.poll_lock()))
+                            .and_then(move |mut guard| {
+                                let mut removed = guard.refresh_tip_ledger();
+                                let inserted = guard.insert(fragment).map(|evicted| {
+                                    removed.extend(evicted);
+                                });
+                                logs.inner().map(move |logs| (inserted, removed, logs))
+                            })
+                            .map(move |(inserted, removed, mut logs)| {
+                                for (removed_id, reason) in removed {
+                                    logs.modify(
+                                        &removed_id.into(),
+                                        FragmentStatus::Rejected { reason },
+                                    );
+                                }
+                                let mut log = FragmentLog::new(id.into(), origin);
+                                match inserted {
+                                    Ok(()) => {
+                                        logs.insert(log);
+                                        FragmentAdmissionStatus::Accepted
+                                    }
+                                    Err(error) => {
+                                        let reason = error.to_string();
+                                        log.modify(FragmentStatus::Rejected {
+                                            reason: reason.clone(),
+                                        });
+                                        logs.insert(log);
+                                        FragmentAdmissionStatus::Rejected { reason }
+                                    }
+                                }
+                            }),
+                    )
                 }
             })
     }
 
+    /// update the pool against a new tip of the blockchain: the fragments
+    /// included in the blocks added to the tip's branch are removed and marked
+    /// as in a block, and the fragments of the blocks rolled back from the
+    /// previous tip's branch are put back in the pool as pending. Then the
+    /// fragments are applied in order to the new tip's ledger and the ones
+    /// that cannot be applied anymore (double spends, spent UTxOs...) are
+    /// removed and marked as rejected.
+    pub fn update_tip(
+        &mut self,
+        ledger: Ledger,
+        ledger_params: LedgerParameters,
+        metadata: HeaderContentEvalContext,
+        included: Vec<FragmentId>,
+        rolled_back: Vec<Fragment>,
+    ) -> impl Future<Item = (), Error = ()> {
+        use chain_core::property::Fragment as _;
+
+        let mut lock = self.pool.clone();
+        let logs = self.logs().clone();
+
+        future::poll_fn(move || Ok(lock.poll_lock()))
+            .and_then(move |pool| logs.inner().map(|logs| (pool, logs)))
+            .map(move |(mut pool, mut logs)| {
+                for id in included.iter() {
+                    if pool.remove(id).is_some() {
+                        logs.modify(
+                            &id.clone().into(),
+                            FragmentStatus::InABlock {
+                                date: metadata.block_date.into(),
+                            },
+                        );
+                    }
+                }
+
+                // the rolled back fragments are older than the pending ones,
+                // they are put back in front of the pool from the newest
+                // to the oldest
+                for fragment in rolled_back.into_iter().rev() {
+                    let id = fragment.id();
+                    if included.contains(&id) {
+                        continue;
+                    }
+                    match pool.reinsert(fragment) {
+                        Ok(evicted) => {
+                            logs.modify(&id.into(), FragmentStatus::Pending);
+                            for (evicted_id, reason) in evicted {
+                                logs.modify(
+                                    &evicted_id.into(),
+                                    FragmentStatus::Rejected { reason },
+                                );
+                            }
+                        }
+                        Err(error) => logs.modify(
+                            &id.into(),
+                            FragmentStatus::Rejected {
+                                reason: error.to_string(),
+                            },
+                        ),
+                    }
+                }
+
+                pool.set_tip_ledger(ledger, ledger_params, metadata);
+                for (id, reason) in pool.refresh_tip_ledger() {
+                    logs.modify(&id.into(), FragmentStatus::Rejected { reason });
+                }
+            })
+    }
+
+    /// get the fragments of the pool with the given identifiers, the
+    /// identifiers of fragments that are not in the pool are ignored.
+    pub fn get(
+        &self,
+        fragment_ids: Vec<FragmentId>,
+    ) -> impl Future<Item = Vec<Fragment>, Error = ()> {
+        let mut lock = self.pool.clone();
+        future::poll_fn(move || Ok(lock.poll_lock())).map(move |pool| {
+            fragment_ids
+                .iter()
+                .filter_map(|fragment_id| pool.get(fragment_id))
+                .cloned()
+                .collect()
+        })
+    }
+
+    /// remove the expired fragments from the pool, the pending fragments
+    /// that cannot be applied anymore without them are marked as rejected.
     pub fn poll_purge(&mut self) -> impl Future<Item = (), Error = timer::Error> {
         let mut lock = self.pool.clone();
+        let logs = self.logs.clone();
         let purge_logs = self.logs.poll_purge();
 
         future::poll_fn(move || Ok(lock.poll_lock()))
             .and_then(move |mut guard| future::poll_fn(move || guard.poll_purge()))
+            .and_then(move |invalidated| {
+                logs.inner().map(move |mut logs| {
+                    for (id, reason) in invalidated {
+                        logs.modify(&id.into(), FragmentStatus::Rejected { reason });
+                    }
+                })
+            })
             .and_then(move |()| purge_logs)
     }
 
@@ -85,7 +228,11 @@ impl Pool {
 }
 
 pub(super) mod internal {
-    use crate::fragment::{Fragment, FragmentId, PoolEntry};
+    use super::{Error, Limits};
+    use crate::{
+        blockcfg::{HeaderContentEvalContext, Ledger, LedgerParameters},
+        fragment::{validation, Fragment, FragmentId, PoolEntry},
+    };
     use std::{
         collections::{BTreeMap, HashMap, VecDeque},
         sync::Arc,
@@ -96,39 +243,257 @@ pub(super) mod internal {
         timer::{self, delay_queue, DelayQueue},
     };
 
+    /// the ledger of the tip, the new fragments are checked against it
+    /// with the pending fragments applied before they are admitted
+    struct TipLedger {
+        ledger: Ledger,
+        ledger_params: LedgerParameters,
+        metadata: HeaderContentEvalContext,
+        /// `ledger` with the pending fragments applied, `None` once a
+        /// fragment has left the pool until it is computed again
+        pending: Option<Ledger>,
+    }
+
+    /// the identifier of a fragment removed from the pool and the reason
+    /// it is rejected
+    pub type Removed = (FragmentId, String);
+
     pub struct Pool {
         pub entries: HashMap<FragmentId, (Arc<PoolEntry>, Fragment, delay_queue::Key)>,
         pub entries_by_id: BTreeMap<FragmentId, Arc<PoolEntry>>,
         pub entries_by_time: VecDeque<FragmentId>,
         expirations: DelayQueue<FragmentId>,
         ttl: Duration,
+        limits: Limits,
+        /// accumulated size of the fragments in the pool
+        total_bytes: usize,
+        /// not known until the first tip update
+        tip_ledger: Option<TipLedger>,
     }
 
     impl Pool {
-        pub fn new(ttl: Duration) -> Self {
+        pub fn new(ttl: Duration, limits: Limits) -> Self {
             Pool {
                 entries: HashMap::new(),
                 entries_by_id: BTreeMap::new(),
                 entries_by_time: VecDeque::new(),
                 expirations: DelayQueue::new(),
                 ttl,
+                limits,
+                total_bytes: 0,
+                tip_ledger: None,
+            }
+        }
+
+        /// set the ledger of the new tip, the pending fragments are applied
+        /// to it by the next `refresh_tip_ledger`
+        pub fn set_tip_ledger(
+            &mut self,
+            ledger: Ledger,
+            ledger_params: LedgerParameters,
+            metadata: HeaderContentEvalContext,
+        ) {
+            self.tip_ledger = Some(TipLedger {
+                ledger,
+                ledger_params,
+                metadata,
+                pending: None,
+            });
+        }
+
+        /// apply the pending fragments to the ledger of the tip again if a
+        /// fragment has left the pool since they were last applied. The
+        /// pending fragments that cannot be applied anymore are removed
+        /// and returned.
+        pub fn refresh_tip_ledger(&mut self) -> Vec<Removed> {
+            match self.tip_ledger {
+                Some(TipLedger { pending: None, .. }) => {}
+                _ => return Vec::new(),
+            }
+            let (pending, invalidated) = self.apply_pending(&[]);
+            for (fragment_id, _) in invalidated.iter() {
+                self.remove(fragment_id);
+            }
+            self.set_pending_ledger(pending);
+            invalidated
+        }
+
+        /// insert the fragment, evicting the entries paying a lower fee
+        /// if the pool is full. Returns the evicted entries and the entries
+        /// that cannot be applied anymore without them.
+        ///
+        /// Once the ledger of the tip is known, the fragment is rejected if
+        /// it cannot be applied after the pending fragments: this is checked
+        /// before any entry is evicted. The pending fragments are expected
+        /// to be applied, see `refresh_tip_ledger`.
+        pub fn insert(&mut self, fragment: Fragment) -> Result<Vec<Removed>, Error> {
+            let entry = Arc::new(PoolEntry::new(&fragment));
+
+            let applied = match self.tip_ledger {
+                Some(TipLedger {
+                    pending: Some(ref pending),
+                    ref ledger_params,
+                    ref metadata,
+                    ..
+                }) => Some(
+                    validation::check_against_ledger(&fragment, pending, ledger_params, metadata)
+                        .map_err(|source| Error::Invalid { source })?,
+                ),
+                _ => None,
+            };
+
+            let evicted = self.make_room_for(&entry)?;
+
+            let mut removed: Vec<Removed> = evicted
+                .iter()
+                .map(|fragment_id| (fragment_id.clone(), Error::Evicted.to_string()))
+                .collect();
+            let applied = match applied {
+                Some(_) if !evicted.is_empty() => {
+                    // the fragment may depend on the evicted ones, it is
+                    // checked again without them before they are removed
+                    let (pending, invalidated) = self.apply_pending(&evicted);
+                    let applied = self.check_against_tip(&fragment, &pending)?;
+                    removed.extend(invalidated);
+                    Some(applied)
+                }
+                applied => applied,
+            };
+
+            for (fragment_id, _) in removed.iter() {
+                self.remove(fragment_id);
+            }
+            if let Some(applied) = applied {
+                self.set_pending_ledger(applied);
             }
+
+            let fragment_id = self.insert_entry(entry, fragment);
+            self.entries_by_time.push_back(fragment_id);
+
+            Ok(removed)
         }
 
-        pub fn insert(&mut self, fragment: Fragment) {
+        /// put back in front of the pool a fragment of a rolled back block,
+        /// evicting the entries paying a lower fee if the pool is full.
+        ///
+        /// The fragment is not checked against the ledger of the tip: the
+        /// whole pool is checked again against the new tip's ledger.
+        pub fn reinsert(&mut self, fragment: Fragment) -> Result<Vec<Removed>, Error> {
             let entry = Arc::new(PoolEntry::new(&fragment));
+            if self.entries.contains_key(entry.fragment_ref()) {
+                return Ok(Vec::new());
+            }
+            let evicted = self.make_room_for(&entry)?;
+
+            for fragment_id in evicted.iter() {
+                self.remove(fragment_id);
+            }
+
+            let fragment_id = self.insert_entry(entry, fragment);
+            self.entries_by_time.push_front(fragment_id);
+
+            Ok(evicted
+                .into_iter()
+                .map(|fragment_id| (fragment_id, Error::Evicted.to_string()))
+                .collect())
+        }
+
+        /// apply the pending fragments, from the oldest, to the ledger of
+        /// the tip, leaving out the `excluded` ones. Returns the resulting
+        /// ledger and the pending fragments that cannot be applied.
+        fn apply_pending(&self, excluded: &[FragmentId]) -> (Ledger, Vec<Removed>) {
+            let tip_ledger = self
+                .tip_ledger
+                .as_ref()
+                .expect("the ledger of the tip is known");
+            let mut ledger = tip_ledger.ledger.clone();
+            let mut invalidated = Vec::new();
+
+            for fragment_id in self.entries_by_time.iter() {
+                if excluded.contains(fragment_id) {
+                    continue;
+                }
+                let fragment = match self.entries.get(fragment_id) {
+                    Some((_, fragment, _)) => fragment,
+                    None => continue,
+                };
+                match self.check_against_tip(fragment, &ledger) {
+                    Ok(applied) => ledger = applied,
+                    Err(error) => invalidated.push((fragment_id.clone(), error.to_string())),
+                }
+            }
+
+            (ledger, invalidated)
+        }
+
+        fn check_against_tip(&self, fragment: &Fragment, ledger: &Ledger) -> Result<Ledger, Error> {
+            let tip_ledger = self
+                .tip_ledger
+                .as_ref()
+                .expect("the ledger of the tip is known");
+            validation::check_against_ledger(
+                fragment,
+                ledger,
+                &tip_ledger.ledger_params,
+                &tip_ledger.metadata,
+            )
+            .map_err(|source| Error::Invalid { source })
+        }
+
+        fn set_pending_ledger(&mut self, pending: Ledger) {
+            if let Some(tip_ledger) = self.tip_ledger.as_mut() {
+                tip_ledger.pending = Some(pending);
+            }
+        }
+
+        /// the effects of a fragment leaving the pool are still applied to
+        /// the pending ledger, it has to be computed again
+        fn invalidate_pending_ledger(&mut self) {
+            if let Some(tip_ledger) = self.tip_ledger.as_mut() {
+                tip_ledger.pending = None;
+            }
+        }
+
+        fn insert_entry(&mut self, entry: Arc<PoolEntry>, fragment: Fragment) -> FragmentId {
             let fragment_id = entry.fragment_ref().clone();
             let delay = self.expirations.insert(fragment_id.clone(), self.ttl);
 
+            self.total_bytes += entry.fragment_size();
             self.entries
                 .insert(fragment_id.clone(), (entry.clone(), fragment, delay));
-            self.entries_by_id
-                .insert(fragment_id.clone(), entry.clone());
-            self.entries_by_time.push_back(fragment_id);
+            self.entries_by_id.insert(fragment_id.clone(), entry);
+
+            fragment_id
+        }
+
+        /// find the entries to evict so the given entry fits in the pool:
+        /// only entries paying a lower fee than the new entry can be
+        /// evicted, the lowest fee first and then the oldest first.
+        fn make_room_for(&self, entry: &PoolEntry) -> Result<Vec<FragmentId>, Error> {
+            let pending = self
+                .entries_by_time
+                .iter()
+                .filter_map(|id| self.entries.get(id))
+                .map(|(pending, _, _)| pending.as_ref());
+
+            select_evictions(
+                &self.limits,
+                self.entries.len(),
+                self.total_bytes,
+                entry,
+                pending,
+            )
+        }
+
+        pub fn get(&self, fragment_id: &FragmentId) -> Option<&Fragment> {
+            self.entries
+                .get(fragment_id)
+                .map(|(_, fragment, _)| fragment)
         }
 
         pub fn remove(&mut self, fragment_id: &FragmentId) -> Option<Fragment> {
-            if let Some((_, fragment, cache_key)) = self.entries.remove(fragment_id) {
+            if let Some((entry, fragment, cache_key)) = self.entries.remove(fragment_id) {
+                self.total_bytes -= entry.fragment_size();
                 self.entries_by_id.remove(fragment_id);
                 self.entries_by_time
                     .iter()
@@ -137,19 +502,26 @@ pub(super) mod internal {
                         self.entries_by_time.remove(position);
                     });
                 self.expirations.remove(&cache_key);
+                self.invalidate_pending_ledger();
                 Some(fragment)
             } else {
                 None
             }
         }
 
-        pub fn poll_purge(&mut self) -> Poll<(), timer::Error> {
+        /// remove the expired fragments. Returns the pending fragments that
+        /// cannot be applied anymore without them, they are removed too.
+        pub fn poll_purge(&mut self) -> Poll<Vec<Removed>, timer::Error> {
             loop {
                 match self.expirations.poll()? {
-                    Async::NotReady => return Ok(Async::Ready(())),
-                    Async::Ready(None) => return Ok(Async::Ready(())),
+                    Async::NotReady | Async::Ready(None) => {
+                        return Ok(Async::Ready(self.refresh_tip_ledger()))
+                    }
                     Async::Ready(Some(entry)) => {
-                        self.entries.remove(entry.get_ref());
+                        if let Some((pool_entry, _, _)) = self.entries.remove(entry.get_ref()) {
+                            self.total_bytes -= pool_entry.fragment_size();
+                            self.invalidate_pending_ledger();
+                        }
                         self.entries_by_id.remove(entry.get_ref());
                         self.entries_by_time
                             .iter()
@@ -162,4 +534,198 @@ pub(super) mod internal {
             }
         }
     }
+
+    /// select, amongst the pending entries given from the oldest to the
+    /// newest, the ones to evict so the new entry fits within the limits.
+    fn select_evictions<'a, I>(
+        limits: &Limits,
+        entries: usize,
+        bytes: usize,
+        entry: &PoolEntry,
+        pending: I,
+    ) -> Result<Vec<FragmentId>, Error>
+    where
+        I: Iterator<Item = &'a PoolEntry>,
+    {
+        let mut entries = entries + 1;
+        let mut bytes = bytes + entry.fragment_size();
+
+        if entries <= limits.max_entries && bytes <= limits.max_bytes {
+            return Ok(Vec::new());
+        }
+
+        let mut candidates: Vec<&PoolEntry> = pending
+            .filter(|candidate| candidate.fragment_fee().0 < entry.fragment_fee().0)
+            .collect();
+        // the sort is stable so the oldest entries come first
+        // amongst the ones paying the same fee
+        candidates.sort_by_key(|candidate| candidate.fragment_fee().0);
+
+        let mut evicted = Vec::new();
+        for candidate in candidates {
+            if entries <= limits.max_entries && bytes <= limits.max_bytes {
+                break;
+            }
+            entries -= 1;
+            bytes -= candidate.fragment_size();
+            evicted.push(candidate.fragment_ref().clone());
+        }
+
+        if entries <= limits.max_entries && bytes <= limits.max_bytes {
+            Ok(evicted)
+        } else {
+            Err(Error::Full)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::blockcfg::{ConfigParam, ConfigParams, Value};
+        use chain_core::property::Fragment as _;
+
+        fn entry(n: u8, fee: u64, size: usize) -> PoolEntry {
+            PoolEntry::new_for_test(FragmentId::from([n; 32]), Value(fee), size)
+        }
+
+        fn id(n: u8) -> FragmentId {
+            FragmentId::from([n; 32])
+        }
+
+        fn fragment(n: u32) -> Fragment {
+            let mut params = ConfigParams::new();
+            params.push(ConfigParam::SlotsPerEpoch(n));
+            Fragment::Initial(params)
+        }
+
+        fn pool() -> Pool {
+            Pool::new(
+                Duration::from_secs(60),
+                Limits {
+                    max_entries: 10,
+                    max_bytes: 10_000,
+                },
+            )
+        }
+
+        #[test]
+        fn reinsert_puts_rolled_back_fragments_first() {
+            let mut pool = pool();
+            let pending = fragment(1);
+            let rolled_back = fragment(2);
+            pool.insert(pending.clone()).unwrap();
+
+            assert_eq!(pool.reinsert(rolled_back.clone()).unwrap(), Vec::new());
+
+            assert_eq!(
+                pool.entries_by_time.iter().cloned().collect::<Vec<_>>(),
+                vec![rolled_back.id(), pending.id()]
+            );
+            assert!(pool.get(&rolled_back.id()).is_some());
+        }
+
+        #[test]
+        fn reinsert_pending_fragment_is_a_no_op() {
+            let mut pool = pool();
+            let pending = fragment(1);
+            pool.insert(pending.clone()).unwrap();
+
+            pool.reinsert(pending.clone()).unwrap();
+
+            assert_eq!(
+                pool.entries_by_time.iter().cloned().collect::<Vec<_>>(),
+                vec![pending.id()]
+            );
+            assert_eq!(pool.entries.len(), 1);
+        }
+
+        #[test]
+        fn reinsert_removes_the_fragment_again() {
+            let mut pool = pool();
+            let rolled_back = fragment(1);
+            pool.reinsert(rolled_back.clone()).unwrap();
+
+            assert!(pool.remove(&rolled_back.id()).is_some());
+            assert!(pool.entries_by_time.is_empty());
+            assert_eq!(pool.total_bytes, 0);
+        }
+
+        #[test]
+        fn refresh_without_tip_ledger_keeps_the_pending_fragments() {
+            let mut pool = pool();
+            let pending = fragment(1);
+            pool.insert(pending.clone()).unwrap();
+            pool.remove(&fragment(2).id());
+
+            assert_eq!(pool.refresh_tip_ledger(), Vec::new());
+            assert!(pool.get(&pending.id()).is_some());
+        }
+
+        #[test]
+        fn within_limits_evicts_nothing() {
+            let limits = Limits {
+                max_entries: 2,
+                max_bytes: 100,
+            };
+            let pending = vec![entry(1, 10, 50)];
+
+            let evicted = select_evictions(&limits, 1, 50, &entry(2, 0, 50), pending.iter());
+
+            assert_eq!(evicted.unwrap(), Vec::<FragmentId>::new());
+        }
+
+        #[test]
+        fn evicts_lowest_fee_then_oldest_first() {
+            let limits = Limits {
+                max_entries: 3,
+                max_bytes: 1000,
+            };
+            let pending = vec![entry(1, 20, 10), entry(2, 10, 10), entry(3, 10, 10)];
+
+            let evicted = select_evictions(&limits, 3, 30, &entry(4, 30, 10), pending.iter());
+
+            assert_eq!(evicted.unwrap(), vec![id(2)]);
+        }
+
+        #[test]
+        fn evicts_until_the_entry_fits_in_bytes() {
+            let limits = Limits {
+                max_entries: 10,
+                max_bytes: 100,
+            };
+            let pending = vec![entry(1, 5, 40), entry(2, 1, 20), entry(3, 1, 20)];
+
+            let evicted = select_evictions(&limits, 3, 80, &entry(4, 10, 60), pending.iter());
+
+            assert_eq!(evicted.unwrap(), vec![id(2), id(3)]);
+        }
+
+        #[test]
+        fn full_when_no_entry_pays_a_lower_fee() {
+            let limits = Limits {
+                max_entries: 2,
+                max_bytes: 1000,
+            };
+            let pending = vec![entry(1, 10, 10), entry(2, 10, 10)];
+
+            match select_evictions(&limits, 2, 20, &entry(3, 10, 10), pending.iter()) {
+                Err(Error::Full) => {}
+                _ => panic!("expected the pool to be full"),
+            }
+        }
+
+        #[test]
+        fn full_when_evicting_cheaper_entries_is_not_enough() {
+            let limits = Limits {
+                max_entries: 10,
+                max_bytes: 100,
+            };
+            let pending = vec![entry(1, 1, 10), entry(2, 50, 90)];
+
+            match select_evictions(&limits, 2, 100, &entry(3, 10, 50), pending.iter()) {
+                Err(Error::Full) => {}
+                _ => panic!("expected the pool to be full"),
+            }
+        }
+    }
 }
@@ -1,8 +1,15 @@
 use crate::{
-    fragment::{Logs, Pool},
-    intercom::TransactionMsg,
+    blockcfg::HeaderContentEvalContext,
+    fragment::{validation, Fragment, Logs, Pool, PoolLimits},
+    intercom::{do_stream_reply, NetworkMsg, PropagateMsg, TransactionMsg},
     stats_counter::StatsCounter,
-    utils::{async_msg::MessageQueue, task::TokioServiceInfo},
+    utils::{
+        async_msg::{MessageBox, MessageQueue},
+        task::TokioServiceInfo,
+    },
+};
+use jormungandr_lib::interfaces::{
+    FragmentAdmissionStatus, FragmentLog, FragmentOrigin, FragmentStatus,
 };
 use slog::Logger;
 use std::time::Duration;
@@ -23,12 +30,13 @@ pub struct Process {
 impl Process {
     pub fn new(
         pool_ttl: Duration,
+        pool_limits: PoolLimits,
         logs_ttl: Duration,
         garbage_collection_interval: Duration,
     ) -> Self {
         let logs = Logs::new(logs_ttl);
         Process {
-            pool: Pool::new(pool_ttl, logs.clone()),
+            pool: Pool::new(pool_ttl, pool_limits, logs.clone()),
             logs,
             garbage_collection_interval,
         }
@@ -45,12 +53,14 @@ impl Process {
         self,
         service_info: TokioServiceInfo,
         stats_counter: StatsCounter,
+        network_msg_box: MessageBox<NetworkMsg>,
         input: MessageQueue<TransactionMsg>,
     ) -> impl Future<Item = (), Error = ()> {
         service_info.spawn(self.start_pool_garbage_collector(service_info.logger().clone()));
 
         let pool = self.pool.clone();
         let pool_copy = self.pool;
+        let logger = service_info.logger().clone();
 
         input.for_each(move |input| {
             match input {
@@ -65,32 +75,78 @@ impl Process {
                 TransactionMsg::SendTransaction(origin, txs) => {
                     // TODO? stats_counter.add_tx_recv_cnt(txs.len());
 
-                    // Note that we cannot use apply_block here, since we don't have a valid context to which to apply
-                    // those blocks. one valid tx in a given context, could be invalid in another. for example
-                    // fee calculations, existence utxo / account solvency.
+                    // Note that a fragment valid against the current tip could be invalid in another context,
+                    // for example fee calculations, existence utxo / account solvency: they are checked again
+                    // when the tip changes and when building a block.
 
-                    // FIXME/TODO check that the txs are valid within themselves with basic requirements (e.g. inputs >= outputs).
-                    // we also want to keep a basic capability to filter away repetitive queries or definitely discarded txid.
+                    // the txs are checked to be valid within themselves with basic requirements (e.g. inputs >= outputs),
+                    // and then against the ledger of the tip with the pending fragments applied (witnesses, certificates):
+                    // the malformed ones are rejected straight away and never enter the pool.
+                    // FIXME/TODO we also want to keep a basic capability to filter away repetitive queries or definitely discarded txid.
 
                     // This interface only makes sense for messages coming from arbitrary users (like transaction, certificates),
                     // for other message we don't want to receive them through this interface, and possibly
                     // put them in another pool.
 
-                    let mut pool_copy = pool_copy.clone();
+                    let mut pool = pool_copy.clone();
                     let stats_counter = stats_counter.clone();
+                    let network_msg_box = network_msg_box.clone();
+                    let logger = logger.clone();
 
                     A(B(stream::iter_ok(txs).for_each(move |tx| {
-                        let stats_counter = stats_counter.clone();
-                        pool_copy.insert(origin, tx).map(move |inserted| {
-                            if inserted {
-                                stats_counter.add_tx_recv_cnt(1)
+                        admit_fragment(
+                            &mut pool,
+                            &stats_counter,
+                            &network_msg_box,
+                            &logger,
+                            origin.clone(),
+                            tx,
+                        )
+                        .map(|_| ())
+                    })))
+                }
+                TransactionMsg::SubmitTransaction(origin, tx, reply) => {
+                    let mut pool = pool_copy.clone();
+
+                    B(B(B(admit_fragment(
+                        &mut pool,
+                        &stats_counter,
+                        &network_msg_box,
+                        &logger,
+                        origin,
+                        tx,
+                    )
+                    .map(move |status| reply.reply_ok(status)))))
+                }
+                TransactionMsg::GetTransactions(txids, handler) => {
+                    B(A(pool.get(txids).map(move |fragments| {
+                        do_stream_reply(handler, |handler| {
+                            for fragment in fragments {
+                                handler.send(fragment);
                             }
+                            Ok(())
                         })
                     })))
                 }
-                TransactionMsg::GetTransactions(_txids, _handler) => {
-                    // this function is no yet implemented, this is not handled in the
-                    B(future::ok(unimplemented!()))
+                TransactionMsg::UpdateTip(tip, included, rolled_back) => {
+                    use chain_core::property::ChainLength as _;
+
+                    let mut pool = pool.clone();
+                    // the fragments are checked for the next block to be
+                    // built on the tip
+                    let metadata = HeaderContentEvalContext {
+                        block_date: tip.block_date().next(tip.era()),
+                        chain_length: tip.chain_length().next(),
+                        nonce: None,
+                    };
+
+                    B(B(A(pool.update_tip(
+                        tip.ledger().as_ref().clone(),
+                        tip.epoch_ledger_parameters().as_ref().clone(),
+                        metadata,
+                        included,
+                        rolled_back,
+                    ))))
                 }
             }
         })
@@ -110,3 +166,48 @@ impl Process {
             })
     }
 }
+
+/// check the fragment is well formed and insert it in the pool. The fragments
+/// new to this node are propagated to the network, so they are not gossiped back.
+fn admit_fragment(
+    pool: &mut Pool,
+    stats_counter: &StatsCounter,
+    network_msg_box: &MessageBox<NetworkMsg>,
+    logger: &Logger,
+    origin: FragmentOrigin,
+    fragment: Fragment,
+) -> impl Future<Item = FragmentAdmissionStatus, Error = ()> {
+    use chain_core::property::Fragment as _;
+
+    if let Err(error) = validation::check(&fragment) {
+        let id = fragment.id();
+        let reason = error.to_string();
+        debug!(logger, "fragment rejected" ; "id" => id.to_string(), "reason" => reason.clone());
+
+        let mut log = FragmentLog::new(id.into(), origin);
+        log.modify(FragmentStatus::Rejected {
+            reason: reason.clone(),
+        });
+        let mut logs = pool.logs().clone();
+        return A(logs
+            .insert(log)
+            .map(move |()| FragmentAdmissionStatus::Rejected { reason }));
+    }
+
+    let stats_counter = stats_counter.clone();
+    let mut network_msg_box = network_msg_box.clone();
+    let logger = logger.clone();
+    let propagated = fragment.clone();
+    B(pool.insert(origin, fragment).map(move |status| {
+        if status == FragmentAdmissionStatus::Accepted {
+            stats_counter.add_tx_recv_cnt(1);
+
+            network_msg_box
+                .try_send(NetworkMsg::Propagate(PropagateMsg::Message(propagated)))
+                .unwrap_or_else(|err| {
+                    error!(logger, "cannot propagate fragment to network: {}", err)
+                });
+        }
+        status
+    }))
+}
@@ -1,10 +1,25 @@
 use super::logs::internal::Logs;
 use super::pool::internal::Pool;
 use crate::{
-    blockcfg::{BlockBuilder, HeaderContentEvalContext, Ledger, LedgerParameters},
-    fragment::FragmentId,
+    blockcfg::{BlockBuilder, HeaderContentEvalContext, Ledger, LedgerParameters, Value},
+    fragment::{Fragment, FragmentId},
 };
+use chain_impl_mockchain::fee::{FeeAlgorithm as _, LinearFee};
 use jormungandr_lib::interfaces::FragmentStatus;
+use std::cmp::Ordering;
+
+/// the fragment selection algorithm the leadership task runs when
+/// preparing a new block, with its limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    OldestFirst {
+        max_per_block: usize,
+    },
+    HighestFeeFirst {
+        max_per_block: usize,
+        max_block_size: usize,
+    },
+}
 
 pub enum SelectionOutput {
     Commit { fragment_id: FragmentId },
@@ -77,16 +92,269 @@ impl FragmentSelectionAlgorithm for OldestFirst {
                     total += 1;
                     ledger_simulation = ledger_new;
                 }
-                Err(error) => {
-                    use std::error::Error as _;
-                    let error = if let Some(source) = error.source() {
-                        format!("{}: {}", error, source)
-                    } else {
-                        error.to_string()
-                    };
-                    logs.modify(&id.into(), FragmentStatus::Rejected { reason: error })
+                Err(error) => logs.modify(
+                    &id.into(),
+                    FragmentStatus::Rejected {
+                        reason: rejection_reason(&error),
+                    },
+                ),
+            }
+        }
+    }
+}
+
+/// select the fragments paying the highest fee per byte first, within
+/// the limits of a maximum number of fragments and of a block size budget.
+///
+/// Fragments that do not fit in the remaining budget are left in the pool
+/// so they can be considered for a later block.
+pub struct HighestFeeFirst {
+    builder: BlockBuilder,
+    max_per_block: usize,
+    max_block_size: usize,
+}
+
+impl HighestFeeFirst {
+    pub fn new(max_per_block: usize, max_block_size: usize) -> Self {
+        HighestFeeFirst {
+            builder: BlockBuilder::new(),
+            max_per_block,
+            max_block_size,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Candidate {
+    id: FragmentId,
+    fee: Value,
+    size: usize,
+}
+
+impl Candidate {
+    /// compare the fee per byte of the candidates without loss of precision:
+    /// `self.fee / self.size` against `other.fee / other.size`
+    fn cmp_fee_per_byte(&self, other: &Self) -> Ordering {
+        let lhs = self.fee.0 as u128 * other.size as u128;
+        let rhs = other.fee.0 as u128 * self.size as u128;
+        lhs.cmp(&rhs)
+    }
+}
+
+/// order the candidates by decreasing fee per byte.
+///
+/// The sort is stable: candidates paying the same fee per byte stay in
+/// the order they were received.
+fn sort_by_fee_per_byte(candidates: &mut [Candidate]) {
+    candidates.sort_by(|a, b| b.cmp_fee_per_byte(a));
+}
+
+/// the space left in the block being built
+struct Budget {
+    max_per_block: usize,
+    max_block_size: usize,
+    total: usize,
+    total_size: usize,
+}
+
+impl Budget {
+    fn new(max_per_block: usize, max_block_size: usize) -> Self {
+        Budget {
+            max_per_block,
+            max_block_size,
+            total: 0,
+            total_size: 0,
+        }
+    }
+
+    /// no more fragments can be added to the block
+    fn is_full(&self) -> bool {
+        self.total >= self.max_per_block
+    }
+
+    /// a fragment of the given size still fits in the block
+    fn fits(&self, size: usize) -> bool {
+        self.total_size + size <= self.max_block_size
+    }
+
+    fn consume(&mut self, size: usize) {
+        self.total += 1;
+        self.total_size += size;
+    }
+}
+
+impl FragmentSelectionAlgorithm for HighestFeeFirst {
+    fn finalize(self) -> BlockBuilder {
+        self.builder
+    }
+
+    fn select(
+        &mut self,
+        ledger: &Ledger,
+        ledger_params: &LedgerParameters,
+        metadata: &HeaderContentEvalContext,
+        logs: &mut Logs,
+        pool: &mut Pool,
+    ) {
+        let mut budget = Budget::new(self.max_per_block, self.max_block_size);
+        let mut ledger_simulation = ledger.clone();
+
+        let mut candidates: Vec<Candidate> = pool
+            .entries_by_time
+            .iter()
+            .filter_map(|id| pool.entries.get(id))
+            .map(|(entry, fragment, _)| Candidate {
+                id: entry.fragment_ref().clone(),
+                fee: fragment_fee(fragment, &ledger_params.fees),
+                size: *entry.fragment_size(),
+            })
+            .collect();
+
+        sort_by_fee_per_byte(&mut candidates);
+
+        for candidate in candidates {
+            if budget.is_full() {
+                break;
+            }
+
+            if !budget.fits(candidate.size) {
+                continue;
+            }
+
+            let id = candidate.id;
+            let fragment = pool.remove(&id).unwrap();
+
+            match ledger_simulation.apply_fragment(ledger_params, &fragment, metadata) {
+                Ok(ledger_new) => {
+                    self.builder.message(fragment);
+
+                    logs.modify(
+                        &id.into(),
+                        FragmentStatus::InABlock {
+                            date: metadata.block_date.into(),
+                        },
+                    );
+
+                    budget.consume(candidate.size);
+                    ledger_simulation = ledger_new;
                 }
+                Err(error) => logs.modify(
+                    &id.into(),
+                    FragmentStatus::Rejected {
+                        reason: rejection_reason(&error),
+                    },
+                ),
             }
         }
     }
 }
+
+/// compute the fee paid by the given fragment with the ledger's fee settings.
+///
+/// Fragments that are not transactions do not pay any fee.
+fn fragment_fee(fragment: &Fragment, fees: &LinearFee) -> Value {
+    let fee = match fragment {
+        Fragment::Transaction(tx) => fees.calculate(&tx.transaction),
+        Fragment::Certificate(tx) => fees.calculate(&tx.transaction),
+        _ => None,
+    };
+    fee.unwrap_or(Value::zero())
+}
+
+pub(super) fn rejection_reason<E: std::error::Error>(error: &E) -> String {
+    if let Some(source) = error.source() {
+        format!("{}: {}", error, source)
+    } else {
+        error.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(n: u8, fee: u64, size: usize) -> Candidate {
+        Candidate {
+            id: FragmentId::from([n; 32]),
+            fee: Value(fee),
+            size,
+        }
+    }
+
+    fn ids(candidates: &[Candidate]) -> Vec<FragmentId> {
+        candidates.iter().map(|c| c.id.clone()).collect()
+    }
+
+    #[test]
+    fn highest_fee_per_byte_first() {
+        let mut candidates = vec![
+            candidate(1, 100, 100),
+            candidate(2, 300, 100),
+            candidate(3, 300, 200),
+            candidate(4, 10, 1),
+        ];
+
+        sort_by_fee_per_byte(&mut candidates);
+
+        assert_eq!(
+            ids(&candidates),
+            vec![
+                FragmentId::from([4; 32]),
+                FragmentId::from([2; 32]),
+                FragmentId::from([3; 32]),
+                FragmentId::from([1; 32]),
+            ]
+        );
+    }
+
+    #[test]
+    fn same_fee_per_byte_keeps_received_order() {
+        let mut candidates = vec![
+            candidate(1, 100, 100),
+            candidate(2, 200, 200),
+            candidate(3, 50, 50),
+            candidate(4, 1, 1),
+        ];
+
+        sort_by_fee_per_byte(&mut candidates);
+
+        assert_eq!(
+            ids(&candidates),
+            vec![
+                FragmentId::from([1; 32]),
+                FragmentId::from([2; 32]),
+                FragmentId::from([3; 32]),
+                FragmentId::from([4; 32]),
+            ]
+        );
+    }
+
+    #[test]
+    fn budget_max_fragments_per_block() {
+        let mut budget = Budget::new(2, 1000);
+
+        assert!(!budget.is_full());
+        budget.consume(10);
+        assert!(!budget.is_full());
+        budget.consume(10);
+        assert!(budget.is_full());
+    }
+
+    #[test]
+    fn budget_max_block_size() {
+        let mut budget = Budget::new(10, 100);
+
+        assert!(budget.fits(100));
+        assert!(!budget.fits(101));
+
+        budget.consume(60);
+        assert!(budget.fits(40));
+        assert!(!budget.fits(41));
+
+        // a smaller fragment further down the list can still use the
+        // space left by a fragment that did not fit
+        budget.consume(40);
+        assert!(budget.fits(0));
+        assert!(!budget.fits(1));
+    }
+}
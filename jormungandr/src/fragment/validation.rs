@@ -0,0 +1,152 @@
+//! checks of the fragments, done before they are admitted in the
+//! mempool.
+//!
+//! The first checks only look at the fragment itself. The witnesses are
+//! then verified against the transaction sign-data id and the keys of the
+//! spent inputs, and the certificates against the registered stake pools,
+//! by applying the fragment to the ledger of the current tip (see
+//! [`check_against_ledger`]).
+//!
+//! [`check_against_ledger`]: ./fn.check_against_ledger.html
+
+use crate::blockcfg::{
+    Fragment, HeaderContentEvalContext, Ledger, LedgerParameters, Value, ValueError,
+};
+use chain_impl_mockchain::ledger;
+
+custom_error! {pub Error
+    Block0Only = "fragment is only valid in the block 0",
+    WitnessCountMismatch { inputs: usize, witnesses: usize } = "transaction has {inputs} inputs but {witnesses} witnesses",
+    ValueOverflow { source: ValueError } = "transaction values cannot be summed: {source}",
+    OutputsExceedInputs { inputs: u64, outputs: u64 } = "transaction outputs ({outputs}) are greater than its inputs ({inputs})",
+    UnsignedCertificate = "certificate is not signed",
+    Ledger { source: ledger::Error } = "fragment cannot be applied to the ledger: {source}",
+}
+
+/// check the given fragment is well formed, independently of the
+/// state of the ledger it will be applied to.
+pub fn check(fragment: &Fragment) -> Result<(), Error> {
+    match fragment {
+        Fragment::Initial(_) | Fragment::OldUtxoDeclaration(_) => Err(Error::Block0Only),
+        Fragment::Transaction(tx) => {
+            check_witnesses(tx.transaction.inputs.len(), tx.witnesses.len())?;
+            check_balance(
+                tx.transaction.inputs.iter().map(|input| input.value),
+                tx.transaction.outputs.iter().map(|output| output.value),
+            )
+        }
+        Fragment::Certificate(tx) => {
+            if tx.transaction.extra.signatures.is_empty() {
+                return Err(Error::UnsignedCertificate);
+            }
+            check_witnesses(tx.transaction.inputs.len(), tx.witnesses.len())?;
+            check_balance(
+                tx.transaction.inputs.iter().map(|input| input.value),
+                tx.transaction.outputs.iter().map(|output| output.value),
+            )
+        }
+        _ => Ok(()),
+    }
+}
+
+/// apply the fragment to the given ledger: this verifies the witnesses
+/// against the transaction sign-data id and the keys of the spent inputs
+/// (including their spending counters), and the certificate payloads
+/// against the state of the ledger.
+///
+/// Returns the ledger with the fragment applied, so the next fragments
+/// can be checked against it.
+pub fn check_against_ledger(
+    fragment: &Fragment,
+    ledger: &Ledger,
+    ledger_params: &LedgerParameters,
+    metadata: &HeaderContentEvalContext,
+) -> Result<Ledger, Error> {
+    ledger
+        .apply_fragment(ledger_params, fragment, metadata)
+        .map_err(|source| Error::Ledger { source })
+}
+
+fn check_witnesses(inputs: usize, witnesses: usize) -> Result<(), Error> {
+    if inputs != witnesses {
+        Err(Error::WitnessCountMismatch { inputs, witnesses })
+    } else {
+        Ok(())
+    }
+}
+
+/// the outputs may not be greater than the inputs, the difference being
+/// the fee: it is checked against the fee settings of the ledger later.
+fn check_balance<I, O>(inputs: I, outputs: O) -> Result<(), Error>
+where
+    I: Iterator<Item = Value>,
+    O: Iterator<Item = Value>,
+{
+    let inputs = Value::sum(inputs)?;
+    let outputs = Value::sum(outputs)?;
+
+    if outputs.0 > inputs.0 {
+        Err(Error::OutputsExceedInputs {
+            inputs: inputs.0,
+            outputs: outputs.0,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn as_many_witnesses_as_inputs() {
+        assert!(check_witnesses(0, 0).is_ok());
+        assert!(check_witnesses(2, 2).is_ok());
+    }
+
+    #[test]
+    fn missing_or_extra_witnesses() {
+        match check_witnesses(2, 1) {
+            Err(Error::WitnessCountMismatch {
+                inputs: 2,
+                witnesses: 1,
+            }) => {}
+            _ => panic!("expected a witness count mismatch"),
+        }
+        match check_witnesses(1, 2) {
+            Err(Error::WitnessCountMismatch {
+                inputs: 1,
+                witnesses: 2,
+            }) => {}
+            _ => panic!("expected a witness count mismatch"),
+        }
+    }
+
+    #[test]
+    fn outputs_within_inputs() {
+        let inputs = vec![Value(10), Value(20)];
+        assert!(check_balance(inputs.clone().into_iter(), vec![Value(30)].into_iter()).is_ok());
+        assert!(check_balance(inputs.into_iter(), vec![Value(25)].into_iter()).is_ok());
+    }
+
+    #[test]
+    fn outputs_exceed_inputs() {
+        match check_balance(vec![Value(10)].into_iter(), vec![Value(11)].into_iter()) {
+            Err(Error::OutputsExceedInputs {
+                inputs: 10,
+                outputs: 11,
+            }) => {}
+            _ => panic!("expected the outputs to exceed the inputs"),
+        }
+    }
+
+    #[test]
+    fn inputs_overflow() {
+        let inputs = vec![Value(u64::max_value()), Value(1)];
+        match check_balance(inputs.into_iter(), vec![Value(1)].into_iter()) {
+            Err(Error::ValueOverflow { .. }) => {}
+            _ => panic!("expected the inputs to overflow"),
+        }
+    }
+}
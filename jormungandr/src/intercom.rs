@@ -1,8 +1,12 @@
 use crate::blockcfg::{Block, Epoch, Fragment, FragmentId, Header, HeaderHash};
-use crate::network::p2p::topology::NodeId;
+use crate::blockchain::Ref;
+use crate::leadership::LeadershipLogHandle;
+use crate::network::p2p::{comm::Misbehavior, topology::NodeId};
 use futures::prelude::*;
 use futures::sync::{mpsc, oneshot};
-use jormungandr_lib::interfaces::FragmentOrigin;
+use jormungandr_lib::interfaces::{
+    BannedPeer, EnclaveLeaderId, FragmentAdmissionStatus, FragmentOrigin,
+};
 use network_core::error as core_error;
 use slog::Logger;
 use std::{
@@ -252,11 +256,56 @@ where
 }
 
 /// ...
-#[derive(Debug)]
 pub enum TransactionMsg {
     ProposeTransaction(Vec<FragmentId>, ReplyHandle<Vec<bool>>),
     SendTransaction(FragmentOrigin, Vec<Fragment>),
+    /// Submit a single fragment, the reply handle is used to report
+    /// whether it has been admitted in the pool.
+    SubmitTransaction(
+        FragmentOrigin,
+        Fragment,
+        ReplyHandle<FragmentAdmissionStatus>,
+    ),
     GetTransactions(Vec<FragmentId>, ReplyStreamHandle<Fragment>),
+    /// The tip of the blockchain has been updated to the given `Ref`. The
+    /// listed fragments are included in the blocks added to the branch of
+    /// the tip, followed by the fragments of the blocks rolled back from the
+    /// previous tip's branch, if the tip has switched to a competing branch.
+    UpdateTip(Ref, Vec<FragmentId>, Vec<Fragment>),
+}
+
+impl Debug for TransactionMsg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TransactionMsg::ProposeTransaction(ids, reply) => f
+                .debug_tuple("ProposeTransaction")
+                .field(ids)
+                .field(reply)
+                .finish(),
+            TransactionMsg::SendTransaction(origin, fragments) => f
+                .debug_tuple("SendTransaction")
+                .field(origin)
+                .field(fragments)
+                .finish(),
+            TransactionMsg::SubmitTransaction(origin, fragment, reply) => f
+                .debug_tuple("SubmitTransaction")
+                .field(origin)
+                .field(fragment)
+                .field(reply)
+                .finish(),
+            TransactionMsg::GetTransactions(ids, reply) => f
+                .debug_tuple("GetTransactions")
+                .field(ids)
+                .field(reply)
+                .finish(),
+            TransactionMsg::UpdateTip(tip, ids, rolled_back) => f
+                .debug_tuple("UpdateTip")
+                .field(&tip.hash())
+                .field(ids)
+                .field(rolled_back)
+                .finish(),
+        }
+    }
 }
 
 /// Client messages, mainly requests from connected peers to our node.
@@ -311,8 +360,9 @@ impl Debug for ClientMsg {
 /// General Block Message for the block task
 #[derive(Debug)]
 pub enum BlockMsg {
-    /// A trusted Block has been received from the leadership task
-    LeadershipBlock(Block),
+    /// A trusted Block has been received from the leadership task,
+    /// the outcome of its processing is recorded in the leadership log
+    LeadershipBlock(Block, LeadershipLogHandle),
     /// Leadership process expect a new end of epoch
     LeadershipExpectEndOfEpoch(Epoch),
     /// A untrusted block Header has been received from the network task
@@ -330,6 +380,17 @@ pub enum BlockMsg {
     ChainHeaders(Vec<Header>, ReplyHandle<()>),
 }
 
+/// Messages to the leadership task
+#[derive(Debug)]
+pub enum LeadershipMsg {
+    /// A leader has been added to the enclave, its remaining slots of the
+    /// ongoing epoch need to be scheduled
+    LeaderAdded(EnclaveLeaderId),
+    /// A leader has been removed from the enclave, its pending schedules
+    /// need to be cancelled
+    LeaderRemoved(EnclaveLeaderId),
+}
+
 /// Propagation requests for the network task.
 #[derive(Clone, Debug)]
 pub enum PropagateMsg {
@@ -338,7 +399,7 @@ pub enum PropagateMsg {
 }
 
 /// Messages to the network task.
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 pub enum NetworkMsg {
     Propagate(PropagateMsg),
     GetBlocks(Vec<HeaderHash>),
@@ -348,6 +409,10 @@ pub enum NetworkMsg {
         from: Vec<HeaderHash>,
         to: HeaderHash,
     },
+    /// A peer has sent invalid data, lower its reputation
+    PeerMisbehaved(NodeId, Misbehavior),
+    /// Get the peers currently banned for having sent invalid data
+    GetBannedPeers(ReplyHandle<Vec<BannedPeer>>),
 }
 
 #[cfg(test)]
@@ -2,6 +2,7 @@ use crate::{
     blockcfg::{Block, BlockBuilder, Leadership},
     secure::enclave::Enclave as SecureEnclave,
 };
+use jormungandr_lib::interfaces::EnclaveLeaderId as LeaderId;
 use std::sync::Arc;
 use tokio::{prelude::*, sync::lock::Lock};
 
@@ -46,6 +47,25 @@ impl Enclave {
             .map(move |guard| guard.leadership_evaluate(&leadership, slot_start, nb_slots))
     }
 
+    /// same as [`query_schedules`] but only for the given leader, to schedule
+    /// a leader added to the enclave during the epoch
+    ///
+    /// [`query_schedules`]: #method.query_schedules
+    pub fn query_leader_schedules(
+        &self,
+        leadership: Arc<Leadership>,
+        leader_id: LeaderId,
+        slot_start: u32,
+        nb_slots: u32,
+    ) -> impl Future<Item = Vec<LeaderEvent>, Error = Error> {
+        let mut inner = self.inner.clone();
+        future::poll_fn(move || Ok(inner.poll_lock())).map(move |guard| {
+            (slot_start..slot_start + nb_slots)
+                .filter_map(|slot| guard.leadership_evaluate1(&leadership, &leader_id, slot))
+                .collect()
+        })
+    }
+
     /// ask the leader associated to the `LeaderEvent` to finalize the given
     /// block by providing the proof.
     ///
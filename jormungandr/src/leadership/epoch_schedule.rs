@@ -0,0 +1,207 @@
+use crate::blockcfg::Epoch;
+use jormungandr_lib::{
+    interfaces::{BlockDate, EnclaveLeaderId, LeadershipSchedule},
+    time::SystemTime,
+};
+use tokio::{
+    prelude::*,
+    sync::lock::{Lock, LockGuard},
+};
+
+/// the slots every leader of the enclave is elected for in the epoch last
+/// scheduled by the leadership module, so they can be queried without
+/// evaluating the leadership of every slot again
+#[derive(Clone)]
+pub struct EpochSchedule(Lock<internal::EpochSchedule>);
+
+impl EpochSchedule {
+    pub fn new() -> Self {
+        EpochSchedule(Lock::new(internal::EpochSchedule::new()))
+    }
+
+    /// record the slots the leaders are elected for in the given epoch. The
+    /// slots recorded for a previous epoch are dropped.
+    pub fn insert(
+        &self,
+        epoch: Epoch,
+        slots: Vec<(EnclaveLeaderId, BlockDate, SystemTime)>,
+    ) -> impl Future<Item = (), Error = ()> {
+        self.inner().and_then(move |mut guard| {
+            guard.insert(epoch, slots);
+            future::ok(())
+        })
+    }
+
+    /// forget the slots of a leader removed from the enclave
+    pub fn remove_leader(&self, leader_id: EnclaveLeaderId) -> impl Future<Item = (), Error = ()> {
+        self.inner().and_then(move |mut guard| {
+            guard.remove_leader(leader_id);
+            future::ok(())
+        })
+    }
+
+    /// the schedule of each of the given leaders, empty for the leaders not
+    /// elected for any slot of the epoch
+    pub fn schedules(
+        &self,
+        leader_ids: Vec<EnclaveLeaderId>,
+    ) -> impl Future<Item = Vec<LeadershipSchedule>, Error = ()> {
+        self.inner()
+            .and_then(move |guard| future::ok(guard.schedules(leader_ids)))
+    }
+
+    fn inner<E>(&self) -> impl Future<Item = LockGuard<internal::EpochSchedule>, Error = E> {
+        let mut lock = self.0.clone();
+        future::poll_fn(move || Ok(lock.poll_lock()))
+    }
+}
+
+pub(super) mod internal {
+    use super::{BlockDate, EnclaveLeaderId, Epoch, LeadershipSchedule, SystemTime};
+    use std::collections::BTreeMap;
+
+    pub struct EpochSchedule {
+        epoch: Option<Epoch>,
+        slots: BTreeMap<EnclaveLeaderId, BTreeMap<BlockDate, SystemTime>>,
+    }
+
+    impl EpochSchedule {
+        pub fn new() -> Self {
+            EpochSchedule {
+                epoch: None,
+                slots: BTreeMap::new(),
+            }
+        }
+
+        pub fn insert(
+            &mut self,
+            epoch: Epoch,
+            slots: Vec<(EnclaveLeaderId, BlockDate, SystemTime)>,
+        ) {
+            if self.epoch != Some(epoch) {
+                self.epoch = Some(epoch);
+                self.slots.clear();
+            }
+
+            for (leader_id, date, time) in slots {
+                self.slots
+                    .entry(leader_id)
+                    .or_insert_with(BTreeMap::new)
+                    .insert(date, time);
+            }
+        }
+
+        pub fn remove_leader(&mut self, leader_id: EnclaveLeaderId) {
+            self.slots.remove(&leader_id);
+        }
+
+        pub fn schedules(&self, mut leader_ids: Vec<EnclaveLeaderId>) -> Vec<LeadershipSchedule> {
+            leader_ids.sort();
+            leader_ids.dedup();
+            leader_ids
+                .into_iter()
+                .map(|leader_id| {
+                    let mut schedule = LeadershipSchedule::new(leader_id);
+                    if let Some(slots) = self.slots.get(&leader_id) {
+                        for (date, time) in slots {
+                            schedule.push(*date, *time);
+                        }
+                    }
+                    schedule
+                })
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockcfg::BlockDate as ChainBlockDate;
+
+    fn leader(n: u32) -> EnclaveLeaderId {
+        (0..n).fold(EnclaveLeaderId::new(), |id, _| id.next())
+    }
+
+    fn date(epoch: Epoch, slot_id: u32) -> BlockDate {
+        ChainBlockDate { epoch, slot_id }.into()
+    }
+
+    fn dates(schedule: &LeadershipSchedule) -> Vec<BlockDate> {
+        schedule
+            .slots()
+            .iter()
+            .map(|slot| *slot.scheduled_at_date())
+            .collect()
+    }
+
+    #[test]
+    fn schedules_are_sorted_and_include_every_leader() {
+        let now = SystemTime::now();
+        let mut epoch_schedule = internal::EpochSchedule::new();
+        epoch_schedule.insert(
+            1,
+            vec![
+                (leader(2), date(1, 7), now),
+                (leader(1), date(1, 5), now),
+                (leader(2), date(1, 3), now),
+            ],
+        );
+
+        let schedules = epoch_schedule.schedules(vec![leader(3), leader(2), leader(1)]);
+
+        let leader_ids: Vec<_> = schedules
+            .iter()
+            .map(|schedule| *schedule.enclave_leader_id())
+            .collect();
+        assert_eq!(leader_ids, vec![leader(1), leader(2), leader(3)]);
+        assert_eq!(dates(&schedules[0]), vec![date(1, 5)]);
+        assert_eq!(dates(&schedules[1]), vec![date(1, 3), date(1, 7)]);
+        assert_eq!(dates(&schedules[2]), vec![]);
+    }
+
+    #[test]
+    fn insert_in_the_same_epoch_keeps_the_slots() {
+        let now = SystemTime::now();
+        let mut epoch_schedule = internal::EpochSchedule::new();
+        epoch_schedule.insert(1, vec![(leader(1), date(1, 5), now)]);
+        epoch_schedule.insert(
+            1,
+            vec![(leader(1), date(1, 5), now), (leader(2), date(1, 3), now)],
+        );
+
+        let schedules = epoch_schedule.schedules(vec![leader(1), leader(2)]);
+
+        assert_eq!(dates(&schedules[0]), vec![date(1, 5)]);
+        assert_eq!(dates(&schedules[1]), vec![date(1, 3)]);
+    }
+
+    #[test]
+    fn insert_in_a_new_epoch_drops_the_previous_slots() {
+        let now = SystemTime::now();
+        let mut epoch_schedule = internal::EpochSchedule::new();
+        epoch_schedule.insert(1, vec![(leader(1), date(1, 5), now)]);
+        epoch_schedule.insert(2, vec![(leader(2), date(2, 3), now)]);
+
+        let schedules = epoch_schedule.schedules(vec![leader(1), leader(2)]);
+
+        assert_eq!(dates(&schedules[0]), vec![]);
+        assert_eq!(dates(&schedules[1]), vec![date(2, 3)]);
+    }
+
+    #[test]
+    fn remove_leader_drops_its_slots_only() {
+        let now = SystemTime::now();
+        let mut epoch_schedule = internal::EpochSchedule::new();
+        epoch_schedule.insert(
+            1,
+            vec![(leader(1), date(1, 5), now), (leader(2), date(1, 3), now)],
+        );
+
+        epoch_schedule.remove_leader(leader(1));
+
+        let schedules = epoch_schedule.schedules(vec![leader(1), leader(2)]);
+        assert_eq!(dates(&schedules[0]), vec![]);
+        assert_eq!(dates(&schedules[1]), vec![date(1, 3)]);
+    }
+}
@@ -1,5 +1,8 @@
-use jormungandr_lib::interfaces::{LeadershipLog, LeadershipLogId};
-use std::time::Duration;
+use jormungandr_lib::{
+    crypto::hash::Hash,
+    interfaces::{LeadershipLog, LeadershipLogId, LeadershipLogStatus},
+};
+use std::{fmt, time::Duration};
 use tokio::{
     prelude::*,
     sync::lock::{Lock, LockGuard},
@@ -15,6 +18,7 @@ pub struct Logs(Lock<internal::Logs>);
 /// without having to hold the [`Logs`]
 ///
 /// [`Logs`]: ./struct.Logs.html
+#[derive(Clone)]
 pub struct LeadershipLogHandle {
     internal_id: LeadershipLogId,
     logs: Logs,
@@ -30,6 +34,8 @@ impl LeadershipLogHandle {
     /// on non-release build, this function will panic if the log was already
     /// marked as awaken.
     ///
+    /// Nothing is done if the log has already been purged.
+    ///
     pub fn mark_wake(&self) -> impl Future<Item = (), Error = ()> {
         self.logs.mark_wake(self.internal_id)
     }
@@ -44,9 +50,30 @@ impl LeadershipLogHandle {
     /// on non-release build, this function will panic if the log was already
     /// marked as finished.
     ///
+    /// Nothing is done if the log has already been purged.
+    ///
     pub fn mark_finished(&self) -> impl Future<Item = (), Error = ()> {
         self.logs.mark_finished(self.internal_id)
     }
+
+    /// set the outcome of the leadership event.
+    pub fn set_status(&self, status: LeadershipLogStatus) -> impl Future<Item = (), Error = ()> {
+        self.logs.set_status(self.internal_id, status)
+    }
+
+    /// remove the log of a leadership event that has been cancelled
+    /// before being triggered.
+    pub fn remove(&self) -> impl Future<Item = (), Error = ()> {
+        self.logs.remove(self.internal_id)
+    }
+}
+
+impl fmt::Debug for LeadershipLogHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LeadershipLogHandle")
+            .field("internal_id", &self.internal_id)
+            .finish()
+    }
 }
 
 impl Logs {
@@ -93,6 +120,42 @@ impl Logs {
         })
     }
 
+    fn set_status(
+        &self,
+        leadership_log_id: LeadershipLogId,
+        status: LeadershipLogStatus,
+    ) -> impl Future<Item = (), Error = ()> {
+        self.inner().and_then(move |mut guard| {
+            guard.set_status(&leadership_log_id, status);
+            future::ok(())
+        })
+    }
+
+    /// mark the logs of the given blocks as orphaned, the blocks are no
+    /// longer part of the main branch.
+    pub fn mark_orphaned(&self, blocks: Vec<Hash>) -> impl Future<Item = (), Error = ()> {
+        self.inner().and_then(move |mut guard| {
+            guard.mark_orphaned(&blocks);
+            future::ok(())
+        })
+    }
+
+    /// mark the logs of the given blocks, previously orphaned, as part of
+    /// the main branch again, with the number of fragments of each block.
+    pub fn mark_adopted(&self, blocks: Vec<(Hash, usize)>) -> impl Future<Item = (), Error = ()> {
+        self.inner().and_then(move |mut guard| {
+            guard.mark_adopted(&blocks);
+            future::ok(())
+        })
+    }
+
+    fn remove(&self, leadership_log_id: LeadershipLogId) -> impl Future<Item = (), Error = ()> {
+        self.inner().and_then(move |mut guard| {
+            guard.remove(&leadership_log_id);
+            future::ok(())
+        })
+    }
+
     pub fn poll_purge(&mut self) -> impl Future<Item = (), Error = timer::Error> {
         self.inner()
             .and_then(move |mut guard| future::poll_fn(move || guard.poll_purge()))
@@ -110,7 +173,7 @@ impl Logs {
 }
 
 pub(super) mod internal {
-    use super::{LeadershipLog, LeadershipLogId};
+    use super::{Hash, LeadershipLog, LeadershipLogId, LeadershipLogStatus};
     use std::{
         collections::HashMap,
         time::{Duration, Instant},
@@ -160,8 +223,6 @@ pub(super) mod internal {
                 log.mark_wake();
 
                 self.expirations.reset_at(key, Instant::now() + self.ttl);
-            } else {
-                unimplemented!()
             }
         }
 
@@ -170,8 +231,56 @@ pub(super) mod internal {
                 log.mark_finished();
 
                 self.expirations.reset_at(key, Instant::now() + self.ttl);
-            } else {
-                unimplemented!()
+            }
+        }
+
+        pub fn set_status(
+            &mut self,
+            leadership_log_id: &LeadershipLogId,
+            status: LeadershipLogStatus,
+        ) {
+            if let Some((ref mut log, ref key)) = self.entries.get_mut(leadership_log_id) {
+                log.set_status(status);
+
+                self.expirations.reset_at(key, Instant::now() + self.ttl);
+            }
+        }
+
+        pub fn mark_orphaned(&mut self, blocks: &[Hash]) {
+            for (log, key) in self.entries.values_mut() {
+                let orphaned = match log.status() {
+                    LeadershipLogStatus::Block { block, .. } if blocks.contains(block) => *block,
+                    _ => continue,
+                };
+                log.set_status(LeadershipLogStatus::Orphaned { block: orphaned });
+
+                self.expirations.reset_at(key, Instant::now() + self.ttl);
+            }
+        }
+
+        pub fn mark_adopted(&mut self, blocks: &[(Hash, usize)]) {
+            for (log, key) in self.entries.values_mut() {
+                let adopted = match log.status() {
+                    LeadershipLogStatus::Orphaned { block } => {
+                        match blocks.iter().find(|(adopted, _)| adopted == block) {
+                            Some(adopted) => *adopted,
+                            None => continue,
+                        }
+                    }
+                    _ => continue,
+                };
+                log.set_status(LeadershipLogStatus::Block {
+                    block: adopted.0,
+                    fragments: adopted.1,
+                });
+
+                self.expirations.reset_at(key, Instant::now() + self.ttl);
+            }
+        }
+
+        pub fn remove(&mut self, leadership_log_id: &LeadershipLogId) {
+            if let Some((_, key)) = self.entries.remove(leadership_log_id) {
+                self.expirations.remove(&key);
             }
         }
 
@@ -192,3 +301,137 @@ pub(super) mod internal {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockcfg::BlockDate;
+    use jormungandr_lib::{interfaces::EnclaveLeaderId, time::SystemTime};
+    use tokio::runtime::current_thread::Runtime;
+
+    /// run the test in a runtime, the logs' expirations need a timer
+    fn with_runtime<F: FnOnce()>(test: F) {
+        Runtime::new()
+            .unwrap()
+            .block_on(future::lazy(|| {
+                test();
+                Ok::<(), ()>(())
+            }))
+            .unwrap();
+    }
+
+    fn log(slot_id: u32) -> LeadershipLog {
+        let date = BlockDate { epoch: 1, slot_id };
+        LeadershipLog::new(EnclaveLeaderId::new(), date.into(), SystemTime::now())
+    }
+
+    fn block(slot_id: u32) -> LeadershipLogStatus {
+        LeadershipLogStatus::Block {
+            block: Hash::from([slot_id as u8; 32]),
+            fragments: slot_id as usize,
+        }
+    }
+
+    fn orphaned(slot_id: u32) -> LeadershipLogStatus {
+        LeadershipLogStatus::Orphaned {
+            block: Hash::from([slot_id as u8; 32]),
+        }
+    }
+
+    fn statuses(logs: &internal::Logs) -> Vec<LeadershipLogStatus> {
+        let mut logs: Vec<_> = logs.logs().collect();
+        logs.sort_by_key(|log| log.leadership_log_id());
+        logs.into_iter().map(|log| log.status().clone()).collect()
+    }
+
+    #[test]
+    fn set_status_updates_the_log_only() {
+        with_runtime(|| {
+            let mut logs = internal::Logs::new(Duration::from_secs(3600));
+            let id = logs.insert(log(1));
+            logs.insert(log(2));
+
+            logs.set_status(&id, block(1));
+
+            assert_eq!(
+                statuses(&logs),
+                vec![block(1), LeadershipLogStatus::Pending]
+            );
+        });
+    }
+
+    #[test]
+    fn set_status_ignores_unknown_logs() {
+        with_runtime(|| {
+            let mut logs = internal::Logs::new(Duration::from_secs(3600));
+            let id = log(1).leadership_log_id();
+            logs.insert(log(2));
+
+            logs.set_status(&id, block(1));
+
+            assert_eq!(statuses(&logs), vec![LeadershipLogStatus::Pending]);
+        });
+    }
+
+    #[test]
+    fn mark_wake_and_finished_ignore_purged_logs() {
+        with_runtime(|| {
+            let mut logs = internal::Logs::new(Duration::from_secs(3600));
+            let id = log(1).leadership_log_id();
+            logs.insert(log(2));
+
+            logs.mark_wake(&id);
+            logs.mark_finished(&id);
+
+            assert_eq!(statuses(&logs), vec![LeadershipLogStatus::Pending]);
+        });
+    }
+
+    #[test]
+    fn mark_orphaned_updates_the_given_blocks_only() {
+        with_runtime(|| {
+            let mut logs = internal::Logs::new(Duration::from_secs(3600));
+            for slot_id in 1..=3 {
+                let id = logs.insert(log(slot_id));
+                logs.set_status(&id, block(slot_id));
+            }
+            let id = logs.insert(log(4));
+            logs.set_status(
+                &id,
+                LeadershipLogStatus::Rejected {
+                    reason: "invalid".to_owned(),
+                },
+            );
+
+            logs.mark_orphaned(&[Hash::from([2; 32]), Hash::from([4; 32])]);
+
+            assert_eq!(
+                statuses(&logs),
+                vec![
+                    block(1),
+                    orphaned(2),
+                    block(3),
+                    LeadershipLogStatus::Rejected {
+                        reason: "invalid".to_owned()
+                    },
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn mark_adopted_reverts_the_orphaned_blocks() {
+        with_runtime(|| {
+            let mut logs = internal::Logs::new(Duration::from_secs(3600));
+            for slot_id in 1..=3 {
+                let id = logs.insert(log(slot_id));
+                logs.set_status(&id, block(slot_id));
+            }
+            logs.mark_orphaned(&[Hash::from([1; 32]), Hash::from([2; 32])]);
+
+            logs.mark_adopted(&[(Hash::from([2; 32]), 2), (Hash::from([3; 32]), 0)]);
+
+            assert_eq!(statuses(&logs), vec![orphaned(1), block(2), block(3)]);
+        });
+    }
+}
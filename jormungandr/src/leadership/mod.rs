@@ -58,10 +58,12 @@
 //!
 
 mod enclave;
+mod epoch_schedule;
 mod logs;
 mod schedule;
 
 pub use self::enclave::{Enclave, Error as EnclaveError, LeaderEvent};
+pub use self::epoch_schedule::EpochSchedule;
 pub use self::logs::{LeadershipLogHandle, Logs};
 pub use self::schedule::{Schedule, Schedules};
 use crate::{
@@ -70,14 +72,22 @@ use crate::{
     },
     blockchain::Branch,
     fragment,
-    intercom::BlockMsg,
-    utils::{async_msg::MessageBox, task::TokioServiceInfo},
+    intercom::{BlockMsg, LeadershipMsg},
+    stats_counter::StatsCounter,
+    utils::{
+        async_msg::{MessageBox, MessageQueue},
+        task::TokioServiceInfo,
+    },
 };
 use chain_time::{
     era::{EpochPosition, EpochSlotOffset},
-    TimeFrame,
+    TimeEra, TimeFrame,
+};
+use jormungandr_lib::{
+    interfaces::{EnclaveLeaderId, LeadershipLogStatus},
+    time::SystemTime,
 };
-use jormungandr_lib::time::SystemTime;
+use slog::Logger;
 use std::{sync::Arc, time::Duration};
 use tokio::{
     prelude::*,
@@ -93,6 +103,9 @@ error_chain! {
         NewEpochToScheduleReceiverError {
             description("cannot receive new epoch to schedule notification "),
         }
+        LeadershipMsgReceiverError {
+            description("cannot receive leadership messages"),
+        }
         FragmentSelectionFailed {
             description("fragment selection failed")
         }
@@ -105,20 +118,36 @@ error_chain! {
     }
 }
 
+#[derive(Clone)]
 pub struct NewEpochToSchedule {
     pub new_schedule: Arc<Leadership>,
     pub new_parameters: Arc<LedgerParameters>,
     pub time_frame: TimeFrame,
+    /// the era of the epoch, relating the block dates to the slots of the
+    /// `time_frame`
+    pub era: TimeEra,
 }
 
 pub struct LeadershipModule {
     logs: Logs,
+    epoch_schedule: EpochSchedule,
     service_info: TokioServiceInfo,
     enclave: Enclave,
     fragment_pool: fragment::Pool,
     tip: Branch,
     block_message: MessageBox<BlockMsg>,
     garbage_collection_interval: Duration,
+    fragment_selection: fragment::selection::Algorithm,
+    stats_counter: StatsCounter,
+    /// the last scheduled epoch, to schedule the leaders added to the
+    /// enclave during the epoch
+    current_epoch: Option<NewEpochToSchedule>,
+}
+
+/// the events, other than the schedules, handled by the leadership module
+enum Event {
+    NewEpoch(NewEpochToSchedule),
+    Leadership(LeadershipMsg),
 }
 
 impl LeadershipModule {
@@ -131,29 +160,52 @@ impl LeadershipModule {
         let date = leader_event.date.clone();
         let ledger_parameters = schedule.epoch_ledger_parameters;
         let sender = self.block_message.clone();
+        let fragment_selection = self.fragment_selection;
         let log_awake = schedule.log.mark_wake();
         let log_finish = schedule.log.mark_finished();
+        let log = schedule.log;
+        let failure_log = log.clone();
+        let stats_counter = self.stats_counter.clone();
 
         self.service_info.spawn(
             log_awake
                 .map_err(|()| unreachable!())
-                .and_then(move |()| {
-                    info!(logger, "leader event starting");
-
-                    prepare_block(fragment_pool, date, tip, ledger_parameters)
+                .and_then({
+                    let logger = logger.clone();
+                    move |()| {
+                        info!(logger, "leader event starting");
+
+                        prepare_block(
+                            fragment_pool,
+                            date,
+                            tip,
+                            ledger_parameters,
+                            fragment_selection,
+                        )
+                    }
                 })
                 .and_then(move |bb| {
                     enclave
                         .query_block_finalize(bb, leader_event)
-                        .map_err(|_| unimplemented!())
+                        .map_err(|e| Error::with_chain(e, ErrorKind::Enclave))
                 })
-                .and_then(|block| {
+                .and_then(move |block| {
                     sender
-                        .send(BlockMsg::LeadershipBlock(block))
+                        .send(BlockMsg::LeadershipBlock(block, log))
                         .map_err(|_send_error| ErrorKind::CannotSendLeadershipBlock.into())
                 })
                 .and_then(|_: MessageBox<BlockMsg>| log_finish.map_err(|()| unreachable!()))
-                .map_err(|_: Error| unimplemented!()),
+                .or_else(move |error: Error| {
+                    // the block is not produced for this slot, the module
+                    // carries on with the next scheduled slots
+                    let reason = error_reason(&error);
+                    error!(logger, "cannot produce the block of the leader event";
+                        "reason" => reason.clone());
+                    stats_counter.add_leadership_failure_cnt(1);
+                    failure_log
+                        .set_status(LeadershipLogStatus::Rejected { reason })
+                        .and_then(move |()| failure_log.mark_finished())
+                }),
         );
     }
 
@@ -191,32 +243,21 @@ impl LeadershipModule {
     }
 
     fn handle_new_epoch_event(
-        self,
+        mut self,
         scheduler: Schedules,
         new_epoch_event: NewEpochToSchedule,
     ) -> impl Future<Item = (Self, Schedules), Error = Error> {
-        let leadership = new_epoch_event.new_schedule;
-        let epoch_parameters = new_epoch_event.new_parameters;
-        let era = leadership.era().clone();
+        let leadership = new_epoch_event.new_schedule.clone();
         let epoch = leadership.epoch();
-        let time_frame = new_epoch_event.time_frame;
-        let logs = self.logs.clone();
-
-        let current_slot = time_frame.slot_at(&std::time::SystemTime::now()).unwrap();
-        let within_era = era.from_slot_to_era(current_slot).unwrap();
-
-        let slot_start = within_era.slot.0;
-        let nb_slots = era.slots_per_epoch() - slot_start;
+        let era = &new_epoch_event.era;
+        let nb_slots = era.slots_per_epoch();
 
         let logger = self.service_info.logger().new(o!("epoch" => epoch));
 
-        debug!(logger, "handling new epoch event";
-            "slot start" => slot_start,
-            "nb_slots" => nb_slots,
-        );
+        debug!(logger, "handling new epoch event"; "nb_slots" => nb_slots);
 
         self.spawn_end_of_epoch(
-            &time_frame,
+            &new_epoch_event.time_frame,
             epoch,
             era.from_era_to_slot(EpochPosition {
                 epoch: chain_time::Epoch(epoch + 1),
@@ -224,25 +265,147 @@ impl LeadershipModule {
             }),
         );
 
+        self.current_epoch = Some(new_epoch_event.clone());
+
+        // the whole epoch is evaluated for the epoch schedule, the slots
+        // already past are not scheduled
         self.enclave
-            .query_schedules(leadership.clone(), slot_start, nb_slots)
+            .query_schedules(leadership, 0, nb_slots)
             .map_err(|e| Error::with_chain(e, ErrorKind::Enclave))
             .and_then(move |schedules| {
+                self.schedule_events(scheduler, &new_epoch_event, schedules, logger)
+            })
+    }
+
+    fn handle_leadership_msg(
+        self,
+        mut scheduler: Schedules,
+        msg: LeadershipMsg,
+    ) -> impl Future<Item = (Self, Schedules), Error = Error> {
+        match msg {
+            LeadershipMsg::LeaderAdded(leader_id) => {
+                future::Either::A(self.handle_leader_added(scheduler, leader_id))
+            }
+            LeadershipMsg::LeaderRemoved(leader_id) => {
+                let cancelled = scheduler.cancel(leader_id);
+                debug!(self.service_info.logger(), "leader removed from the enclave";
+                    "leader" => leader_id.to_string(),
+                    "cancelled schedules" => cancelled.len(),
+                );
+                future::Either::B(
+                    stream::iter_ok::<_, ()>(cancelled)
+                        .for_each(|schedule| schedule.log.remove())
+                        .and_then({
+                            let epoch_schedule = self.epoch_schedule.clone();
+                            move |()| epoch_schedule.remove_leader(leader_id)
+                        })
+                        .map_err(|()| unreachable!())
+                        .map(move |()| (self, scheduler)),
+                )
+            }
+        }
+    }
+
+    /// schedule the remaining slots of the ongoing epoch for a leader
+    /// added to the enclave during the epoch, the slots already past are
+    /// only added to the epoch schedule
+    fn handle_leader_added(
+        self,
+        scheduler: Schedules,
+        leader_id: EnclaveLeaderId,
+    ) -> impl Future<Item = (Self, Schedules), Error = Error> {
+        let logger = self
+            .service_info
+            .logger()
+            .new(o!("leader" => leader_id.to_string()));
+
+        let current_epoch = match self.current_epoch.clone() {
+            Some(current_epoch) => current_epoch,
+            None => {
+                debug!(logger, "leader added before the first epoch is scheduled");
+                return future::Either::A(future::ok((self, scheduler)));
+            }
+        };
+
+        let leadership = current_epoch.new_schedule.clone();
+        let nb_slots = current_epoch.era.slots_per_epoch();
+        let logger = logger.new(o!("epoch" => leadership.epoch()));
+
+        debug!(logger, "leader added to the enclave"; "nb_slots" => nb_slots);
+
+        future::Either::B(
+            self.enclave
+                .query_leader_schedules(leadership, leader_id, 0, nb_slots)
+                .then(move |result| match result {
+                    Ok(schedules) => future::Either::A(self.schedule_events(
+                        scheduler,
+                        &current_epoch,
+                        schedules,
+                        logger,
+                    )),
+                    // the leader is scheduled from the next epoch, the
+                    // module carries on with the other leaders
+                    Err(error) => {
+                        error!(logger, "cannot schedule the leader added to the enclave";
+                            "reason" => error.to_string());
+                        future::Either::B(future::ok((self, scheduler)))
+                    }
+                }),
+        )
+    }
+
+    /// record the leader events in the epoch schedule and schedule the
+    /// ones yet to come
+    fn schedule_events(
+        self,
+        scheduler: Schedules,
+        epoch: &NewEpochToSchedule,
+        schedules: Vec<LeaderEvent>,
+        logger: Logger,
+    ) -> impl Future<Item = (Self, Schedules), Error = Error> {
+        let logs = self.logs.clone();
+        let leadership = epoch.new_schedule.clone();
+        let epoch_parameters = epoch.new_parameters.clone();
+        let time_frame = epoch.time_frame.clone();
+        let era = epoch.era.clone();
+
+        let schedules: Vec<(LeaderEvent, SystemTime)> = schedules
+            .into_iter()
+            .map(|schedule| {
+                let slot = era.from_era_to_slot(EpochPosition {
+                    epoch: chain_time::Epoch(schedule.date.epoch),
+                    slot: EpochSlotOffset(schedule.date.slot_id),
+                });
+                let slot_system_time: SystemTime = time_frame
+                    .slot_to_systemtime(slot)
+                    .expect("The slot should always be in the given time frame here")
+                    .into();
+                (schedule, slot_system_time)
+            })
+            .collect();
+        let slots = schedules
+            .iter()
+            .map(|(schedule, slot_system_time)| {
+                (schedule.id, schedule.date.into(), *slot_system_time)
+            })
+            .collect();
+
+        self.epoch_schedule
+            .insert(leadership.epoch(), slots)
+            .map_err(|()| unreachable!())
+            .and_then(move |()| {
                 stream::iter_ok::<_, Error>(schedules).fold(
                     scheduler,
-                    move |scheduler, schedule| {
-                        let slot = era.from_era_to_slot(EpochPosition {
-                            epoch: chain_time::Epoch(schedule.date.epoch),
-                            slot: EpochSlotOffset(schedule.date.slot_id),
-                        });
-                        let slot_system_time: SystemTime = time_frame
-                            .slot_to_systemtime(slot)
-                            .expect("The slot should always be in the given time frame here")
-                            .into();
-
+                    move |scheduler, (schedule, slot_system_time)| {
                         let now = SystemTime::now();
 
-                        if slot_system_time <= now {
+                        if scheduler.is_scheduled(schedule.id, &schedule.date) {
+                            debug!(logger, "ignoring already scheduled leader event";
+                                "leader"     => schedule.id.to_string(),
+                                "block date" => schedule.date.to_string(),
+                            );
+                            future::Either::A(future::ok(scheduler))
+                        } else if slot_system_time <= now {
                             debug!(logger, "ignoring new leader event";
                                 "leader"     => schedule.id.to_string(),
                                 "block date" => schedule.date.to_string(),
@@ -296,44 +459,59 @@ impl LeadershipModule {
     pub fn start(
         service_info: TokioServiceInfo,
         logs: Logs,
+        epoch_schedule: EpochSchedule,
         garbage_collection_interval: Duration,
         enclave: Enclave,
         fragment_pool: fragment::Pool,
+        fragment_selection: fragment::selection::Algorithm,
+        stats_counter: StatsCounter,
         tip_branch: Branch,
         new_epoch_events: mpsc::Receiver<NewEpochToSchedule>,
+        leadership_queue: MessageQueue<LeadershipMsg>,
         block_message: MessageBox<BlockMsg>,
     ) -> impl Future<Item = (), Error = Error> {
-        let scheduler_future = Schedules::new().into_future();
-        let new_epoch_future = new_epoch_events.into_future();
+        let scheduler: Schedules = Schedules::new();
+        let scheduler_future = scheduler.into_future();
+        let events = new_epoch_events
+            .map(Event::NewEpoch)
+            .map_err(|error| Error::with_chain(error, ErrorKind::NewEpochToScheduleReceiverError))
+            .select(
+                leadership_queue
+                    .map(Event::Leadership)
+                    .map_err(|()| ErrorKind::LeadershipMsgReceiverError.into()),
+            );
+        let events_future = events.into_future();
 
         let leadership_module = LeadershipModule {
             logs,
+            epoch_schedule,
             service_info,
             enclave,
             fragment_pool,
             tip: tip_branch,
             block_message,
             garbage_collection_interval,
+            fragment_selection,
+            stats_counter,
+            current_epoch: None,
         };
 
         leadership_module.spawn_log_purge();
 
         future::loop_fn(
-            (leadership_module, scheduler_future, new_epoch_future),
-            |(leadership_module, scheduler_future, new_epoch_future)| {
+            (leadership_module, scheduler_future, events_future),
+            |(leadership_module, scheduler_future, events_future)| {
                 scheduler_future
-                    .select2(new_epoch_future)
+                    .select2(events_future)
                     .map_err(|either| match either {
-                        future::Either::A(((error, _scheduler), _new_epoch_events)) => {
+                        future::Either::A(((error, _scheduler), _events)) => {
                             Error::with_chain(error, ErrorKind::ScheduleError)
                         }
-                        future::Either::B(((error, _new_epoch_events), _scheduler)) => {
-                            Error::with_chain(error, ErrorKind::NewEpochToScheduleReceiverError)
-                        }
+                        future::Either::B(((error, _events), _scheduler)) => error,
                     })
                     .and_then(move |either| {
                         match either {
-                            future::Either::A(((schedule, schedules), new_epoch_future)) => {
+                            future::Either::A(((schedule, schedules), events_future)) => {
                                 if let Some(schedule) = schedule {
                                     leadership_module.handle_schedule(schedule.into_inner());
                                 } else {
@@ -347,30 +525,34 @@ impl LeadershipModule {
                                 future::Either::A(future::ok((
                                     leadership_module,
                                     scheduler_future,
-                                    new_epoch_future,
+                                    events_future,
                                 )))
                             }
-                            future::Either::B((
-                                (new_epoch_event, new_epoch_events),
-                                scheduler_future,
-                            )) => {
-                                let new_epoch_event =
-                                    new_epoch_event.expect("Expect the event to not close");
+                            future::Either::B(((event, events), scheduler_future)) => {
+                                let event = event.expect("Expect the event to not close");
 
                                 // the stream didn't yield an element so we can retrieve the inner schedule here
                                 let schedules = scheduler_future.into_inner().unwrap();
 
-                                future::Either::B(
-                                    leadership_module
-                                        .handle_new_epoch_event(schedules, new_epoch_event)
-                                        .map(move |(leadership_module, schedules)| {
-                                            (
-                                                leadership_module,
-                                                schedules.into_future(),
-                                                new_epoch_events.into_future(),
-                                            )
-                                        }),
-                                )
+                                let handled = match event {
+                                    Event::NewEpoch(new_epoch_event) => future::Either::A(
+                                        leadership_module
+                                            .handle_new_epoch_event(schedules, new_epoch_event),
+                                    ),
+                                    Event::Leadership(msg) => future::Either::B(
+                                        leadership_module.handle_leadership_msg(schedules, msg),
+                                    ),
+                                };
+
+                                future::Either::B(handled.map(
+                                    move |(leadership_module, schedules)| {
+                                        (
+                                            leadership_module,
+                                            schedules.into_future(),
+                                            events.into_future(),
+                                        )
+                                    },
+                                ))
                             }
                         }
                     })
@@ -384,15 +566,25 @@ impl LeadershipModule {
     }
 }
 
+/// the description of the error followed by the ones of its causes
+fn error_reason(error: &Error) -> String {
+    error
+        .iter()
+        .map(|error| error.to_string())
+        .collect::<Vec<_>>()
+        .join(": ")
+}
+
 fn prepare_block(
     mut fragment_pool: fragment::Pool,
     date: BlockDate,
     tip: Branch,
     epoch_parameters: Arc<LedgerParameters>,
+    fragment_selection: fragment::selection::Algorithm,
 ) -> impl Future<Item = BlockBuilder, Error = Error> {
-    use crate::fragment::selection::{FragmentSelectionAlgorithm as _, OldestFirst};
-
-    let selection_algorithm = OldestFirst::new(250 /* TODO!! */);
+    use crate::fragment::selection::{
+        Algorithm, FragmentSelectionAlgorithm as _, HighestFeeFirst, OldestFirst,
+    };
 
     tip.get_ref()
         .map_err(|_: std::convert::Infallible| unreachable!())
@@ -401,7 +593,8 @@ fn prepare_block(
 
             let parent_id = tip_reference.hash().clone();
             let chain_length = tip_reference.chain_length().next();
-            let ledger = tip_reference.ledger();
+            let ledger = tip_reference.ledger().as_ref().clone();
+            let epoch_parameters = epoch_parameters.as_ref().clone();
 
             let metadata = HeaderContentEvalContext {
                 block_date: date,
@@ -409,14 +602,33 @@ fn prepare_block(
                 nonce: None,
             };
 
-            fragment_pool
-                .select(
-                    ledger.as_ref().clone(),
-                    metadata,
-                    epoch_parameters.as_ref().clone(),
-                    selection_algorithm,
-                )
-                .map(|selection_algorithm| selection_algorithm.finalize())
+            let selection = match fragment_selection {
+                Algorithm::OldestFirst { max_per_block } => future::Either::A(
+                    fragment_pool
+                        .select(
+                            ledger,
+                            metadata,
+                            epoch_parameters,
+                            OldestFirst::new(max_per_block),
+                        )
+                        .map(|selection_algorithm| selection_algorithm.finalize()),
+                ),
+                Algorithm::HighestFeeFirst {
+                    max_per_block,
+                    max_block_size,
+                } => future::Either::B(
+                    fragment_pool
+                        .select(
+                            ledger,
+                            metadata,
+                            epoch_parameters,
+                            HighestFeeFirst::new(max_per_block, max_block_size),
+                        )
+                        .map(|selection_algorithm| selection_algorithm.finalize()),
+                ),
+            };
+
+            selection
                 .map(move |mut bb| {
                     bb.date(date).parent(parent_id).chain_length(chain_length);
                     bb
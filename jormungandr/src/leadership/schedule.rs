@@ -1,9 +1,12 @@
 use crate::{
-    blockcfg::{Leadership, LedgerParameters},
+    blockcfg::{BlockDate, Leadership, LedgerParameters},
     leadership::{LeaderEvent, LeadershipLogHandle, Logs},
 };
-use jormungandr_lib::{interfaces::LeadershipLog, time::SystemTime};
-use std::sync::Arc;
+use jormungandr_lib::{
+    interfaces::{EnclaveLeaderId, LeadershipLog},
+    time::SystemTime,
+};
+use std::{collections::HashMap, sync::Arc, time::Instant};
 use tokio::{
     prelude::*,
     timer::delay_queue::{self, DelayQueue},
@@ -25,8 +28,11 @@ pub struct Schedule {
 }
 
 /// one of the main issue with the current build for the
-pub struct Schedules {
-    scheduler: DelayQueue<Schedule>,
+pub struct Schedules<T = Schedule> {
+    scheduler: DelayQueue<T>,
+    /// the keys of the pending schedules of every leader, to cancel them
+    /// when the leader is removed from the enclave
+    keys: HashMap<EnclaveLeaderId, HashMap<BlockDate, delay_queue::Key>>,
 }
 
 impl Schedule {
@@ -47,13 +53,69 @@ impl Schedule {
     }
 }
 
-impl Schedules {
+impl AsRef<LeaderEvent> for Schedule {
+    fn as_ref(&self) -> &LeaderEvent {
+        &self.leader_event
+    }
+}
+
+impl<T: AsRef<LeaderEvent>> Schedules<T> {
     pub fn new() -> Self {
         Schedules {
             scheduler: DelayQueue::new(),
+            keys: HashMap::new(),
+        }
+    }
+
+    /// tell if the leader already has a pending schedule at the given date
+    pub fn is_scheduled(&self, leader_id: EnclaveLeaderId, date: &BlockDate) -> bool {
+        self.keys
+            .get(&leader_id)
+            .map(|keys| keys.contains_key(date))
+            .unwrap_or(false)
+    }
+
+    /// queue the schedule until the given time, unless its leader already
+    /// has a pending schedule at the same date
+    fn insert(&mut self, schedule: T, scheduled_time: Instant) {
+        let leader_id = schedule.as_ref().id;
+        let date = schedule.as_ref().date;
+        if self.is_scheduled(leader_id, &date) {
+            return;
         }
+        let key = self.scheduler.insert_at(schedule, scheduled_time);
+        self.keys
+            .entry(leader_id)
+            .or_insert_with(HashMap::new)
+            .insert(date, key);
     }
 
+    /// remove the pending schedules of the given leader, returns the
+    /// cancelled schedules.
+    pub fn cancel(&mut self, leader_id: EnclaveLeaderId) -> Vec<T> {
+        match self.keys.remove(&leader_id) {
+            None => Vec::new(),
+            Some(keys) => keys
+                .values()
+                .map(|key| self.scheduler.remove(key).into_inner())
+                .collect(),
+        }
+    }
+
+    fn forget(&mut self, schedule: &T) {
+        let leader_id = schedule.as_ref().id;
+        if let Some(keys) = self.keys.get_mut(&leader_id) {
+            keys.remove(&schedule.as_ref().date);
+            if keys.is_empty() {
+                self.keys.remove(&leader_id);
+            }
+        }
+    }
+}
+
+impl Schedules<Schedule> {
+    /// schedule the leader event at the given time. Nothing is done if
+    /// the leader already has a pending schedule at the event's date.
     pub fn schedule(
         mut self,
         logs: Logs,
@@ -62,7 +124,11 @@ impl Schedules {
         scheduled_at_time: SystemTime,
         leader_event: LeaderEvent,
     ) -> impl Future<Item = Self, Error = ()> {
-        let now = std::time::Instant::now();
+        if self.is_scheduled(leader_event.id, &leader_event.date) {
+            return future::Either::A(future::ok(self));
+        }
+
+        let now = Instant::now();
         let duration = scheduled_at_time
             .as_ref()
             .duration_since(std::time::SystemTime::now())
@@ -70,28 +136,145 @@ impl Schedules {
         let scheduled_time = now + duration;
 
         let log = LeadershipLog::new(leader_event.id, leader_event.date.into(), scheduled_at_time);
-        logs.insert(log)
-            .map(move |handle| Schedule {
-                log: handle,
-                leadership,
-                epoch_ledger_parameters,
-                leader_event,
-            })
-            .map(move |schedule| {
-                self.scheduler.insert_at(schedule, scheduled_time);
-                self
-            })
+        future::Either::B(
+            logs.insert(log)
+                .map(move |handle| Schedule {
+                    log: handle,
+                    leadership,
+                    epoch_ledger_parameters,
+                    leader_event,
+                })
+                .map(move |schedule| {
+                    self.insert(schedule, scheduled_time);
+                    self
+                }),
+        )
     }
 }
 
-impl Stream for Schedules {
+impl<T: AsRef<LeaderEvent>> Stream for Schedules<T> {
     type Error = tokio::timer::Error;
-    type Item = delay_queue::Expired<Schedule>;
+    type Item = delay_queue::Expired<T>;
 
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
         match try_ready!(self.scheduler.poll()) {
-            Some(item) => Ok(Async::Ready(Some(item))),
+            Some(item) => {
+                self.forget(item.get_ref());
+                Ok(Async::Ready(Some(item)))
+            }
             None => Ok(Async::NotReady),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockcfg::LeaderOutput;
+    use std::time::Duration;
+    use tokio::runtime::current_thread::Runtime;
+
+    impl AsRef<LeaderEvent> for LeaderEvent {
+        fn as_ref(&self) -> &LeaderEvent {
+            self
+        }
+    }
+
+    fn leader(n: u32) -> EnclaveLeaderId {
+        (0..n).fold(EnclaveLeaderId::new(), |id, _| id.next())
+    }
+
+    fn event(leader_id: EnclaveLeaderId, slot_id: u32) -> LeaderEvent {
+        LeaderEvent {
+            id: leader_id,
+            date: BlockDate { epoch: 1, slot_id },
+            output: LeaderOutput::None,
+        }
+    }
+
+    /// run the test in a runtime, the schedules need a timer
+    fn with_runtime<F: FnOnce()>(test: F) {
+        Runtime::new()
+            .unwrap()
+            .block_on(future::lazy(|| {
+                test();
+                Ok::<(), ()>(())
+            }))
+            .unwrap();
+    }
+
+    fn later() -> Instant {
+        Instant::now() + Duration::from_secs(3600)
+    }
+
+    fn dates(mut events: Vec<LeaderEvent>) -> Vec<BlockDate> {
+        events.sort_by_key(|event| event.date.slot_id);
+        events.into_iter().map(|event| event.date).collect()
+    }
+
+    #[test]
+    fn cancel_removes_the_schedules_of_the_leader_only() {
+        with_runtime(|| {
+            let mut schedules = Schedules::new();
+            schedules.insert(event(leader(1), 1), later());
+            schedules.insert(event(leader(1), 5), later());
+            schedules.insert(event(leader(2), 3), later());
+
+            let cancelled = schedules.cancel(leader(1));
+
+            assert_eq!(
+                dates(cancelled),
+                vec![
+                    BlockDate {
+                        epoch: 1,
+                        slot_id: 1
+                    },
+                    BlockDate {
+                        epoch: 1,
+                        slot_id: 5
+                    }
+                ]
+            );
+            assert!(!schedules.is_scheduled(
+                leader(1),
+                &BlockDate {
+                    epoch: 1,
+                    slot_id: 1
+                }
+            ));
+            assert!(schedules.is_scheduled(
+                leader(2),
+                &BlockDate {
+                    epoch: 1,
+                    slot_id: 3
+                }
+            ));
+            assert_eq!(schedules.cancel(leader(2)).len(), 1);
+        });
+    }
+
+    #[test]
+    fn cancel_unknown_leader_is_a_no_op() {
+        with_runtime(|| {
+            let mut schedules = Schedules::new();
+            schedules.insert(event(leader(1), 1), later());
+
+            assert!(schedules.cancel(leader(2)).is_empty());
+            assert_eq!(schedules.cancel(leader(1)).len(), 1);
+            assert!(schedules.cancel(leader(1)).is_empty());
+        });
+    }
+
+    #[test]
+    fn same_date_is_scheduled_once() {
+        with_runtime(|| {
+            let mut schedules = Schedules::new();
+            schedules.insert(event(leader(1), 1), later());
+            schedules.insert(event(leader(1), 1), later());
+            schedules.insert(event(leader(2), 1), later());
+
+            assert_eq!(schedules.cancel(leader(1)).len(), 1);
+            assert_eq!(schedules.cancel(leader(2)).len(), 1);
+        });
+    }
+}
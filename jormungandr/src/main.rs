@@ -53,8 +53,9 @@ use crate::{
     utils::{async_msg, task::Services},
 };
 use futures::Future;
-use settings::{start::RawSettings, CommandLine};
+use settings::{start::RawSettings, ChainFileCommand, CommandLine, StorageCheck};
 use slog::Logger;
+use std::sync::mpsc;
 use std::thread;
 use std::time::Duration;
 use tokio::sync::lock::Lock;
@@ -80,6 +81,14 @@ use stats_counter::StatsCounter;
 fn start() -> Result<(), start_up::Error> {
     let initialized_node = initialize_node()?;
 
+    if let Some(command) = initialized_node.settings.chain_file.clone() {
+        return run_chain_file_command(initialized_node, command);
+    }
+
+    if let Some(check) = initialized_node.settings.storage_check {
+        return run_storage_check(initialized_node, check);
+    }
+
     let bootstrapped_node = bootstrap(initialized_node)?;
 
     start_services(bootstrapped_node)
@@ -97,13 +106,18 @@ pub struct BootstrappedNode {
 
 const FRAGMENT_TASK_QUEUE_LEN: usize = 1024;
 const NETWORK_TASK_QUEUE_LEN: usize = 32;
+const LEADERSHIP_TASK_QUEUE_LEN: usize = 32;
+
+// TODO: we should get this value from the configuration
+const BLOCK_CACHE_TTL: Duration = Duration::from_secs(5 * 24 * 3600);
 
 fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::Error> {
     let mut services = Services::new(bootstrapped_node.logger.clone());
 
     // initialize the network propagation channel
-    let (mut network_msgbox, network_queue) = async_msg::channel(NETWORK_TASK_QUEUE_LEN);
+    let (network_msgbox, network_queue) = async_msg::channel(NETWORK_TASK_QUEUE_LEN);
     let (fragment_msgbox, fragment_queue) = async_msg::channel(FRAGMENT_TASK_QUEUE_LEN);
+    let (leadership_msgbox, leadership_queue) = async_msg::channel(LEADERSHIP_TASK_QUEUE_LEN);
     let mut new_epoch_announcements = bootstrapped_node.new_epoch_announcements;
     let new_epoch_notifier = bootstrapped_node.new_epoch_notifier;
     let blockchain_tip = bootstrapped_node.blockchain_tip;
@@ -112,13 +126,22 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
         leadership::Logs::new(bootstrapped_node.settings.leadership.log_ttl.into());
     let leadership_garbage_collection_interval =
         bootstrapped_node.settings.leadership.log_ttl.into();
+    let fragment_selection = bootstrapped_node.settings.leadership.fragment_selection();
+    let leadership_schedule = leadership::EpochSchedule::new();
 
-    let stats_counter = StatsCounter::default();
+    let stats_counter = StatsCounter::new(
+        blockchain.ref_cache_stats().clone(),
+        blockchain.multiverse_stats().clone(),
+    );
 
     let (fragment_pool, pool_logs) = {
         let stats_counter = stats_counter.clone();
         let process = fragment::Process::new(
             bootstrapped_node.settings.mempool.fragment_ttl.into(),
+            fragment::PoolLimits {
+                max_entries: bootstrapped_node.settings.mempool.max_entries,
+                max_bytes: bootstrapped_node.settings.mempool.max_bytes,
+            },
             bootstrapped_node.settings.mempool.log_ttl.into(),
             bootstrapped_node
                 .settings
@@ -130,16 +153,20 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
         let pool = process.pool().clone();
         let logs = process.logs().clone();
 
+        let network_msgbox = network_msgbox.clone();
         services.spawn_future("fragment", move |info| {
-            process.start(info, stats_counter, fragment_queue)
+            process.start(info, stats_counter, network_msgbox, fragment_queue)
         });
         (pool, logs)
     };
 
     let block_task = {
+        let mut network_msgbox = network_msgbox.clone();
         let mut blockchain = blockchain.clone();
         let mut blockchain_tip = blockchain_tip.clone();
         let stats_counter = stats_counter.clone();
+        let mut fragment_msgbox = fragment_msgbox.clone();
+        let leadership_logs = leadership_logs.clone();
         services.spawn_future_with_inputs("block", move |info, input| {
             blockchain::handle_input(
                 info,
@@ -148,6 +175,8 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
                 &stats_counter,
                 &mut new_epoch_announcements,
                 &mut network_msgbox,
+                &mut fragment_msgbox,
+                &leadership_logs,
                 input,
             )
         })
@@ -201,25 +230,44 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
     let leader_secrets = leader_secrets?;
     let enclave = Enclave::from_vec(leader_secrets);
 
+    // the node is shut down when the REST server is stopped or when a
+    // service it cannot run without fails
+    let (shutdown_sender, shutdown_receiver) = mpsc::channel();
+
     {
         let leadership_logs = leadership_logs.clone();
+        let leadership_schedule = leadership_schedule.clone();
         let fragment_pool = fragment_pool.clone();
         let block_task = block_task.clone();
         let blockchain_tip = blockchain_tip.clone();
         let enclave = leadership::Enclave::new(enclave.clone());
+        let stats_counter = stats_counter.clone();
+        let shutdown_sender = shutdown_sender.clone();
 
         services.spawn_future("leadership", move |info| {
+            let logger = info.logger().clone();
             leadership::LeadershipModule::start(
                 info,
                 leadership_logs,
+                leadership_schedule,
                 leadership_garbage_collection_interval,
                 enclave,
                 fragment_pool,
+                fragment_selection,
+                stats_counter,
                 blockchain_tip,
                 new_epoch_notifier,
+                leadership_queue,
                 block_task,
             )
-            .map_err(|e| unimplemented!("error in leadership {}", e))
+            .map_err(move |e| {
+                crit!(
+                    logger,
+                    "error in leadership, shutting down the node";
+                    "reason" => e.to_string()
+                );
+                let _ = shutdown_sender.send(());
+            })
         });
     }
 
@@ -227,25 +275,46 @@ fn start_services(bootstrapped_node: BootstrappedNode) -> Result<(), start_up::E
         Some(rest) => {
             let context = rest::Context {
                 stats_counter,
-                blockchain,
-                blockchain_tip,
+                blockchain: blockchain.clone(),
+                blockchain_tip: blockchain_tip.clone(),
                 transaction_task: fragment_msgbox,
+                network_task: network_msgbox,
+                leadership_task: leadership_msgbox,
                 logs: pool_logs,
                 leadership_logs,
+                leadership_schedule,
                 server: Lock::new(None),
                 enclave,
+                logger: bootstrapped_node.logger.new(o!(log::KEY_TASK => "rest")),
             };
             Some(rest::start_rest_server(&rest, context)?)
         }
         None => None,
     };
 
-    match rest_server {
-        Some(server) => server.wait_for_stop(),
-        None => thread::sleep(Duration::from_secs(u64::max_value())),
+    if let Some(server) = rest_server.clone() {
+        thread::spawn(move || {
+            server.wait_for_stop();
+            let _ = shutdown_sender.send(());
+        });
+    }
+    let _ = shutdown_receiver.recv();
+    if let Some(server) = rest_server {
+        server.stop();
     }
     info!(bootstrapped_node.logger, "Shutting down node");
 
+    // the services are not waited on, make sure the tip is saved so the
+    // node resumes from it on the next start
+    blockchain::save_tip(&bootstrapped_node.logger, &blockchain, &blockchain_tip)
+        .wait()
+        .unwrap_or_else(|err| {
+            error!(
+                bootstrapped_node.logger,
+                "cannot save the blockchain tip: {}", err
+            )
+        });
+
     Ok(())
 }
 
@@ -274,14 +343,16 @@ fn bootstrap(initialized_node: InitializedNode) -> Result<BootstrappedNode, star
 
     let block0_hash = block0.header.hash();
 
-    // TODO: we should get this value from the configuration
-    let block_cache_ttl: Duration = Duration::from_secs(5 * 24 * 3600);
-
     let (blockchain, blockchain_tip) = start_up::load_blockchain(
         block0,
         storage,
+        start_up::prepare_storage_pruning(&settings),
         new_epoch_announcements.clone(),
-        block_cache_ttl,
+        BLOCK_CACHE_TTL,
+        blockchain::CacheLimits {
+            max_refs: settings.block_cache.max_blocks,
+            max_ledgers: settings.block_cache.max_ledgers,
+        },
     )?;
 
     network::bootstrap(
@@ -302,6 +373,49 @@ fn bootstrap(initialized_node: InitializedNode) -> Result<BootstrappedNode, star
     })
 }
 
+/// run the offline operation on a chain file instead of starting the node
+fn run_chain_file_command(
+    initialized_node: InitializedNode,
+    command: ChainFileCommand,
+) -> Result<(), start_up::Error> {
+    let InitializedNode {
+        block0,
+        storage,
+        logger,
+        ..
+    } = initialized_node;
+    let logger = logger.new(o!(log::KEY_TASK => "chain_file"));
+
+    match command {
+        ChainFileCommand::Export(path) => {
+            start_up::export_chain(storage, block0, &path, BLOCK_CACHE_TTL, &logger)
+        }
+        ChainFileCommand::Import(path) => {
+            start_up::import_chain(storage, block0, &path, BLOCK_CACHE_TTL, &logger)
+        }
+    }
+}
+
+/// verify the blocks of the storage instead of starting the node
+fn run_storage_check(
+    initialized_node: InitializedNode,
+    check: StorageCheck,
+) -> Result<(), start_up::Error> {
+    let InitializedNode {
+        block0,
+        storage,
+        logger,
+        ..
+    } = initialized_node;
+    let logger = logger.new(o!(log::KEY_TASK => "verify_storage"));
+
+    let repair = match check {
+        StorageCheck::Verify => false,
+        StorageCheck::Repair => true,
+    };
+    start_up::verify_storage(storage, block0, repair, BLOCK_CACHE_TTL, &logger)
+}
+
 pub struct InitializedNode {
     pub settings: Settings,
     pub block0: blockcfg::Block,
@@ -1,19 +1,21 @@
 use super::{
     chain_pull, grpc,
     inbound::InboundProcessing,
-    p2p::comm::{PeerComms, Subscription},
+    p2p::comm::{Misbehavior, PeerComms, Subscription},
     p2p::topology,
-    subscription, Channels, ConnectionState, GlobalStateR,
+    subscription, Channels, ConnectionState, GlobalState, GlobalStateR,
 };
 use crate::{
-    blockcfg::{Block, Header, HeaderHash},
+    blockcfg::{Block, Fragment, Header, HeaderHash},
     intercom::{self, BlockMsg, ClientMsg},
 };
 use futures::prelude::*;
 use network_core::client::block::BlockService;
+use network_core::client::content::ContentService;
 use network_core::client::gossip::GossipService;
 use network_core::client::p2p::P2pService;
 use network_core::client::{self as core_client, Client as _};
+use network_core::error as core_error;
 use network_core::gossip::Node;
 use network_core::subscription::{BlockEvent, ChainPullRequest};
 use slog::Logger;
@@ -49,8 +51,10 @@ where
     S: P2pService<NodeId = topology::NodeId>,
     S: BlockService<Block = Block>,
     S: GossipService<Node = topology::Node>,
+    S: ContentService<Fragment = Fragment>,
     S::UploadBlocksFuture: Send + 'static,
     S::GossipSubscription: Send + 'static,
+    S::ContentSubscription: Send + 'static,
 {
     fn subscribe(
         service: S,
@@ -59,6 +63,7 @@ where
     ) -> impl Future<Item = (Self, PeerComms), Error = ()> {
         let mut peer_comms = PeerComms::new();
         let err_logger = state.logger().clone();
+        let content_logger = state.logger().clone();
         service
             .ready()
             .and_then(move |mut service| {
@@ -70,12 +75,34 @@ where
             })
             .and_then(move |(mut service, mut peer_comms, block_req)| {
                 let gossip_req = service.gossip_subscription(peer_comms.subscribe_to_gossip());
-                block_req
-                    .join(gossip_req)
-                    .map(move |(block_res, gossip_res)| {
-                        (service, peer_comms, block_res, gossip_res)
-                    })
+                service
+                    .ready()
+                    .map(move |service| (service, peer_comms, block_req, gossip_req))
             })
+            .and_then(
+                move |(mut service, mut peer_comms, block_req, gossip_req)| {
+                    // older nodes do not provide the content service: the
+                    // connection is kept without the fragment subscription
+                    let content_req = service
+                        .content_subscription(peer_comms.subscribe_to_messages())
+                        .then(move |res| match res {
+                            Ok(content_res) => Ok(Some(content_res)),
+                            Err(err) => {
+                                warn!(
+                                    content_logger,
+                                    "content subscription request failed, fragments are not exchanged with the peer: {:?}",
+                                    err
+                                );
+                                Ok(None)
+                            }
+                        });
+                    block_req.join3(gossip_req, content_req).map(
+                        move |(block_res, gossip_res, content_res)| {
+                            (service, peer_comms, block_res, gossip_res, content_res)
+                        },
+                    )
+                },
+            )
             .map_err(move |err| {
                 warn!(err_logger, "subscription request failed: {:?}", err);
             })
@@ -85,19 +112,39 @@ where
                     mut peer_comms,
                     (block_events, node_id),
                     (gossip_sub, node_id_1),
+                    content_res,
                 )| {
-                    if node_id != node_id_1 {
+                    let node_id_2 = content_res.as_ref().map(|(_, node_id_2)| *node_id_2);
+                    if node_id != node_id_1 || node_id_2.map_or(false, |id| id != node_id) {
                         warn!(
                             state.logger(),
-                            "peer subscription IDs do not match: {} != {}", node_id, node_id_1
+                            "peer subscription IDs do not match: {}, {}, {:?}",
+                            node_id,
+                            node_id_1,
+                            node_id_2
                         );
                         return Err(());
                     }
+                    if state.global.peers.is_banned(node_id) {
+                        info!(state.logger(), "disconnecting from banned peer {}", node_id);
+                        return Err(());
+                    }
                     let logger = state.logger().new(o!("node_id" => node_id.0.as_u128()));
 
                     // Spin off processing tasks for subscriptions that can be
                     // managed with just the global state.
                     subscription::process_gossip(gossip_sub, state.global.clone(), logger.clone());
+                    match content_res {
+                        Some((content_sub, _)) => subscription::process_fragments(
+                            content_sub,
+                            node_id,
+                            Some(state.connection),
+                            state.global.clone(),
+                            channels.transaction_box.clone(),
+                            logger.clone(),
+                        ),
+                        None => peer_comms.set_messages_unsupported(),
+                    }
 
                     // Plug the block solicitations and header pulls to be handled
                     // via client requests.
@@ -163,14 +210,11 @@ where
             }
             BlockEvent::Missing(req) => {
                 debug!(self.logger, "received block event Missing");
-                self.push_missing_blocks(req);
+                self.push_missing_headers(req);
             }
         }
     }
 
-    // FIXME: use this to handle BlockEvent::Missing events when two-stage
-    // chain pull processing is implemented in the blockchain task.
-    #[allow(dead_code)]
     fn push_missing_headers(&mut self, req: ChainPullRequest<HeaderHash>) {
         let (reply_handle, stream) =
             intercom::stream_reply::<Header, network_core::error::Error>(self.logger.clone());
@@ -193,29 +237,6 @@ where
                 }),
         );
     }
-
-    // Temporary support for pushing chain blocks without two-stage
-    // retrieval.
-    fn push_missing_blocks(&mut self, req: ChainPullRequest<HeaderHash>) {
-        let (reply_handle, stream) =
-            intercom::stream_reply::<Block, network_core::error::Error>(self.logger.clone());
-        self.channels
-            .client_box
-            .send_to(ClientMsg::PullBlocksToTip(req.from, reply_handle));
-        let node_id = self.remote_node_id;
-        let done_logger = self.logger.clone();
-        let err_logger = self.logger.clone();
-        tokio::spawn(
-            self.service
-                .upload_blocks(stream)
-                .map(move |_| {
-                    debug!(done_logger, "finished pushing blocks to {}", node_id);
-                })
-                .map_err(move |err| {
-                    warn!(err_logger, "UploadBlocks request failed: {:?}", err);
-                }),
-        );
-    }
 }
 
 impl<S> Client<S>
@@ -224,11 +245,10 @@ where
     S::PullHeadersFuture: Send + 'static,
     S::PullHeadersStream: Send + 'static,
 {
-    // FIXME: use this to handle chain pull requests when two-stage
-    // chain pull processing is implemented in the blockchain task.
-    #[allow(dead_code)]
     fn pull_headers(&mut self, req: ChainPullRequest<HeaderHash>) {
         let block_box = self.channels.block_box.clone();
+        let global_state = self.global_state.clone();
+        let node_id = self.remote_node_id;
         let logger = self.logger.clone();
         let err_logger = logger.clone();
         tokio::spawn(
@@ -246,56 +266,15 @@ where
                         .chunks(chain_pull::CHUNK_SIZE)
                         .for_each(move |headers| {
                             let err_logger = logger.clone();
+                            let global_state = global_state.clone();
                             InboundProcessing::with_unary(
                                 block_box.clone(),
                                 logger.clone(),
                                 |reply| BlockMsg::ChainHeaders(headers, reply),
                             )
                             .map_err(move |e| {
-                                warn!(err_logger, "chain header validation failed: {:?}", e)
-                            })
-                        })
-                }),
-        );
-    }
-}
-
-impl<S> Client<S>
-where
-    S: BlockService<Block = Block>,
-    S::PullBlocksToTipFuture: Send + 'static,
-    S::PullBlocksStream: Send + 'static,
-{
-    // Temporary support for pulling chain blocks without two-stage
-    // retrieval.
-    fn pull_blocks_to_tip(&mut self, req: ChainPullRequest<HeaderHash>) {
-        let block_box = self.channels.block_box.clone();
-        let logger = self.logger.clone();
-        let err_logger = logger.clone();
-        tokio::spawn(
-            self.service
-                .pull_blocks_to_tip(&req.from)
-                .map_err(move |e| {
-                    warn!(err_logger, "PullBlocksToTip request failed: {:?}", e);
-                })
-                .and_then(move |stream| {
-                    let err_logger = logger.clone();
-                    stream
-                        .map_err(move |e| {
-                            warn!(
-                                err_logger,
-                                "PullBlocksToTip response stream failed: {:?}", e
-                            );
-                        })
-                        .for_each(move |block| {
-                            let err_logger = logger.clone();
-                            InboundProcessing::with_unary(
-                                block_box.clone(),
-                                logger.clone(),
-                                |reply| BlockMsg::NetworkBlock(block, reply),
-                            )
-                            .map_err(move |e| {
-                                warn!(err_logger, "pulled block validation failed: {:?}", e)
+                                warn!(err_logger, "chain header validation failed: {:?}", e);
+                                report_rejected_data(&global_state, node_id, &e);
                             })
                         })
                 }),
@@ -311,6 +290,8 @@ where
 {
     fn solicit_blocks(&mut self, block_ids: &[HeaderHash]) {
         let block_box = self.channels.block_box.clone();
+        let global_state = self.global_state.clone();
+        let node_id = self.remote_node_id;
         let logger = self.logger.clone();
         let err_logger = logger.clone();
         tokio::spawn(
@@ -330,13 +311,15 @@ where
                         })
                         .for_each(move |block| {
                             let err_logger = logger.clone();
+                            let global_state = global_state.clone();
                             InboundProcessing::with_unary(
                                 block_box.clone(),
                                 logger.clone(),
                                 |reply| BlockMsg::NetworkBlock(block, reply),
                             )
                             .map_err(move |e| {
-                                warn!(err_logger, "network block validation failed: {:?}", e)
+                                warn!(err_logger, "network block validation failed: {:?}", e);
+                                report_rejected_data(&global_state, node_id, &e);
                             })
                         })
                 }),
@@ -350,8 +333,6 @@ where
     S: BlockService<Block = Block>,
     S::GetBlocksFuture: Send + 'static,
     S::GetBlocksStream: Send + 'static,
-    S::PullBlocksToTipFuture: Send + 'static,
-    S::PullBlocksStream: Send + 'static,
     S::PullHeadersFuture: Send + 'static,
     S::PullHeadersStream: Send + 'static,
     S::PushHeadersFuture: Send + 'static,
@@ -377,6 +358,13 @@ where
                     return Ok(().into());
                 }
                 Async::Ready(Some(event)) => {
+                    if self.global_state.peers.is_banned(self.remote_node_id) {
+                        info!(
+                            self.logger,
+                            "disconnecting from banned peer {}", self.remote_node_id
+                        );
+                        return Ok(().into());
+                    }
                     streams_ready = true;
                     self.process_block_event(event);
                 }
@@ -403,9 +391,7 @@ where
                 }
                 Async::Ready(Some(req)) => {
                     streams_ready = true;
-                    // FIXME: implement two-stage chain pull processing
-                    // in the blockchain task and use pull_headers here.
-                    self.pull_blocks_to_tip(req);
+                    self.pull_headers(req);
                 }
             }
             if !streams_ready {
@@ -415,6 +401,22 @@ where
     }
 }
 
+// Lower the reputation of the peer if the blockchain task rejected
+// the data it has sent. Errors caused by the local node itself, like
+// storage failures or a stopped task, are not held against the peer.
+fn report_rejected_data(
+    global_state: &GlobalState,
+    node_id: topology::NodeId,
+    err: &core_error::Error,
+) {
+    let misbehavior = match err.code() {
+        core_error::Code::InvalidArgument => Misbehavior::InvalidBlock,
+        core_error::Code::FailedPrecondition => Misbehavior::UnconnectedBlock,
+        _ => return,
+    };
+    global_state.peers.report_misbehavior(node_id, misbehavior);
+}
+
 pub fn connect(
     state: ConnectionState,
     channels: Channels,
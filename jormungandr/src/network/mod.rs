@@ -14,7 +14,7 @@ mod service;
 mod subscription;
 
 // Constants
-mod chain_pull {
+pub mod chain_pull {
     // Size of chunks to split processing of chain pull streams.
     // Apart from sizing data chunks for intercom messages, it also
     // determines how many blocks will be requested per each GetBlocks request
@@ -38,6 +38,7 @@ use crate::utils::{
 };
 use futures::prelude::*;
 use futures::stream;
+use jormungandr_lib::interfaces::BannedPeer;
 use network_core::{
     error as core_error,
     gossip::{Gossip, Node},
@@ -255,6 +256,20 @@ fn handle_network_input(
             state.peers.pull_headers(node_id, from, to);
             Ok(())
         }
+        NetworkMsg::PeerMisbehaved(node_id, misbehavior) => {
+            state.peers.report_misbehavior(node_id, misbehavior);
+            Ok(())
+        }
+        NetworkMsg::GetBannedPeers(handle) => {
+            let banned_peers = state
+                .peers
+                .banned_peers()
+                .into_iter()
+                .map(|(node_id, until)| BannedPeer::new(node_id.to_string(), until.into()))
+                .collect();
+            handle.reply_ok(banned_peers);
+            Ok(())
+        }
     })
 }
 
@@ -325,6 +340,10 @@ fn connect_and_propagate_with<F>(
         }
     };
     let node_id = node.id();
+    if state.peers.is_banned(node_id) {
+        debug!(state.logger(), "not connecting to banned peer {}", node_id);
+        return;
+    }
     let peer = Peer::new(addr, Protocol::Grpc);
     let conn_state = ConnectionState::new(state.clone(), &peer);
     debug!(conn_state.logger(), "connecting to node {}", node_id);
@@ -335,6 +354,12 @@ fn connect_and_propagate_with<F>(
                 let res = once_connected(&mut comms);
                 match res {
                     Ok(()) => (),
+                    Err(p2p::comm::ErrorKind::Unsupported) => {
+                        debug!(
+                            client.logger(),
+                            "peer does not support the propagated item subscription"
+                        );
+                    }
                     Err(e) => {
                         info!(
                             client.logger(),
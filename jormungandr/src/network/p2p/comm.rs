@@ -10,12 +10,45 @@ use network_core::subscription::{BlockEvent, ChainPullRequest};
 use slog::Logger;
 
 use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
 
 // Buffer size determines the number of stream items pending processing that
 // can be buffered before back pressure is applied to the inbound half of
 // a gRPC subscription stream.
 const BUFFER_LEN: usize = 8;
 
+// Reputation score below which a peer gets disconnected and banned.
+// Every peer starts with a score of zero.
+const BAN_THRESHOLD: i32 = -100;
+
+// How long a peer stays banned once its score fell below the threshold.
+//
+// This may need to be made into a configuration parameter.
+const BAN_DURATION: Duration = Duration::from_secs(3600);
+
+/// Misbehaviour of a peer, lowering its reputation score.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Misbehavior {
+    /// The peer sent a block failing the header verification
+    /// or that cannot be applied to the ledger.
+    InvalidBlock,
+    /// The peer sent a block or a chain of headers that does not connect
+    /// to the local chain.
+    UnconnectedBlock,
+    /// The peer sent a fragment failing the stateless checks.
+    InvalidFragment,
+}
+
+impl Misbehavior {
+    fn penalty(self) -> i32 {
+        match self {
+            Misbehavior::InvalidBlock => 50,
+            Misbehavior::UnconnectedBlock => 10,
+            Misbehavior::InvalidFragment => 10,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct PropagateError<T> {
     kind: ErrorKind,
@@ -37,6 +70,8 @@ pub enum ErrorKind {
     NotSubscribed,
     SubscriptionClosed,
     StreamOverflow,
+    /// the peer does not provide the subscription
+    Unsupported,
     Unexpected,
 }
 
@@ -95,6 +130,12 @@ impl<T> CommHandle<T> {
         Subscription { inner: rx }
     }
 
+    /// Marks the subscription as not provided by the peer, the items sent
+    /// to it are rejected with an `Unsupported` error.
+    pub fn set_unsupported(&mut self) {
+        self.state = SubscriptionState::Unsupported;
+    }
+
     // Try sending the item to the subscriber.
     // Sending is done as best effort: if the stream buffer is full due to a
     // blockage downstream, a `StreamOverflow` error is
@@ -105,6 +146,10 @@ impl<T> CommHandle<T> {
                 kind: ErrorKind::NotSubscribed,
                 item,
             }),
+            SubscriptionState::Unsupported => Err(PropagateError {
+                kind: ErrorKind::Unsupported,
+                item,
+            }),
             SubscriptionState::Subscribed(ref mut sender) => sender.try_send(item).map_err(|e| {
                 if e.is_disconnected() {
                     PropagateError {
@@ -130,6 +175,7 @@ impl<T> CommHandle<T> {
 enum SubscriptionState<T> {
     NotSubscribed,
     Subscribed(mpsc::Sender<T>),
+    Unsupported,
 }
 
 /// State of the communication streams that a single peer connection polls
@@ -186,6 +232,12 @@ impl PeerComms {
         self.messages.subscribe()
     }
 
+    /// The peer has no content service: the fragments are not propagated
+    /// to it, while the other subscriptions are kept.
+    pub fn set_messages_unsupported(&mut self) {
+        self.messages.set_unsupported()
+    }
+
     pub fn subscribe_to_gossip(&mut self) -> Subscription<Gossip<topology::Node>> {
         self.gossip.subscribe()
     }
@@ -210,9 +262,38 @@ impl Peers {
 
     pub fn insert_peer(&self, id: topology::NodeId, comms: PeerComms) {
         let mut map = self.mutex.lock().unwrap();
+        if map.is_banned(id) {
+            info!(self.logger, "not adding banned peer {}", id);
+            return;
+        }
         map.insert_peer(id, comms)
     }
 
+    pub fn is_banned(&self, id: topology::NodeId) -> bool {
+        let mut map = self.mutex.lock().unwrap();
+        map.is_banned(id)
+    }
+
+    /// Returns the currently banned peers along with the time
+    /// their ban expires.
+    pub fn banned_peers(&self) -> Vec<(topology::NodeId, SystemTime)> {
+        let mut map = self.mutex.lock().unwrap();
+        map.banned_peers()
+    }
+
+    /// Lower the reputation score of the peer for the given misbehaviour.
+    /// Once the score falls below the threshold, the peer is disconnected
+    /// and banned for a while.
+    pub fn report_misbehavior(&self, id: topology::NodeId, misbehavior: Misbehavior) {
+        let mut map = self.mutex.lock().unwrap();
+        let score = map.lower_score(id, misbehavior.penalty());
+        info!(self.logger, "peer {} misbehaved: {:?}", id, misbehavior; "score" => score);
+        if score < BAN_THRESHOLD {
+            warn!(self.logger, "banning peer {}", id; "duration" => ?BAN_DURATION);
+            map.ban(id, SystemTime::now() + BAN_DURATION);
+        }
+    }
+
     pub fn subscribe_to_block_events(&self, id: topology::NodeId) -> BlockEventSubscription {
         let mut map = self.mutex.lock().unwrap();
         let handles = map.ensure_peer_comms(id);
@@ -262,6 +343,13 @@ impl Peers {
                 if let Some(mut entry) = map.entry(id) {
                     match f(entry.comms()) {
                         Ok(()) => false,
+                        Err(ref e) if e.kind() == ErrorKind::Unsupported => {
+                            debug!(
+                                self.logger,
+                                "not propagating to peer {}: subscription not supported", id
+                            );
+                            false
+                        }
                         Err(e) => {
                             info!(
                                 self.logger,
@@ -406,3 +494,37 @@ impl Peers {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use slog::Discard;
+
+    #[test]
+    fn ban_once_the_score_falls_below_the_threshold() {
+        let peers = Peers::new(Logger::root(Discard, o!()));
+        let id = topology::NodeId::generate();
+
+        // -50, then -100: at the threshold but not below it
+        peers.report_misbehavior(id, Misbehavior::InvalidBlock);
+        peers.report_misbehavior(id, Misbehavior::InvalidBlock);
+        assert!(!peers.is_banned(id));
+
+        peers.report_misbehavior(id, Misbehavior::InvalidFragment);
+        assert!(peers.is_banned(id));
+    }
+
+    #[test]
+    fn misbehaviors_of_other_peers_do_not_add_up() {
+        let peers = Peers::new(Logger::root(Discard, o!()));
+        let id = topology::NodeId::generate();
+        let other = topology::NodeId::generate();
+
+        peers.report_misbehavior(id, Misbehavior::InvalidBlock);
+        peers.report_misbehavior(other, Misbehavior::InvalidBlock);
+        peers.report_misbehavior(other, Misbehavior::InvalidBlock);
+
+        assert!(!peers.is_banned(id));
+        assert!(!peers.is_banned(other));
+    }
+}
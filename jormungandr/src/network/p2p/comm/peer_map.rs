@@ -4,10 +4,16 @@ use crate::network::p2p::topology::NodeId;
 use std::collections::{hash_map, HashMap};
 use std::pin::Pin;
 use std::ptr::NonNull;
+use std::time::SystemTime;
 
 pub struct PeerMap {
     map: HashMap<NodeId, Pin<Box<Node>>>,
     block_cursor: BlockFetchCursor,
+    // Reputation scores of the peers, kept separately from the map nodes
+    // so that they survive reconnections.
+    scores: HashMap<NodeId, i32>,
+    // Banned peers with the time their ban expires.
+    bans: HashMap<NodeId, SystemTime>,
 }
 
 unsafe impl Send for PeerMap {}
@@ -17,6 +23,8 @@ impl PeerMap {
         PeerMap {
             map: HashMap::new(),
             block_cursor: BlockFetchCursor::Empty,
+            scores: HashMap::new(),
+            bans: HashMap::new(),
         }
     }
 
@@ -82,6 +90,40 @@ impl PeerMap {
         }
     }
 
+    /// Lower the reputation score of the peer by the given penalty,
+    /// returning the new score.
+    pub fn lower_score(&mut self, id: NodeId, penalty: i32) -> i32 {
+        let score = self.scores.entry(id).or_insert(0);
+        *score = score.saturating_sub(penalty);
+        *score
+    }
+
+    /// Ban the peer until the given time: the peer is removed from the map,
+    /// dropping its communication handles, and its score is reset so that
+    /// it starts afresh once the ban expires.
+    pub fn ban(&mut self, id: NodeId, until: SystemTime) {
+        if let Some(entry) = self.entry(id) {
+            entry.remove();
+        }
+        self.scores.remove(&id);
+        self.bans.insert(id, until);
+    }
+
+    pub fn is_banned(&mut self, id: NodeId) -> bool {
+        self.expire_bans();
+        self.bans.contains_key(&id)
+    }
+
+    pub fn banned_peers(&mut self) -> Vec<(NodeId, SystemTime)> {
+        self.expire_bans();
+        self.bans.iter().map(|(id, until)| (*id, *until)).collect()
+    }
+
+    fn expire_bans(&mut self) {
+        let now = SystemTime::now();
+        self.bans.retain(|_, until| *until > now);
+    }
+
     pub fn next_peer_for_block_fetch(&mut self) -> Option<(NodeId, &mut PeerComms)> {
         unsafe {
             match self.block_cursor.next() {
@@ -285,3 +327,63 @@ impl<'a> Entry<'a> {
         self.inner.remove();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn scores_accumulate_per_peer() {
+        let mut map = PeerMap::new();
+        let id = NodeId::generate();
+        let other = NodeId::generate();
+
+        assert_eq!(map.lower_score(id, 10), -10);
+        assert_eq!(map.lower_score(id, 50), -60);
+        assert_eq!(map.lower_score(other, 10), -10);
+    }
+
+    #[test]
+    fn score_saturates() {
+        let mut map = PeerMap::new();
+        let id = NodeId::generate();
+
+        map.lower_score(id, i32::max_value());
+        map.lower_score(id, i32::max_value());
+
+        assert_eq!(map.lower_score(id, 0), i32::min_value());
+    }
+
+    #[test]
+    fn ban_resets_the_score() {
+        let mut map = PeerMap::new();
+        let id = NodeId::generate();
+        map.lower_score(id, 100);
+
+        map.ban(id, SystemTime::now() + Duration::from_secs(60));
+
+        assert!(map.is_banned(id));
+        assert_eq!(map.lower_score(id, 10), -10);
+    }
+
+    #[test]
+    fn bans_expire() {
+        let mut map = PeerMap::new();
+        let id = NodeId::generate();
+        let expired = NodeId::generate();
+
+        map.ban(id, SystemTime::now() + Duration::from_secs(60));
+        map.ban(expired, SystemTime::now() - Duration::from_secs(1));
+
+        assert!(map.is_banned(id));
+        assert!(!map.is_banned(expired));
+        assert_eq!(
+            map.banned_peers()
+                .into_iter()
+                .map(|(id, _)| id)
+                .collect::<Vec<_>>(),
+            vec![id]
+        );
+    }
+}
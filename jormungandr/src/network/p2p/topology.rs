@@ -151,6 +151,15 @@ impl P2pTopology {
         topology.view().into_iter().map(Node)
     }
 
+    /// look up the address of the given node in the current view of
+    /// the topology. Returns `None` if the node is not in the view or
+    /// if it did not advertise a reachable address.
+    pub fn node_address(&self, id: NodeId) -> Option<SocketAddr> {
+        self.view()
+            .find(|node| node.id() == id)
+            .and_then(|node| node.address())
+    }
+
     /// this is the function to utilise when we receive a gossip in order
     /// to update the P2P Topology internal state
     pub fn update<I>(&self, new_nodes: I)
@@ -6,7 +6,7 @@ use super::{
     subscription, Channels, GlobalStateR,
 };
 use crate::blockcfg::{Block, BlockDate, Fragment, FragmentId, Header, HeaderHash};
-use crate::intercom::{self, BlockMsg, ClientMsg, ReplyFuture, ReplyStream};
+use crate::intercom::{self, BlockMsg, ClientMsg, ReplyFuture, ReplyStream, TransactionMsg};
 use futures::future::{self, FutureResult};
 use futures::prelude::*;
 use network_core::{
@@ -37,6 +37,23 @@ impl NodeService {
     pub fn logger(&self) -> &Logger {
         &self.logger
     }
+
+    // Refuse the subscriptions of a peer that is banned for having sent
+    // invalid data.
+    fn check_not_banned(&self, subscriber: topology::NodeId) -> Result<(), core_error::Error> {
+        if self.global_state.peers.is_banned(subscriber) {
+            info!(
+                self.logger(),
+                "refusing subscription from banned peer {}", subscriber
+            );
+            Err(core_error::Error::new(
+                core_error::Code::PermissionDenied,
+                "the peer is banned",
+            ))
+        } else {
+            Ok(())
+        }
+    }
 }
 
 impl Node for NodeService {
@@ -49,8 +66,7 @@ impl Node for NodeService {
     }
 
     fn content_service(&mut self) -> Option<&mut Self::ContentService> {
-        // Not implemented yet
-        None
+        Some(self)
     }
 
     fn gossip_service(&mut self) -> Option<&mut Self::GossipService> {
@@ -196,6 +212,10 @@ impl BlockService for NodeService {
     where
         In: Stream<Item = Self::Header, Error = core_error::Error> + Send + 'static,
     {
+        if let Err(e) = self.check_not_banned(subscriber) {
+            return future::err(e);
+        }
+
         subscription::process_block_announcements(
             inbound,
             subscriber,
@@ -216,23 +236,52 @@ impl ContentService for NodeService {
     type Fragment = Fragment;
     type FragmentId = FragmentId;
     type GetFragmentsStream = ReplyStream<Self::Fragment, core_error::Error>;
-    type GetFragmentsFuture = ReplyFuture<Self::GetFragmentsStream, core_error::Error>;
+    type GetFragmentsFuture = FutureResult<Self::GetFragmentsStream, core_error::Error>;
     type ContentSubscription = Subscription<Fragment>;
     type ContentSubscriptionFuture = FutureResult<Self::ContentSubscription, core_error::Error>;
 
-    fn get_fragments(&mut self, _ids: &[Self::FragmentId]) -> Self::GetFragmentsFuture {
-        unimplemented!()
+    fn get_fragments(&mut self, ids: &[Self::FragmentId]) -> Self::GetFragmentsFuture {
+        let (handle, stream) = intercom::stream_reply(self.logger().clone());
+        match self
+            .channels
+            .transaction_box
+            .try_send(TransactionMsg::GetTransactions(ids.into(), handle))
+        {
+            Ok(()) => future::ok(stream),
+            Err(err) => {
+                warn!(self.logger(), "cannot query the fragment task: {}", err);
+                future::err(core_error::Error::new(
+                    core_error::Code::Unavailable,
+                    "the node cannot process the fragment request",
+                ))
+            }
+        }
     }
 
     fn content_subscription<S>(
         &mut self,
         subscriber: Self::NodeId,
-        _inbound: S,
+        inbound: S,
     ) -> Self::ContentSubscriptionFuture
     where
-        S: Stream<Item = Self::Fragment, Error = core_error::Error>,
+        S: Stream<Item = Self::Fragment, Error = core_error::Error> + Send + 'static,
     {
-        unimplemented!()
+        if let Err(e) = self.check_not_banned(subscriber) {
+            return future::err(e);
+        }
+
+        let address = self.global_state.topology.node_address(subscriber);
+        subscription::process_fragments(
+            inbound,
+            subscriber,
+            address,
+            self.global_state.clone(),
+            self.channels.transaction_box.clone(),
+            self.logger().clone(),
+        );
+
+        let subscription = self.global_state.peers.subscribe_to_messages(subscriber);
+        future::ok(subscription)
     }
 }
 
@@ -249,6 +298,10 @@ impl GossipService for NodeService {
     where
         In: Stream<Item = Gossip<Self::Node>, Error = core_error::Error> + Send + 'static,
     {
+        if let Err(e) = self.check_not_banned(subscriber) {
+            return future::err(e);
+        }
+
         subscription::process_gossip(inbound, self.global_state.clone(), self.logger().clone());
 
         let subscription = self.global_state.peers.subscribe_to_gossip(subscriber);
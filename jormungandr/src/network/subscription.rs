@@ -1,11 +1,21 @@
 use super::{
-    p2p::topology::{Node, NodeId},
+    p2p::{
+        comm::Misbehavior,
+        topology::{Node, NodeId},
+    },
     GlobalState, GlobalStateR,
 };
-use crate::{blockcfg::Header, intercom::BlockMsg, utils::async_msg::MessageBox};
+use crate::{
+    blockcfg::{Fragment, Header},
+    fragment::validation,
+    intercom::{BlockMsg, TransactionMsg},
+    utils::async_msg::MessageBox,
+};
 use futures::prelude::*;
+use jormungandr_lib::interfaces::FragmentOrigin;
 use network_core::{error as core_error, gossip::Gossip};
 use slog::Logger;
+use std::net::SocketAddr;
 
 pub fn process_block_announcements<S>(
     inbound: S,
@@ -17,8 +27,11 @@ pub fn process_block_announcements<S>(
 where
     S: Stream<Item = Header, Error = core_error::Error> + Send + 'static,
 {
+    let ban_logger = logger.clone();
+    let ban_state = global_state.clone();
     tokio::spawn(
         inbound
+            .take_while(move |_| Ok(!peer_banned(&ban_state, node_id, &ban_logger)))
             .for_each(move |header| {
                 process_block_announcement(header, node_id, &global_state, &mut block_box);
                 Ok(())
@@ -29,6 +42,20 @@ where
     )
 }
 
+// Check whether the peer has been banned since its subscription stream was
+// opened, in which case the items it still sends are dropped and the stream
+// is closed.
+fn peer_banned(global_state: &GlobalState, node_id: NodeId, logger: &Logger) -> bool {
+    let banned = global_state.peers.is_banned(node_id);
+    if banned {
+        info!(
+            logger,
+            "closing the subscription of banned peer {}", node_id
+        );
+    }
+    banned
+}
+
 pub fn process_block_announcement(
     header: Header,
     node_id: NodeId,
@@ -58,3 +85,60 @@ where
             }),
     )
 }
+
+/// forward the fragments received from the given peer to the fragment task,
+/// which discards the ones it already knows about.
+///
+/// The fragments are logged as received from the peer `node_id`, reachable
+/// at `address` when it is known. Malformed fragments lower the reputation
+/// of the peer, and the stream is closed once the peer is banned.
+pub fn process_fragments<S>(
+    inbound: S,
+    node_id: NodeId,
+    address: Option<SocketAddr>,
+    global_state: GlobalStateR,
+    transaction_box: MessageBox<TransactionMsg>,
+    logger: Logger,
+) -> tokio::executor::Spawn
+where
+    S: Stream<Item = Fragment, Error = core_error::Error> + Send + 'static,
+{
+    let err_logger = logger.clone();
+    let sink_err_logger = logger.clone();
+    let ban_logger = logger.clone();
+    let ban_state = global_state.clone();
+    tokio::spawn(
+        inbound
+            .map_err(move |err| {
+                info!(
+                    err_logger,
+                    "fragment subscription stream failure: {:?}", err
+                );
+            })
+            .take_while(move |_| Ok(!peer_banned(&ban_state, node_id, &ban_logger)))
+            .map(move |fragment| {
+                trace!(logger, "received fragment from {}", node_id);
+                if let Err(err) = validation::check(&fragment) {
+                    debug!(
+                        logger,
+                        "received malformed fragment from {}: {}", node_id, err
+                    );
+                    global_state
+                        .peers
+                        .report_misbehavior(node_id, Misbehavior::InvalidFragment);
+                }
+                let origin = FragmentOrigin::Network {
+                    node_id: node_id.to_string(),
+                    address,
+                };
+                TransactionMsg::SendTransaction(origin, vec![fragment])
+            })
+            .forward(transaction_box.sink_map_err(move |_| {
+                error!(
+                    sink_err_logger,
+                    "the fragment task stopped processing fragments"
+                );
+            }))
+            .map(|_| ()),
+    )
+}
@@ -10,17 +10,18 @@ use actix_web::dev::Resource;
 use actix_web::middleware::cors::Cors;
 use actix_web::App;
 use futures::{future, Future};
+use slog::Logger;
 use std::convert::Infallible;
 use tokio::sync::lock::Lock;
 
 use crate::blockchain::{Blockchain, Branch};
 use crate::fragment::Logs;
-use crate::leadership::Logs as LeadershipLogs;
+use crate::leadership::{EpochSchedule, Logs as LeadershipLogs};
 use crate::secure::enclave::Enclave;
 use crate::settings::start::{Cors as CorsConfig, Error as ConfigError, Rest};
 use crate::stats_counter::StatsCounter;
 
-use crate::intercom::TransactionMsg;
+use crate::intercom::{LeadershipMsg, NetworkMsg, TransactionMsg};
 use crate::utils::async_msg::MessageBox;
 
 #[derive(Clone)]
@@ -29,10 +30,14 @@ pub struct Context {
     pub blockchain: Blockchain,
     pub blockchain_tip: Branch,
     pub transaction_task: MessageBox<TransactionMsg>,
+    pub network_task: MessageBox<NetworkMsg>,
+    pub leadership_task: MessageBox<LeadershipMsg>,
     pub logs: Logs,
     pub leadership_logs: LeadershipLogs,
+    pub leadership_schedule: EpochSchedule,
     pub server: Lock<Option<Server>>,
     pub enclave: Enclave,
+    pub logger: Logger,
 }
 
 pub fn start_rest_server(config: &Rest, mut context: Context) -> Result<Server, ConfigError> {
@@ -4,19 +4,20 @@ use jormungandr_lib::time::SystemTime;
 use actix_web::error::{ErrorBadRequest, ErrorInternalServerError, ErrorNotFound};
 use actix_web::{Error, HttpResponse};
 use actix_web::{Json, Path, Query, Responder, State};
-use chain_core::property::{Block, Deserialize, Serialize as _};
+use chain_core::property::{Block, Deserialize, Fragment as _, Serialize as _};
 use chain_crypto::{Blake2b256, PublicKey};
 use chain_impl_mockchain::account::{AccountAlg, Identifier};
-use chain_impl_mockchain::fragment::Fragment;
+use chain_impl_mockchain::fragment::{Fragment, FragmentId};
 use chain_impl_mockchain::key::Hash;
 use chain_impl_mockchain::leadership::{Leader, LeadershipConsensus};
 use chain_impl_mockchain::value::{Value, ValueError};
 
 use crate::blockchain::Ref;
-use crate::intercom::TransactionMsg;
+use crate::intercom::{self, LeadershipMsg, NetworkMsg, TransactionMsg};
 use crate::secure::NodeSecret;
 use bytes::{Bytes, IntoBuf};
 use futures::{future, Future, IntoFuture, Stream};
+use std::collections::HashMap;
 use std::convert::Infallible;
 use std::str::FromStr;
 
@@ -72,15 +73,83 @@ pub fn get_message_logs(context: State<Context>) -> ActixFuture!() {
         .map(Json)
 }
 
-pub fn post_message(context: State<Context>, message: Bytes) -> Result<HttpResponse, Error> {
-    let fragment = Fragment::deserialize(message.into_buf()).map_err(ErrorBadRequest)?;
-    let msg = TransactionMsg::SendTransaction(FragmentOrigin::Rest, vec![fragment]);
-    context
-        .transaction_task
-        .clone()
-        .try_send(msg)
-        .map_err(|e| ErrorInternalServerError(e))?;
-    Ok(HttpResponse::Ok().finish())
+pub fn get_fragment_status(
+    context: State<Context>,
+    fragment_id_hex: Path<String>,
+) -> ActixFuture!() {
+    parse_fragment_id(&fragment_id_hex)
+        .into_future()
+        .and_then(move |fragment_id| {
+            context
+                .logs
+                .statuses(vec![fragment_id])
+                .map_err(|_| ErrorInternalServerError("Failed to get logs"))
+        })
+        .and_then(|statuses| {
+            statuses
+                .into_iter()
+                .next()
+                .map(|(_, status)| Json(status))
+                .ok_or_else(|| ErrorNotFound("Fragment not found"))
+        })
+}
+
+pub fn post_fragment_statuses(
+    context: State<Context>,
+    fragment_ids_hex: Json<Vec<String>>,
+) -> ActixFuture!() {
+    fragment_ids_hex
+        .iter()
+        .map(|id_hex| parse_fragment_id(id_hex))
+        .collect::<Result<Vec<_>, _>>()
+        .into_future()
+        .and_then(move |fragment_ids| {
+            context
+                .logs
+                .statuses(fragment_ids)
+                .map_err(|_| ErrorInternalServerError("Failed to get logs"))
+        })
+        .map(|statuses| {
+            let statuses = statuses
+                .into_iter()
+                .map(|(id, status)| (id.to_string(), status))
+                .collect::<HashMap<_, _>>();
+            Json(statuses)
+        })
+}
+
+fn parse_fragment_id(hex: &str) -> Result<FragmentId, Error> {
+    Blake2b256::from_str(hex)
+        .map_err(|e| ErrorBadRequest(e))
+        .map(Into::into)
+}
+
+pub fn post_message(context: State<Context>, message: Bytes) -> ActixFuture!() {
+    Fragment::deserialize(message.into_buf())
+        .map_err(ErrorBadRequest)
+        .into_future()
+        .and_then(move |fragment| {
+            let fragment_id = fragment.id();
+            let (reply_handle, reply_future) = intercom::unary_reply(context.logger.clone());
+            let msg =
+                TransactionMsg::SubmitTransaction(FragmentOrigin::Rest, fragment, reply_handle);
+            context
+                .transaction_task
+                .clone()
+                .try_send(msg)
+                .map_err(|e| ErrorInternalServerError(e))
+                .into_future()
+                .and_then(move |()| {
+                    reply_future.map_err(|e: intercom::Error| ErrorInternalServerError(e))
+                })
+                .map(move |status| {
+                    let mut response = match status {
+                        FragmentAdmissionStatus::Rejected { .. } => HttpResponse::BadRequest(),
+                        _ => HttpResponse::Ok(),
+                    };
+                    response.json(FragmentAdmission::new(fragment_id, status))
+                })
+        })
 }
 
 pub fn get_tip(context: State<Context>) -> ActixFuture!() {
@@ -128,11 +197,20 @@ pub fn get_stats_counter(context: State<Context>) -> ActixFuture!() {
             Ok(Json(json!({
                 "txRecvCnt": stats.tx_recv_cnt(),
                 "blockRecvCnt": stats.block_recv_cnt(),
+                "leadershipFailureCnt": stats.leadership_failure_cnt(),
                 "uptime": stats.uptime_sec(),
                 "lastBlockTime": stats.slot_start_time().map(SystemTime::from),
                 "lastBlockTx": block_tx_count,
                 "lastBlockSum": block_input_sum.0,
                 "lastBlockFees": block_fee_sum.0,
+                "blockCacheSize": stats.ref_cache().size(),
+                "blockCacheHits": stats.ref_cache().hits(),
+                "blockCacheMisses": stats.ref_cache().misses(),
+                "blockCacheEvictions": stats.ref_cache().evictions(),
+                "ledgerCacheSize": stats.multiverse().size(),
+                "ledgerCacheHits": stats.multiverse().hits(),
+                "ledgerCacheMisses": stats.multiverse().misses(),
+                "ledgerCacheEvictions": stats.multiverse().evictions(),
             })))
         })
 }
@@ -257,6 +335,18 @@ pub fn get_shutdown(context: State<Context>) -> ActixFuture!() {
     })
 }
 
+pub fn get_banned_peers(context: State<Context>) -> ActixFuture!() {
+    let (reply_handle, reply_future) = intercom::unary_reply(context.logger.clone());
+    context
+        .network_task
+        .clone()
+        .try_send(NetworkMsg::GetBannedPeers(reply_handle))
+        .map_err(|e| ErrorInternalServerError(e))
+        .into_future()
+        .and_then(move |()| reply_future.map_err(|e: intercom::Error| ErrorInternalServerError(e)))
+        .map(Json)
+}
+
 pub fn get_leaders(context: State<Context>) -> impl Responder {
     Json(json! {
         context.enclave.get_leaderids()
@@ -269,6 +359,7 @@ pub fn post_leaders(secret: Json<NodeSecret>, context: State<Context>) -> impl R
         genesis_leader: secret.genesis(),
     };
     let leader_id = context.enclave.add_leader(leader);
+    notify_leadership_task(&context, LeadershipMsg::LeaderAdded(leader_id));
     Json(leader_id)
 }
 
@@ -277,11 +368,23 @@ pub fn delete_leaders(
     leader_id: Path<EnclaveLeaderId>,
 ) -> Result<impl Responder, Error> {
     match context.enclave.remove_leader(*leader_id) {
-        true => Ok(HttpResponse::Ok().finish()),
+        true => {
+            notify_leadership_task(&context, LeadershipMsg::LeaderRemoved(*leader_id));
+            Ok(HttpResponse::Ok().finish())
+        }
         false => Err(ErrorNotFound("Leader with given ID not found")),
     }
 }
 
+/// the enclave is already updated, if the leadership task cannot be notified
+/// the change only applies from the next epoch
+fn notify_leadership_task(context: &State<Context>, msg: LeadershipMsg) {
+    if let Err(error) = context.leadership_task.clone().try_send(msg) {
+        warn!(context.logger, "cannot notify the leadership task of the leaders change";
+              "reason" => error.to_string());
+    }
+}
+
 pub fn get_leaders_logs(context: State<Context>) -> ActixFuture!() {
     context
         .leadership_logs
@@ -290,6 +393,17 @@ pub fn get_leaders_logs(context: State<Context>) -> ActixFuture!() {
         .map_err(|_| ErrorInternalServerError("Failed to get leader logs"))
 }
 
+/// the slots every leader of the enclave is elected for in the current
+/// epoch, as evaluated by the leadership task
+pub fn get_leaders_schedule(context: State<Context>) -> ActixFuture!() {
+    let leader_ids = context.enclave.get_leaderids();
+    context
+        .leadership_schedule
+        .schedules(leader_ids)
+        .map(Json)
+        .map_err(|_| ErrorInternalServerError("Failed to get leaders schedule"))
+}
+
 pub fn get_stake_pools(context: State<Context>) -> ActixFuture!() {
     chain_tip_fut(&context).map(|blockchain_tip| {
         let stake_pool_ids = blockchain_tip
@@ -19,6 +19,12 @@ pub fn resources() -> Vec<(
         ("/fragment/logs", &|r| {
             r.get().with_async(handlers::get_message_logs)
         }),
+        ("/fragment/statuses", &|r| {
+            r.post().with_async(handlers::post_fragment_statuses)
+        }),
+        ("/fragment/{fragment_id}", &|r| {
+            r.get().with_async(handlers::get_fragment_status)
+        }),
         ("/leaders", &|r| {
             r.get().with(handlers::get_leaders);
             r.post().with(handlers::post_leaders);
@@ -26,6 +32,9 @@ pub fn resources() -> Vec<(
         ("/leaders/logs", &|r| {
             r.get().with_async(handlers::get_leaders_logs);
         }),
+        ("/leaders/schedule", &|r| {
+            r.get().with_async(handlers::get_leaders_schedule);
+        }),
         ("/leaders/{leader_id}", &|r| {
             r.delete().with(handlers::delete_leaders)
         }),
@@ -37,7 +46,10 @@ pub fn resources() -> Vec<(
             r.get().with_async(handlers::get_stake_pools)
         }),
         ("/shutdown", &|r| r.get().with_async(handlers::get_shutdown)),
-        ("/message", &|r| r.post().with(handlers::post_message)),
+        ("/message", &|r| r.post().with_async(handlers::post_message)),
+        ("/network/banned_peers", &|r| {
+            r.get().with_async(handlers::get_banned_peers)
+        }),
         ("/node/stats", &|r| {
             r.get().with_async(handlers::get_stats_counter)
         }),
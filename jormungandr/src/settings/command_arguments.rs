@@ -61,6 +61,40 @@ pub struct StartArguments {
     /// or from the network.
     #[structopt(long = "genesis-block-hash", parse(try_from_str))]
     pub block_0_hash: Option<HeaderHash>,
+
+    /// Export the blocks of the storage, from the genesis block to the
+    /// tip, in the given chain file then exit without starting the node.
+    #[structopt(long = "export-chain", parse(from_os_str))]
+    pub export_chain: Option<PathBuf>,
+
+    /// Import the blocks of the given chain file, as created with
+    /// `--export-chain`, in the storage then exit without starting the node.
+    /// The storage must not already contain the blockchain.
+    #[structopt(
+        long = "import-chain",
+        parse(from_os_str),
+        conflicts_with = "export_chain"
+    )]
+    pub import_chain: Option<PathBuf>,
+
+    /// Validate again the blocks of the storage, from the genesis block to
+    /// the tip, and report the first invalid block then exit without
+    /// starting the node.
+    #[structopt(
+        long = "verify-storage",
+        raw(conflicts_with_all = r#"&["export_chain", "import_chain"]"#)
+    )]
+    pub verify_storage: bool,
+
+    /// Same as `--verify-storage`, but also set the tip of the storage to
+    /// the last valid block. Only the tags of the storage are moved: the
+    /// blocks after it are kept in the storage but are no longer part of
+    /// the blockchain.
+    #[structopt(
+        long = "repair-storage",
+        raw(conflicts_with_all = r#"&["export_chain", "import_chain", "verify_storage"]"#)
+    )]
+    pub repair_storage: bool,
 }
 
 #[derive(StructOpt, Debug)]
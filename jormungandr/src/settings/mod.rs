@@ -22,3 +22,37 @@ pub enum Block0Info {
     Path(PathBuf),
     Hash(HeaderHash),
 }
+
+/// the backend storing the blocks and its directory
+#[derive(Clone, Debug)]
+pub enum Storage {
+    Memory,
+    SQLite {
+        path: PathBuf,
+        /// remove the abandoned forks deeper than this number of blocks
+        /// at every new epoch of the tip
+        prune_depth: Option<u64>,
+    },
+    FlatFile {
+        path: PathBuf,
+        /// remove the abandoned forks deeper than this number of blocks
+        /// when the storage is opened and at every new epoch of the tip
+        prune_depth: Option<u64>,
+    },
+}
+
+/// offline operation on a chain file, to run instead of starting the node
+#[derive(Clone, Debug)]
+pub enum ChainFileCommand {
+    Export(PathBuf),
+    Import(PathBuf),
+}
+
+/// offline verification of the storage, to run instead of starting the node
+#[derive(Clone, Copy, Debug)]
+pub enum StorageCheck {
+    /// only report the first invalid block
+    Verify,
+    /// also set the tip to the last valid block
+    Repair,
+}
@@ -1,4 +1,5 @@
 use crate::{
+    fragment::selection,
     network::p2p::topology::{NodeId, NEW_BLOCKS_TOPIC, NEW_MESSAGES_TOPIC},
     settings::logging::{LogFormat, LogOutput},
     settings::LOG_FILTER_LEVEL_POSSIBLE_VALUES,
@@ -14,7 +15,7 @@ pub struct Config {
     pub secret_files: Option<Vec<PathBuf>>,
     pub legacy_peers: Option<Vec<SocketAddr>>,
     pub grpc_peers: Option<Vec<SocketAddr>>,
-    pub storage: Option<PathBuf>,
+    pub storage: Option<StorageConfig>,
     pub log: Option<ConfigLogSettings>,
 
     /// setting of the mempool, fragment logs and related data
@@ -24,10 +25,46 @@ pub struct Config {
     #[serde(default)]
     pub leadership: Leadership,
 
+    /// limits of the in-memory caches of the blockchain
+    #[serde(default)]
+    pub block_cache: BlockCache,
+
     pub rest: Option<Rest>,
     pub p2p: P2pConfig,
 }
 
+/// the storage of the blocks: either the path of the storage directory
+/// alone, with the default backend, or the detailed settings
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum StorageConfig {
+    Path(PathBuf),
+    Settings {
+        /// the directory of the storage, required for all the backends
+        /// but `memory`
+        path: Option<PathBuf>,
+        /// defaults to `sqlite` if a path is given, `memory` otherwise
+        backend: Option<StorageBackend>,
+        /// remove the blocks of the forks which branched off more than
+        /// this number of blocks below the tip, at every new epoch of the
+        /// tip and, with the `flat_file` backend, when the node starts.
+        /// Not supported by the `memory` backend.
+        prune_depth: Option<u64>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageBackend {
+    /// the blocks are stored in a SQLite database, `blocks.sqlite`
+    Sqlite,
+    /// the blocks are only kept in memory and lost when the node stops
+    Memory,
+    /// the blocks are appended to a single file, `blocks.dat`. Faster to
+    /// write than SQLite but the last blocks may be lost on a crash.
+    FlatFile,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct ConfigLogSettings {
     #[serde(with = "filter_level_opt_serde")]
@@ -79,15 +116,33 @@ pub struct P2pConfig {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
 pub struct Leadership {
     /// LeadershipLog time to live, it is for information purposes, we log all the Leadership
     /// event logs in a cache. The log will be discarded at the end of the ttl.
     pub log_ttl: Duration,
     /// interval between 2 garbage collection check logs
     pub garbage_collection_interval: Duration,
+    /// the algorithm used to select the fragments of the mempool to put in a new block
+    pub fragment_selection: FragmentSelection,
+    /// maximum number of fragments to put in a new block
+    pub max_fragments_per_block: usize,
+    /// maximum size, in bytes, of the fragments put in a new block. Only honoured
+    /// by the `highest_fee` fragment selection.
+    pub max_block_size: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FragmentSelection {
+    /// select the fragments in the order they have been received
+    OldestFirst,
+    /// select the fragments paying the highest fee per byte first
+    HighestFee,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
 pub struct Mempool {
     /// time to live in the mempool before being discarded. If the value is not applied
     /// in a block within this duration it will be discarded.
@@ -97,6 +152,21 @@ pub struct Mempool {
     pub log_ttl: Duration,
     /// interval between 2 garbage collection check of the mempool and the log cache.
     pub garbage_collection_interval: Duration,
+    /// maximum number of fragments pending in the mempool
+    pub max_entries: usize,
+    /// maximum accumulated size, in bytes, of the fragments pending in the mempool
+    pub max_bytes: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BlockCache {
+    /// maximum number of blocks of which the state is kept in memory, the
+    /// least recently used are evicted first. No limit if not set.
+    pub max_blocks: Option<usize>,
+    /// maximum number of ledger states kept in memory before collecting
+    /// the ones of the evicted blocks. No limit if not set.
+    pub max_ledgers: Option<usize>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -120,6 +190,8 @@ impl Default for Mempool {
             fragment_ttl: Duration::new(30 * 60, 0),
             log_ttl: Duration::new(3600, 0),
             garbage_collection_interval: Duration::new(3600 / 4, 0),
+            max_entries: 10_000,
+            max_bytes: 16 * 1024 * 1024,
         }
     }
 }
@@ -129,6 +201,23 @@ impl Default for Leadership {
         Leadership {
             log_ttl: Duration::new(3600, 0),
             garbage_collection_interval: Duration::new(3600 / 4, 0),
+            fragment_selection: FragmentSelection::OldestFirst,
+            max_fragments_per_block: 250,
+            max_block_size: 64 * 1024,
+        }
+    }
+}
+
+impl Leadership {
+    pub fn fragment_selection(&self) -> selection::Algorithm {
+        match self.fragment_selection {
+            FragmentSelection::OldestFirst => selection::Algorithm::OldestFirst {
+                max_per_block: self.max_fragments_per_block,
+            },
+            FragmentSelection::HighestFee => selection::Algorithm::HighestFeeFirst {
+                max_per_block: self.max_fragments_per_block,
+                max_block_size: self.max_block_size,
+            },
         }
     }
 }
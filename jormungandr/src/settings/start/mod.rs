@@ -1,12 +1,12 @@
 mod config;
 pub mod network;
 
-use self::config::{Config, Leadership, Mempool};
+use self::config::{BlockCache, Config, Leadership, Mempool, StorageBackend, StorageConfig};
 pub use self::config::{Cors, Rest};
 use self::network::Protocol;
 use crate::rest::Error as RestError;
 use crate::settings::logging::{self, LogFormat, LogOutput, LogSettings};
-use crate::settings::{command_arguments::*, Block0Info};
+use crate::settings::{command_arguments::*, Block0Info, ChainFileCommand, Storage, StorageCheck};
 use slog::{FilterLevel, Logger};
 
 use std::{collections::BTreeMap, fs::File, path::PathBuf};
@@ -17,17 +17,21 @@ custom_error! {pub Error
    Rest { source: RestError } = "The Rest configuration is invalid: {source}",
    ExpectedBlock0Info = "Cannot start the node without the information to retrieve the genesis block",
    TooMuchBlock0Info = "Use only `--genesis-block-hash' or `--genesis-block'",
+   ExpectedStoragePath = "The storage path is required by all the storage backends but `memory'",
 }
 
 /// Overall Settings for node
 pub struct Settings {
     pub network: network::Configuration,
-    pub storage: Option<PathBuf>,
+    pub storage: Storage,
     pub block_0: Block0Info,
     pub secrets: Vec<PathBuf>,
     pub rest: Option<Rest>,
     pub mempool: Mempool,
     pub leadership: Leadership,
+    pub block_cache: BlockCache,
+    pub chain_file: Option<ChainFileCommand>,
+    pub storage_check: Option<StorageCheck>,
 }
 
 pub struct RawSettings {
@@ -88,11 +92,39 @@ impl RawSettings {
         let command_arguments = &command_line.start_arguments;
         let network = generate_network(&command_arguments, &config);
 
-        let storage = match (command_arguments.storage.as_ref(), config.storage) {
-            (Some(path), _) => Some(path.clone()),
-            (None, Some(path)) => Some(path.clone()),
-            (None, None) => None,
+        let (config_path, backend, prune_depth) = match config.storage {
+            None => (None, None, None),
+            Some(StorageConfig::Path(path)) => (Some(path), None, None),
+            Some(StorageConfig::Settings {
+                path,
+                backend,
+                prune_depth,
+            }) => (path, backend, prune_depth),
+        };
+        let path = command_arguments.storage.clone().or(config_path);
+        let storage = match (backend, path) {
+            (None, None) => Storage::Memory,
+            (None, Some(path)) | (Some(StorageBackend::Sqlite), Some(path)) => {
+                Storage::SQLite { path, prune_depth }
+            }
+            (Some(StorageBackend::FlatFile), Some(path)) => Storage::FlatFile { path, prune_depth },
+            (Some(StorageBackend::Memory), path) => {
+                if let Some(path) = path {
+                    warn!(
+                        logger,
+                        "storage path {:?} ignored by the memory backend", path
+                    );
+                }
+                Storage::Memory
+            }
+            (Some(_backend), None) => return Err(Error::ExpectedStoragePath),
         };
+        if let (Storage::Memory, Some(_)) = (&storage, prune_depth) {
+            warn!(
+                logger,
+                "storage prune_depth ignored, the memory backend is not pruned"
+            );
+        }
 
         let mut secrets = command_arguments.secret.clone();
         if let Some(secret_files) = config.secret_files {
@@ -116,6 +148,23 @@ impl RawSettings {
             (None, Some(hash)) => Block0Info::Hash(hash.clone()),
         };
 
+        let chain_file = match (
+            &command_arguments.export_chain,
+            &command_arguments.import_chain,
+        ) {
+            (Some(path), _) => Some(ChainFileCommand::Export(path.clone())),
+            (None, Some(path)) => Some(ChainFileCommand::Import(path.clone())),
+            (None, None) => None,
+        };
+
+        let storage_check = if command_arguments.repair_storage {
+            Some(StorageCheck::Repair)
+        } else if command_arguments.verify_storage {
+            Some(StorageCheck::Verify)
+        } else {
+            None
+        };
+
         Ok(Settings {
             storage: storage,
             block_0: block0_info,
@@ -124,6 +173,9 @@ impl RawSettings {
             rest: config.rest,
             mempool: config.mempool,
             leadership: config.leadership,
+            block_cache: config.block_cache,
+            chain_file,
+            storage_check,
         })
     }
 }
@@ -0,0 +1,217 @@
+//! offline export and import of the blockchain
+//!
+//! A chain file is the sequence of the blocks of the main branch, from
+//! the block0 to the tip. Every block is serialised and prefixed with
+//! its length in bytes as a big endian `u32`.
+
+use super::{Error, ErrorKind, NodeStorage};
+use crate::{
+    blockcfg::{Block, BLOCK_MAX_SIZE},
+    blockchain::{Blockchain, CacheLimits, ErrorKind as BlockchainError},
+};
+use chain_core::property::{Deserialize as _, Serialize as _};
+use slog::Logger;
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    path::Path,
+    time::Duration,
+};
+use tokio::prelude::*;
+
+/// write the blocks of the storage, from the block0 to the `HEAD`,
+/// in the chain file at the given path
+pub fn export_chain(
+    storage: NodeStorage,
+    block0: Block,
+    path: &Path,
+    block_cache_ttl: Duration,
+    logger: &Logger,
+) -> Result<(), Error> {
+    let blockchain = Blockchain::new(storage, block_cache_ttl, CacheLimits::default());
+
+    let file = File::create(path).map_err(chain_file_io_error)?;
+    let mut writer = BufWriter::new(file);
+
+    write_block(&mut writer, &block0).map_err(chain_file_io_error)?;
+    let count = blockchain
+        .stream_from_block0_to_head(block0.header.hash())
+        .map_err(Error::from)
+        .and_then(|block_stream| {
+            block_stream
+                .map_err(Error::from)
+                .fold(1u64, |count, block| {
+                    write_block(&mut writer, &block)
+                        .map(|()| count + 1)
+                        .map_err(chain_file_io_error)
+                })
+        })
+        .wait()?;
+    writer.flush().map_err(chain_file_io_error)?;
+
+    info!(logger, "blockchain exported to {:?}", path; "blocks" => count);
+    Ok(())
+}
+
+/// validate and add the blocks of the chain file at the given path in the
+/// storage, which must not already contain the blockchain
+pub fn import_chain(
+    storage: NodeStorage,
+    block0: Block,
+    path: &Path,
+    block_cache_ttl: Duration,
+    logger: &Logger,
+) -> Result<(), Error> {
+    let mut blockchain = Blockchain::new(storage, block_cache_ttl, CacheLimits::default());
+
+    let file = File::open(path).map_err(chain_file_io_error)?;
+    let mut reader = BufReader::new(file);
+
+    match read_block(&mut reader)? {
+        Some(ref file_block0) if file_block0.header.hash() == block0.header.hash() => {}
+        _ => return Err(Error::ChainFileBlock0Mismatch),
+    }
+
+    if let Err(error) = blockchain.load_from_block0(block0).wait() {
+        return match error.kind() {
+            BlockchainError::Block0AlreadyInStorage => Err(Error::ChainImportIntoExistingStorage),
+            _ => Err(error.into()),
+        };
+    }
+
+    let mut count = 1u64;
+    let mut tip = None;
+    while let Some(block) = read_block(&mut reader)? {
+        let block_id = block.header.hash();
+        let new_ref = blockchain.import_block(block).wait().map_err(|error| {
+            error!(logger, "invalid block in the chain file";
+                   "hash" => block_id.to_string(), "index" => count);
+            error
+        })?;
+        tip = Some(new_ref);
+        count += 1;
+    }
+
+    if let Some(tip) = tip {
+        blockchain.put_tip(&tip).wait()?;
+    }
+
+    info!(logger, "blockchain imported from {:?}", path; "blocks" => count);
+    Ok(())
+}
+
+fn write_block<W: Write>(writer: &mut W, block: &Block) -> io::Result<()> {
+    let bytes = block.serialize_as_vec()?;
+    writer.write_all(&(bytes.len() as u32).to_be_bytes())?;
+    writer.write_all(&bytes)
+}
+
+/// read the next block of the chain file, `None` at the end of the file
+fn read_block<R: BufRead>(reader: &mut R) -> Result<Option<Block>, Error> {
+    if reader.fill_buf().map_err(chain_file_io_error)?.is_empty() {
+        return Ok(None);
+    }
+
+    let mut len = [0u8; 4];
+    reader
+        .read_exact(&mut len)
+        .map_err(chain_file_parse_error)?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > BLOCK_MAX_SIZE {
+        return Err(chain_file_parse_error(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "block of {} bytes, larger than the maximum of {} bytes",
+                len, BLOCK_MAX_SIZE
+            ),
+        )));
+    }
+    let mut bytes = vec![0u8; len];
+    reader
+        .read_exact(&mut bytes)
+        .map_err(chain_file_parse_error)?;
+
+    Block::deserialize(&bytes[..])
+        .map(Some)
+        .map_err(chain_file_parse_error)
+}
+
+fn chain_file_io_error(source: io::Error) -> Error {
+    Error::IO {
+        source,
+        reason: ErrorKind::ChainFile,
+    }
+}
+
+fn chain_file_parse_error(source: io::Error) -> Error {
+    Error::ParseError {
+        source,
+        reason: ErrorKind::ChainFile,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockcfg::{BlockBuilder, BlockDate};
+    use chain_core::property::ChainLength as _;
+
+    fn blocks(len: u32) -> Vec<Block> {
+        let mut blocks: Vec<Block> = vec![BlockBuilder::new().make_genesis_block()];
+        for slot_id in 1..len {
+            let mut builder = BlockBuilder::new();
+            {
+                let parent = blocks.last().unwrap();
+                builder
+                    .date(BlockDate { epoch: 0, slot_id })
+                    .parent(parent.header.hash())
+                    .chain_length(parent.header.chain_length().next());
+            }
+            blocks.push(builder.make_genesis_block());
+        }
+        blocks
+    }
+
+    #[test]
+    fn exported_blocks_are_imported_in_order() {
+        let blocks = blocks(4);
+        let mut file = Vec::new();
+        for block in blocks.iter() {
+            write_block(&mut file, block).unwrap();
+        }
+
+        let mut reader = &file[..];
+        let mut imported = Vec::new();
+        while let Some(block) = read_block(&mut reader).unwrap() {
+            imported.push(block.header.hash());
+        }
+
+        let exported: Vec<_> = blocks.iter().map(|block| block.header.hash()).collect();
+        assert_eq!(imported, exported);
+    }
+
+    #[test]
+    fn truncated_block_is_a_parse_error() {
+        let blocks = blocks(1);
+        let mut file = Vec::new();
+        write_block(&mut file, &blocks[0]).unwrap();
+        file.pop();
+
+        match read_block(&mut &file[..]) {
+            Err(Error::ParseError { .. }) => (),
+            _ => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn oversized_block_length_is_rejected_before_reading_it() {
+        let file = ((BLOCK_MAX_SIZE + 1) as u32).to_be_bytes();
+
+        match read_block(&mut &file[..]) {
+            Err(Error::ParseError { source, .. }) => {
+                assert_eq!(source.kind(), io::ErrorKind::InvalidData)
+            }
+            _ => panic!("expected a parse error"),
+        }
+    }
+}
@@ -7,7 +7,9 @@ use std::io;
 
 custom_error! {pub ErrorKind
    SQLite = "SQLite file",
-   Block0 = "Block0"
+   Block0 = "Block0",
+   ChainFile = "chain file",
+   FlatFile = "flat file storage"
 }
 
 custom_error! {pub Error
@@ -22,6 +24,10 @@ custom_error! {pub Error
     NetworkBootstrapError { source: network::BootstrapError } = "Error while loading the blockchain from the network",
     NodeSecrets { source: secure::NodeSecretFromFileError} = "Error while loading the node's secrets.",
     Block0InFuture = "Block 0 is set to start in the future",
+    ChainFileBlock0Mismatch = "The chain file does not start with the node's genesis block",
+    ChainImportIntoExistingStorage = "Cannot import a chain file in a storage which already contains the blockchain",
+    StorageVerificationFailed = "The storage contains invalid blocks, run with `--repair-storage` to set the tip to the last valid block",
+    StorageRepairRefused = "Cannot repair the storage: none of its blocks could be read, the tip is left unchanged",
 }
 
 impl Error {
@@ -39,6 +45,10 @@ impl Error {
             Error::NodeSecrets { .. } => 8,
             Error::FetchBlock0 { .. } => 9,
             Error::NetworkBootstrapError { .. } => 10,
+            Error::ChainFileBlock0Mismatch => 11,
+            Error::ChainImportIntoExistingStorage => 12,
+            Error::StorageVerificationFailed => 13,
+            Error::StorageRepairRefused => 14,
         }
     }
 }
@@ -1,12 +1,22 @@
+mod chain_file;
 mod error;
+mod prune_storage;
+mod verify_storage;
 
+pub use self::chain_file::{export_chain, import_chain};
 pub use self::error::{Error, ErrorKind};
+pub use self::prune_storage::prepare_storage_pruning;
+use self::prune_storage::{flat_file_path, sqlite_path};
+pub use self::verify_storage::verify_storage;
 use crate::{
     blockcfg::{Block, Leadership},
-    blockchain::{Blockchain, Branch, ErrorKind as BlockchainError},
+    blockchain::{
+        Blockchain, Branch, CacheLimits, ErrorKind as BlockchainError, FlatFileBlockStore,
+        StoragePruning,
+    },
     leadership::NewEpochToSchedule,
     network,
-    settings::start::Settings,
+    settings::{start::Settings, Storage},
 };
 use chain_storage::{memory::MemoryBlockStore, store::BlockStore};
 use chain_storage_sqlite::SQLiteBlockStore;
@@ -20,20 +30,41 @@ pub type NodeStorage = Box<dyn BlockStore<Block = Block> + Send + Sync>;
 ///
 pub fn prepare_storage(setting: &Settings, logger: &Logger) -> Result<NodeStorage, Error> {
     match &setting.storage {
-        None => {
+        Storage::Memory => {
             info!(logger, "storing blockchain in memory");
             Ok(Box::new(MemoryBlockStore::new()))
         }
-        Some(dir) => {
+        Storage::SQLite { path: dir, .. } => {
             std::fs::create_dir_all(dir).map_err(|err| Error::IO {
                 source: err,
                 reason: ErrorKind::SQLite,
             })?;
-            let mut sqlite = dir.clone();
-            sqlite.push("blocks.sqlite");
+            let sqlite = sqlite_path(dir);
             info!(logger, "storing blockchain in '{:?}'", sqlite);
             Ok(Box::new(SQLiteBlockStore::new(sqlite)))
         }
+        Storage::FlatFile {
+            path: dir,
+            prune_depth,
+        } => {
+            let flat_file_error = |err| Error::IO {
+                source: err,
+                reason: ErrorKind::FlatFile,
+            };
+            std::fs::create_dir_all(dir).map_err(flat_file_error)?;
+            let flat_file = flat_file_path(dir);
+            info!(logger, "storing blockchain in '{:?}'", flat_file);
+            let mut store = FlatFileBlockStore::new(flat_file).map_err(flat_file_error)?;
+            if let Some(prune_depth) = prune_depth {
+                let reclaimed = store.prune_forks(*prune_depth).map_err(flat_file_error)?;
+                info!(
+                    logger,
+                    "pruned the forks deeper than {} blocks", prune_depth;
+                    "reclaimed_bytes" => reclaimed
+                );
+            }
+            Ok(Box::new(store))
+        }
     }
 }
 
@@ -86,12 +117,17 @@ pub fn prepare_block_0(
 pub fn load_blockchain(
     block0: Block,
     storage: NodeStorage,
+    storage_pruning: Option<StoragePruning>,
     epoch_event: mpsc::Sender<NewEpochToSchedule>,
     block_cache_ttl: Duration,
+    cache_limits: CacheLimits,
 ) -> Result<(Blockchain, Branch), Error> {
     use tokio::prelude::*;
 
-    let mut blockchain = Blockchain::new(storage, block_cache_ttl);
+    let mut blockchain = Blockchain::new(storage, block_cache_ttl, cache_limits);
+    if let Some(storage_pruning) = storage_pruning {
+        blockchain.set_storage_pruning(storage_pruning);
+    }
 
     let main_branch: Branch = match blockchain.load_from_block0(block0.clone()).wait() {
         Err(error) => match error.kind() {
@@ -106,17 +142,13 @@ pub fn load_blockchain(
         .map_err(|_: std::convert::Infallible| unreachable!())
         .and_then(move |reference| {
             let time_frame = reference.time_frame();
-            let current_known_leadership = reference.epoch_leadership_schedule();
             let current_known_state = reference.ledger();
 
             let slot = time_frame
                 .slot_at(&std::time::SystemTime::now())
                 .ok_or(Error::Block0InFuture)
                 .unwrap();
-            let date = current_known_leadership
-                .era()
-                .from_slot_to_era(slot)
-                .unwrap();
+            let date = reference.era().from_slot_to_era(slot).unwrap();
             let new_schedule = Leadership::new(date.epoch.0, &current_known_state);
 
             epoch_event
@@ -124,6 +156,7 @@ pub fn load_blockchain(
                     new_schedule: Arc::new(new_schedule),
                     new_parameters: reference.epoch_ledger_parameters().clone(),
                     time_frame: reference.time_frame().as_ref().clone(),
+                    era: reference.era().as_ref().clone(),
                 })
                 .into_future()
         })
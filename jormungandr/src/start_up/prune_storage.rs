@@ -0,0 +1,265 @@
+use super::NodeStorage;
+use crate::{
+    blockchain::{FlatFileBlockStore, RebuildStorage, StoragePruning},
+    settings::{start::Settings, Storage},
+};
+use chain_storage::error::Error as StorageError;
+use chain_storage_sqlite::SQLiteBlockStore;
+use std::{
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// the pruning of the storage from the given settings, `None` if the
+/// storage is not to be pruned
+pub fn prepare_storage_pruning(setting: &Settings) -> Option<StoragePruning> {
+    match &setting.storage {
+        Storage::Memory => None,
+        Storage::SQLite { path, prune_depth } => prune_depth.map(|prune_depth| {
+            let rebuild = SQLiteRebuild {
+                path: sqlite_path(path),
+            };
+            StoragePruning::new(prune_depth, Box::new(rebuild))
+        }),
+        Storage::FlatFile { path, prune_depth } => prune_depth.map(|prune_depth| {
+            let rebuild = FlatFileRebuild {
+                path: flat_file_path(path),
+            };
+            StoragePruning::new(prune_depth, Box::new(rebuild))
+        }),
+    }
+}
+
+pub(super) fn sqlite_path(dir: &Path) -> PathBuf {
+    dir.join("blocks.sqlite")
+}
+
+pub(super) fn flat_file_path(dir: &Path) -> PathBuf {
+    dir.join("blocks.dat")
+}
+
+/// the SQLite database is written again in `blocks.sqlite.prune`, then
+/// renamed along with its write-ahead log
+struct SQLiteRebuild {
+    path: PathBuf,
+}
+
+impl SQLiteRebuild {
+    fn pruned_path(&self) -> PathBuf {
+        with_suffix(&self.path, ".prune")
+    }
+}
+
+impl RebuildStorage for SQLiteRebuild {
+    fn create(&self) -> Result<NodeStorage, StorageError> {
+        let pruned_path = self.pruned_path();
+        // the leftovers of a pruning which did not complete
+        for path in sqlite_files(&pruned_path).iter() {
+            remove_if_exists(path).map_err(backend_error)?;
+        }
+        Ok(Box::new(SQLiteBlockStore::new(pruned_path)))
+    }
+
+    fn replace(&self) -> Result<(NodeStorage, u64), StorageError> {
+        let previous_size = files_size(&sqlite_files(&self.path)).map_err(backend_error)?;
+
+        // the write-ahead log of the previous database must not be
+        // applied to the new one: everything it holds has been copied
+        let files = sqlite_files(&self.path);
+        let pruned_files = sqlite_files(&self.pruned_path());
+        remove_if_exists(&files[1]).map_err(backend_error)?;
+        remove_if_exists(&files[2]).map_err(backend_error)?;
+        fs::rename(&pruned_files[0], &files[0]).map_err(backend_error)?;
+        if pruned_files[1].exists() {
+            fs::rename(&pruned_files[1], &files[1]).map_err(backend_error)?;
+        }
+        remove_if_exists(&pruned_files[2]).map_err(backend_error)?;
+
+        let size = files_size(&sqlite_files(&self.path)).map_err(backend_error)?;
+        Ok((self.reopen()?, previous_size.saturating_sub(size)))
+    }
+
+    fn reopen(&self) -> Result<NodeStorage, StorageError> {
+        Ok(Box::new(SQLiteBlockStore::new(self.path.clone())))
+    }
+}
+
+/// the flat file is written again in `blocks.dat.prune`, then renamed
+struct FlatFileRebuild {
+    path: PathBuf,
+}
+
+impl FlatFileRebuild {
+    fn pruned_path(&self) -> PathBuf {
+        with_suffix(&self.path, ".prune")
+    }
+}
+
+impl RebuildStorage for FlatFileRebuild {
+    fn create(&self) -> Result<NodeStorage, StorageError> {
+        let pruned_path = self.pruned_path();
+        remove_if_exists(&pruned_path).map_err(backend_error)?;
+        let store = FlatFileBlockStore::new(pruned_path).map_err(backend_error)?;
+        Ok(Box::new(store))
+    }
+
+    fn replace(&self) -> Result<(NodeStorage, u64), StorageError> {
+        let previous_size = files_size(&[self.path.clone()]).map_err(backend_error)?;
+        fs::rename(self.pruned_path(), &self.path).map_err(backend_error)?;
+        let size = files_size(&[self.path.clone()]).map_err(backend_error)?;
+        Ok((self.reopen()?, previous_size.saturating_sub(size)))
+    }
+
+    fn reopen(&self) -> Result<NodeStorage, StorageError> {
+        let store = FlatFileBlockStore::new(&self.path).map_err(backend_error)?;
+        Ok(Box::new(store))
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+/// the database, its write-ahead log and its shared memory index
+fn sqlite_files(path: &Path) -> [PathBuf; 3] {
+    [
+        path.to_path_buf(),
+        with_suffix(path, "-wal"),
+        with_suffix(path, "-shm"),
+    ]
+}
+
+fn files_size(paths: &[PathBuf]) -> io::Result<u64> {
+    let mut size = 0;
+    for path in paths {
+        match fs::metadata(path) {
+            Ok(metadata) => size += metadata.len(),
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => (),
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(size)
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+        res => res,
+    }
+}
+
+fn backend_error(error: io::Error) -> StorageError {
+    StorageError::BackendError(Box::new(error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockcfg::{Block, BlockBuilder, BlockDate};
+    use crate::blockchain::Storage;
+    use chain_core::property::{Block as _, ChainLength as _};
+    use chain_storage::store::BlockStore;
+    use tokio::prelude::*;
+
+    fn genesis() -> Block {
+        BlockBuilder::new().make_genesis_block()
+    }
+
+    fn child(parent: &Block, slot_id: u32) -> Block {
+        let mut builder = BlockBuilder::new();
+        builder
+            .date(BlockDate { epoch: 0, slot_id })
+            .parent(parent.id())
+            .chain_length(parent.header.chain_length().next());
+        builder.make_genesis_block()
+    }
+
+    fn chain(parent: &Block, first_slot_id: u32, len: u32) -> Vec<Block> {
+        let mut blocks: Vec<Block> = Vec::new();
+        for slot_id in first_slot_id..first_slot_id + len {
+            let block = child(blocks.last().unwrap_or(parent), slot_id);
+            blocks.push(block);
+        }
+        blocks
+    }
+
+    fn flat_file_storage(path: &Path, prune_depth: u64) -> Storage {
+        let store = FlatFileBlockStore::new(path).unwrap();
+        let mut storage = Storage::new(Box::new(store));
+        let rebuild = FlatFileRebuild {
+            path: path.to_path_buf(),
+        };
+        storage.set_pruning(StoragePruning::new(prune_depth, Box::new(rebuild)));
+        storage
+    }
+
+    #[test]
+    fn prune_forks_keeps_the_tagged_chain_and_the_recent_forks() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = flat_file_path(dir.path());
+        let block0 = genesis();
+        let main = chain(&block0, 1, 6);
+        // branches off at depth 1, below the horizon
+        let old_fork = chain(&main[0], 20, 2);
+        // branches off at depth 5, above the horizon
+        let recent_fork = chain(&main[4], 30, 2);
+
+        // the old fork was stored before the node started
+        {
+            let mut store = FlatFileBlockStore::new(&path).unwrap();
+            store.put_block(&block0).unwrap();
+            for block in main[..1].iter().chain(old_fork.iter()) {
+                store.put_block(block).unwrap();
+            }
+        }
+        let mut storage = flat_file_storage(&path, 3);
+        for block in main[1..].iter().chain(recent_fork.iter()) {
+            storage.put_block(block.clone()).wait().unwrap();
+        }
+        storage
+            .put_tag("HEAD".to_owned(), main[5].id())
+            .wait()
+            .unwrap();
+
+        let reclaimed = storage
+            .prune_forks(vec!["HEAD".to_owned(), "LAST_BLOCK".to_owned()])
+            .wait()
+            .unwrap();
+        assert!(reclaimed.unwrap() > 0);
+
+        let exists = |block: &Block| storage.block_exists(block.id()).wait().unwrap();
+        assert!(exists(&block0));
+        assert!(main.iter().all(|block| exists(block)));
+        assert!(recent_fork.iter().all(|block| exists(block)));
+        assert!(!old_fork.iter().any(|block| exists(block)));
+        assert_eq!(
+            storage.get_tag("HEAD".to_owned()).wait().unwrap(),
+            Some(main[5].id())
+        );
+
+        // the pruned file is the one opened again
+        let store = FlatFileBlockStore::new(&path).unwrap();
+        assert!(!store.block_exists(&old_fork[0].id()).unwrap());
+        assert_eq!(store.get_tag("HEAD").unwrap(), Some(main[5].id()));
+    }
+
+    #[test]
+    fn prune_forks_without_pruning_set_does_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = flat_file_path(dir.path());
+        let block0 = genesis();
+        let store = FlatFileBlockStore::new(&path).unwrap();
+        let mut storage = Storage::new(Box::new(store));
+        storage.put_block(block0.clone()).wait().unwrap();
+        storage
+            .put_tag("HEAD".to_owned(), block0.id())
+            .wait()
+            .unwrap();
+
+        let reclaimed = storage.prune_forks(vec!["HEAD".to_owned()]).wait().unwrap();
+        assert_eq!(reclaimed, None);
+    }
+}
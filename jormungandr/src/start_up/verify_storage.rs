@@ -0,0 +1,143 @@
+//! offline verification of the blocks of the storage
+//!
+//! The blocks of the main branch are read back from the block0 to the
+//! `HEAD` and validated again, header and ledger transition, as if they
+//! were received from the network.
+//!
+//! The storage cannot remove blocks, so repairing the storage only moves
+//! the tags: the `HEAD` tag and the last block tag are rewritten to the
+//! last valid block. The blocks after it, valid or not, are left in the
+//! storage but are no longer part of the main branch, and are not applied
+//! again when the node starts.
+//!
+//! If the blocks of the storage cannot be read at all, the repair is
+//! refused rather than moving the tags back to the block0.
+
+use super::{Error, NodeStorage};
+use crate::{
+    blockcfg::Block,
+    blockchain::{Blockchain, CacheLimits},
+};
+use slog::Logger;
+use std::time::Duration;
+use tokio::prelude::*;
+
+/// check the blocks of the storage from the block0 to the `HEAD`, or to
+/// the last stored block if the `HEAD` tag is missing, and report the
+/// first invalid block.
+///
+/// If `repair` is set, the `HEAD` and the last block tags are rewritten
+/// to the last valid block. No block is removed from the storage.
+pub fn verify_storage(
+    storage: NodeStorage,
+    block0: Block,
+    repair: bool,
+    block_cache_ttl: Duration,
+    logger: &Logger,
+) -> Result<(), Error> {
+    let mut blockchain = Blockchain::new(storage, block_cache_ttl, CacheLimits::default());
+    let block0_id = block0.header.hash();
+
+    let branch = blockchain.apply_block0(block0).wait()?;
+    let mut last_valid = branch.get_ref().wait().unwrap();
+    let mut count = 1u64;
+
+    let failure = match blockchain.get_head_or_last_block().wait()? {
+        None => Some(Failure::Unreadable(
+            "neither the HEAD tag nor the last block tag are in the storage".to_owned(),
+        )),
+        Some(head_hash) => {
+            match blockchain
+                .storage()
+                .stream_from_to(block0_id, head_hash)
+                .wait()
+            {
+                Err(error) => Some(Failure::Unreadable(format!(
+                    "cannot read the blocks up to the HEAD: {}",
+                    error
+                ))),
+                Ok(None) => Some(Failure::Unreadable(format!(
+                    "the block {} is not a descendant of the block0",
+                    head_hash
+                ))),
+                Ok(Some(block_stream)) => {
+                    let mut failure = None;
+                    for block in block_stream.wait() {
+                        let block = match block {
+                            Ok(block) => block,
+                            Err(error) => {
+                                failure = Some(Failure::Invalid(format!(
+                                    "cannot read the block following {}: {}",
+                                    last_valid.hash(),
+                                    error
+                                )));
+                                break;
+                            }
+                        };
+                        let block_id = block.header.hash();
+                        match blockchain.apply_stored_block(block).wait() {
+                            Ok(new_ref) => {
+                                last_valid = new_ref;
+                                count += 1;
+                            }
+                            Err(error) => {
+                                failure = Some(Failure::Invalid(format!(
+                                    "block {} is invalid: {}",
+                                    block_id, error
+                                )));
+                                break;
+                            }
+                        }
+                    }
+                    failure
+                }
+            }
+        }
+    };
+
+    let failure = match failure {
+        None => {
+            info!(logger, "storage verified";
+                  "tip" => last_valid.hash().to_string(), "blocks" => count);
+            return Ok(());
+        }
+        Some(failure) => failure,
+    };
+
+    let unreadable = match failure {
+        Failure::Unreadable(_) => true,
+        Failure::Invalid(_) => false,
+    };
+    error!(logger, "storage verification failed: {}", failure.reason();
+           "last_valid_block" => last_valid.hash().to_string(), "valid_blocks" => count);
+
+    if !repair {
+        return Err(Error::StorageVerificationFailed);
+    }
+    // only the block0 is known valid: moving the tags to it would drop
+    // the whole blockchain because of a storage which could not be read
+    if unreadable {
+        return Err(Error::StorageRepairRefused);
+    }
+
+    blockchain.reset_head(&last_valid).wait()?;
+    warn!(logger, "HEAD tag rewritten to the last valid block";
+          "tip" => last_valid.hash().to_string(), "blocks" => count);
+    Ok(())
+}
+
+/// why the verification stopped
+enum Failure {
+    /// the blocks to verify could not be found in the storage
+    Unreadable(String),
+    /// a block could not be read or is invalid
+    Invalid(String),
+}
+
+impl Failure {
+    fn reason(&self) -> &str {
+        match self {
+            Failure::Unreadable(reason) | Failure::Invalid(reason) => reason.as_str(),
+        }
+    }
+}
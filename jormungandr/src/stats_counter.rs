@@ -14,8 +14,25 @@ pub struct StatsCounter {
 struct StatsCounterImpl {
     tx_recv_cnt: AtomicUsize,
     block_recv_cnt: AtomicUsize,
+    leadership_failure_cnt: AtomicUsize,
     start_time: Instant,
     slot_start_time: AtomicU64,
+    ref_cache: CacheStats,
+    multiverse: CacheStats,
+}
+
+/// counters of an in-memory cache, updated by the cache itself
+#[derive(Clone, Debug, Default)]
+pub struct CacheStats {
+    stats: Arc<CacheStatsImpl>,
+}
+
+#[derive(Debug, Default)]
+struct CacheStatsImpl {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    size: AtomicUsize,
 }
 
 impl Default for StatsCounterImpl {
@@ -23,13 +40,27 @@ impl Default for StatsCounterImpl {
         Self {
             tx_recv_cnt: AtomicUsize::default(),
             block_recv_cnt: AtomicUsize::default(),
+            leadership_failure_cnt: AtomicUsize::default(),
             start_time: Instant::now(),
             slot_start_time: AtomicU64::new(SLOT_START_TIME_UNDEFINED),
+            ref_cache: CacheStats::default(),
+            multiverse: CacheStats::default(),
         }
     }
 }
 
 impl StatsCounter {
+    /// create the counters, sharing the ones of the blockchain's caches
+    pub fn new(ref_cache: CacheStats, multiverse: CacheStats) -> Self {
+        StatsCounter {
+            stats: Arc::new(StatsCounterImpl {
+                ref_cache,
+                multiverse,
+                ..StatsCounterImpl::default()
+            }),
+        }
+    }
+
     pub fn add_tx_recv_cnt(&self, count: usize) {
         self.stats.tx_recv_cnt.fetch_add(count, Ordering::Relaxed);
     }
@@ -48,6 +79,17 @@ impl StatsCounter {
         self.stats.block_recv_cnt.load(Ordering::Relaxed) as u64
     }
 
+    /// count the leadership events that failed to produce a block
+    pub fn add_leadership_failure_cnt(&self, count: usize) {
+        self.stats
+            .leadership_failure_cnt
+            .fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn leadership_failure_cnt(&self) -> u64 {
+        self.stats.leadership_failure_cnt.load(Ordering::Relaxed) as u64
+    }
+
     pub fn uptime_sec(&self) -> u64 {
         self.stats.start_time.elapsed().as_secs()
     }
@@ -65,4 +107,50 @@ impl StatsCounter {
         }
         .map(SecondsSinceUnixEpoch::from_secs)
     }
+
+    /// the counters of the cache of the blocks' `Ref`
+    pub fn ref_cache(&self) -> &CacheStats {
+        &self.stats.ref_cache
+    }
+
+    /// the counters of the multiverse of the ledgers
+    pub fn multiverse(&self) -> &CacheStats {
+        &self.stats.multiverse
+    }
+}
+
+impl CacheStats {
+    pub fn add_hit(&self) {
+        self.stats.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.stats.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn add_miss(&self) {
+        self.stats.misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.stats.misses.load(Ordering::Relaxed)
+    }
+
+    pub fn add_evictions(&self, count: usize) {
+        self.stats
+            .evictions
+            .fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    pub fn evictions(&self) -> u64 {
+        self.stats.evictions.load(Ordering::Relaxed)
+    }
+
+    pub fn set_size(&self, size: usize) {
+        self.stats.size.store(size, Ordering::Relaxed)
+    }
+
+    pub fn size(&self) -> u64 {
+        self.stats.size.load(Ordering::Relaxed) as u64
+    }
 }